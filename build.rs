@@ -1,12 +1,22 @@
 //! Build script for the RMK Q6 HE firmware.
 //!
-//! Registers the layout feature flags for change detection and
-//! sets the linker arguments required by the Cortex-M4 target.
+//! Registers the layout feature flags for change detection, puts
+//! `memory.x` on the linker search path, and sets the linker arguments
+//! required by the Cortex-M4 target.
 
-fn main() {
+use std::{env, fs, io, path::PathBuf};
+
+fn main() -> io::Result<()> {
     println!("cargo:rerun-if-env-changed=CARGO_FEATURE_ANSI_LAYOUT");
     println!("cargo:rerun-if-env-changed=CARGO_FEATURE_ISO_LAYOUT");
     println!("cargo:rerun-if-env-changed=CARGO_FEATURE_JIS_LAYOUT");
+    // `memory.x` keeps the EEPROM fallback store's flash sectors out of the
+    // firmware image.
+    let out = PathBuf::from(env::var_os("OUT_DIR").ok_or_else(|| io::Error::other("OUT_DIR is not set"))?);
+    fs::copy("memory.x", out.join("memory.x"))?;
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory.x");
     println!("cargo:rustc-link-arg=--nmagic");
     println!("cargo:rustc-link-arg=-Tlink.x");
+    Ok(())
 }
@@ -0,0 +1,42 @@
+use crate::matrix::calib_store::Crc32;
+
+/// Value the STM32 CRC unit resets to.
+const INIT: u32 = 0xFFFF_FFFF;
+/// CRC-32 polynomial of the STM32 CRC unit, without the implicit top bit.
+const POLY: u32 = 0x04C1_1DB7;
+
+/// The STM32 CRC unit in software: CRC-32 with [`POLY`], fed a word at a
+/// time most significant bit first, with neither reflection nor a final
+/// XOR.
+pub struct SoftCrc {
+    /// Running remainder.
+    value: u32,
+}
+
+impl SoftCrc {
+    /// Create a unit in its reset state.
+    #[must_use]
+    pub const fn new() -> Self { Self { value: INIT } }
+}
+
+impl Default for SoftCrc {
+    fn default() -> Self { Self::new() }
+}
+
+impl Crc32 for SoftCrc {
+    fn feed_word(&mut self, word: u32) {
+        let mut value = self.value ^ word;
+        for _ in 0..u32::BITS {
+            let carry = value & 0x8000_0000 != 0;
+            value <<= 1;
+            if carry {
+                value ^= POLY;
+            }
+        }
+        self.value = value;
+    }
+
+    fn read(&self) -> u32 { self.value }
+
+    fn reset(&mut self) { self.value = INIT; }
+}
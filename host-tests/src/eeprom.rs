@@ -0,0 +1,9 @@
+#[path = "../../src/eeprom/flash_store.rs"]
+pub mod flash_store;
+#[path = "../../src/eeprom/ft24c64.rs"]
+pub mod ft24c64;
+#[path = "../../src/eeprom/store.rs"]
+pub mod store;
+
+pub use ft24c64::{EEPROM_SIZE, Ft24c64, Ft24c64Error, PAGE_SIZE};
+pub use store::Store;
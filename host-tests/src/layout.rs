@@ -0,0 +1,6 @@
+/// Number of matrix columns.
+pub const COL: usize = 21;
+/// Layout identifier stored in the calibration header; `1` is ANSI.
+pub const LAYOUT_ID: u8 = 1;
+/// Number of matrix rows.
+pub const ROW: usize = 6;
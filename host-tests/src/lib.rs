@@ -0,0 +1,30 @@
+//! Host-side tests for the firmware's storage code.
+//!
+//! The firmware crate only builds for the MCU, so this crate compiles the
+//! target-independent sources under test straight from `../src` with
+//! `#[path]`, next to stand-ins for the board modules they reach into, and
+//! the tests in `tests/` drive them against [`model`], an in-memory FT24C64
+//! and internal flash, and [`crc::SoftCrc`], a software copy of the STM32
+//! CRC unit.
+//!
+//! The repository's `.cargo/config.toml` cross-compiles for the MCU, so run
+//! the tests from outside the checkout:
+//! `cargo test --manifest-path <checkout>/host-tests/Cargo.toml`.
+
+#![feature(const_cmp, const_convert, const_index, const_option_ops, const_result_trait_fn, const_trait_impl)]
+// The storage trait is crate-private in the firmware; here it is public
+// only so the tests can reach it.
+#![allow(async_fn_in_trait)]
+
+/// Software CRC-32 matching the STM32 CRC unit.
+pub mod crc;
+/// The EEPROM driver, its internal-flash fallback, and the storage trait,
+/// compiled from the firmware sources.
+pub mod eeprom;
+/// Stand-in for the firmware's layout module: the ANSI board's matrix.
+pub mod layout;
+/// The calibration store, compiled from the firmware sources.
+pub mod matrix;
+/// In-memory models of the FT24C64 with its write-protect pin, and of the
+/// internal flash.
+pub mod model;
@@ -0,0 +1,8 @@
+/// Stand-in for the firmware's analog matrix: the types the calibration
+/// store serializes.
+pub mod analog_matrix;
+#[path = "../../src/matrix/calib_store.rs"]
+pub mod calib_store;
+/// Stand-in for the firmware's tuning store, which fixes where calibration
+/// slot B starts.
+pub mod tuning_store;
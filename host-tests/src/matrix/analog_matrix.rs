@@ -0,0 +1,6 @@
+// Only the switch profiles are needed, not the lookup itself.
+#[allow(dead_code)]
+#[path = "../../../src/matrix/analog_matrix/lut.rs"]
+mod lut;
+/// The per-key calibration fields the calibration store reads and writes.
+pub mod types;
@@ -0,0 +1,17 @@
+pub use super::lut::SwitchProfile;
+
+/// The calibration fields of the firmware's `KeyEntry`; the rest of the
+/// per-key state never reaches the calibration store.
+#[derive(Clone, Copy, Default)]
+pub struct KeyEntry {
+    /// Whether this matrix position has a valid hall-effect sensor.
+    pub calib_used: bool,
+    /// Raw ADC at zero travel.
+    pub calib_zero: u16,
+    /// Persistent full-travel ADC reading.
+    pub entry_full: u16,
+    /// Standard deviation of the resting reading in 1/16 ADC counts.
+    pub noise:      u16,
+    /// Switch profile persisted with the calibration.
+    pub profile:    SwitchProfile,
+}
@@ -0,0 +1,13 @@
+use crate::{
+    eeprom::PAGE_SIZE,
+    matrix::calib_store::{CALIB_BUF_LEN, SLOT_A_ADDR},
+};
+
+/// EEPROM address of the tuning block, right after calibration slot A as in
+/// the firmware.
+pub const TUNING_BASE_ADDR: u16 =
+    SLOT_A_ADDR.saturating_add(u16::try_from(CALIB_BUF_LEN.next_multiple_of(PAGE_SIZE)).unwrap_or(u16::MAX));
+/// Length of the tuning block, rounded up to a whole page. Its contents need
+/// the whole matrix, so this is a stand-in, sized to put slot B at the same
+/// address as in the firmware.
+pub const TUNING_BUF_LEN: usize = 960;
@@ -0,0 +1,383 @@
+use crate::eeprom::{EEPROM_SIZE, PAGE_SIZE};
+use core::cell::RefCell;
+use embedded_hal::{
+    digital::{self, OutputPin},
+    i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation},
+};
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+use embedded_storage::nor_flash::{
+    self,
+    NorFlash,
+    NorFlashErrorKind,
+    ReadNorFlash,
+    check_erase,
+    check_read,
+    check_write,
+};
+use std::rc::Rc;
+
+/// Acknowledgement polls a write cycle NACKs unless told otherwise.
+const DEFAULT_WRITE_CYCLE_POLLS: u8 = 3;
+/// 7-bit I²C address the board straps the chip to.
+pub const DEVICE_ADDR: u8 = 0x51;
+/// Byte length of the [`FlashModel`]: STM32F401 sectors 0 to 2, up to the
+/// end of the internal-flash store.
+pub const FLASH_LEN: usize = 0xC000;
+/// Byte length of the 16 KiB sectors the [`FlashModel`] erases.
+pub const FLASH_SECTOR_LEN: usize = 0x4000;
+/// Bytes a [`FlashModel`] programs at a time; the store lays its records
+/// out in 8-byte units.
+const FLASH_WRITE_LEN: usize = 8;
+/// Bits of the word address the chip decodes; the rest are ignored.
+const WORD_ADDR_MASK: u16 = 0x1FFF;
+
+/// State of the simulated chip, shared by its bus, pin, and clock handles.
+struct Chip {
+    /// Addressings still NACKed by the write cycle in progress.
+    busy:              u8,
+    /// Microseconds the driver has waited on the [`Delay`].
+    elapsed_us:        u64,
+    /// Byte-carrying transfers still to be NACKed by injected faults.
+    failing:           u8,
+    /// The memory array, erased to `0xFF`.
+    mem:               Vec<u8>,
+    /// Page writes the chip has carried out.
+    page_writes:       usize,
+    /// Word address of the next current-address read.
+    pointer:           u16,
+    /// Whether the chip answers on the bus at all.
+    present:           bool,
+    /// Addressings every write cycle NACKs after its data is sent.
+    write_cycle_polls: u8,
+    /// Level of the write-protect pin; high protects the whole array.
+    wp_high:           bool,
+    /// Level the pin is stuck at, failing any attempt to drive the other.
+    wp_stuck:          Option<bool>,
+}
+
+impl Chip {
+    /// Carry out the write addressed by `written`: the two word-address
+    /// bytes, then any data. Data wraps within the addressed page like on
+    /// the real chip, and is refused with a NACK while WP is high.
+    fn latch(&mut self, written: &[u8]) -> Result<(), ErrorKind> {
+        let [hi, lo, data @ ..] = written else {
+            return Ok(());
+        };
+        self.pointer = u16::from_be_bytes([*hi, *lo]) & WORD_ADDR_MASK;
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.wp_high {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+        }
+        let start = usize::from(self.pointer);
+        let page = start - start % PAGE_SIZE;
+        for (index, &byte) in data.iter().enumerate() {
+            self.mem[page + (start % PAGE_SIZE + index) % PAGE_SIZE] = byte;
+        }
+        self.page_writes += 1;
+        self.busy = self.write_cycle_polls;
+        Ok(())
+    }
+
+    /// Run one I²C transaction addressed to `address`.
+    fn transfer(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+        if !self.present || address != DEVICE_ADDR {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        if self.busy > 0 {
+            self.busy -= 1;
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        let carries_bytes = operations.iter().any(|operation| match operation {
+            Operation::Read(buf) => !buf.is_empty(),
+            Operation::Write(bytes) => !bytes.is_empty(),
+        });
+        if carries_bytes && self.failing > 0 {
+            self.failing -= 1;
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+        }
+        // Adjacent writes go out as one stream; a read after a write is a
+        // repeated start, which sets the address and reads from it.
+        let mut written = Vec::new();
+        for operation in operations.iter_mut() {
+            match operation {
+                Operation::Write(bytes) => written.extend_from_slice(bytes),
+                Operation::Read(buf) => {
+                    self.latch(&written)?;
+                    written.clear();
+                    for byte in buf.iter_mut() {
+                        *byte = self.mem[usize::from(self.pointer)];
+                        self.pointer = (self.pointer + 1) & WORD_ADDR_MASK;
+                    }
+                },
+            }
+        }
+        self.latch(&written)
+    }
+}
+
+/// In-memory FT24C64 on the board's I²C address, with its write-protect
+/// pin pulled high.
+///
+/// Hands out a [`Bus`], a [`WpPin`], and a [`Delay`] for the driver, and
+/// lets a test look at the memory and inject faults while the driver holds
+/// them.
+#[derive(Clone)]
+pub struct Model(Rc<RefCell<Chip>>);
+
+impl Model {
+    /// Create an erased chip whose write cycles NACK
+    /// [`DEFAULT_WRITE_CYCLE_POLLS`] acknowledgement polls.
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(Chip {
+            busy:              0,
+            elapsed_us:        0,
+            failing:           0,
+            mem:               vec![0xFF; EEPROM_SIZE],
+            page_writes:       0,
+            pointer:           0,
+            present:           true,
+            write_cycle_polls: DEFAULT_WRITE_CYCLE_POLLS,
+            wp_high:           true,
+            wp_stuck:          None,
+        })))
+    }
+
+    /// Handle to the chip's I²C bus.
+    #[must_use]
+    pub fn bus(&self) -> Bus { Bus(Rc::clone(&self.0)) }
+
+    /// Handle to the clock the driver waits on.
+    #[must_use]
+    pub fn delay(&self) -> Delay { Delay(Rc::clone(&self.0)) }
+
+    /// Microseconds the driver has waited so far.
+    #[must_use]
+    pub fn elapsed_us(&self) -> u64 { self.0.borrow().elapsed_us }
+
+    /// NACK the next `count` transfers that carry bytes; acknowledgement
+    /// polls are not affected.
+    pub fn fail_transfers(&self, count: u8) { self.0.borrow_mut().failing = count; }
+
+    /// Store `bytes` at `addr` directly, bypassing the bus.
+    pub fn load(&self, addr: u16, bytes: &[u8]) {
+        let start = usize::from(addr);
+        self.0.borrow_mut().mem[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Page writes the chip has carried out.
+    #[must_use]
+    pub fn page_writes(&self) -> usize { self.0.borrow().page_writes }
+
+    /// `len` bytes of memory at `addr`, read directly.
+    #[must_use]
+    pub fn peek(&self, addr: u16, len: usize) -> Vec<u8> {
+        let start = usize::from(addr);
+        self.0.borrow().mem[start..start + len].to_vec()
+    }
+
+    /// Connect or disconnect the chip from the bus.
+    pub fn set_present(&self, present: bool) { self.0.borrow_mut().present = present; }
+
+    /// Make every write cycle NACK `polls` acknowledgement polls.
+    pub fn set_write_cycle_polls(&self, polls: u8) { self.0.borrow_mut().write_cycle_polls = polls; }
+
+    /// Stick the write-protect pin at `level`, high for `true`, or free it
+    /// again with `None`.
+    pub fn stick_wp(&self, level: Option<bool>) {
+        let mut chip = self.0.borrow_mut();
+        chip.wp_stuck = level;
+        if let Some(high) = level {
+            chip.wp_high = high;
+        }
+    }
+
+    /// Whether the write-protect pin is high.
+    #[must_use]
+    pub fn wp_high(&self) -> bool { self.0.borrow().wp_high }
+
+    /// Handle to the chip's write-protect pin.
+    #[must_use]
+    pub fn wp(&self) -> WpPin { WpPin(Rc::clone(&self.0)) }
+}
+
+impl Default for Model {
+    fn default() -> Self { Self::new() }
+}
+
+/// The [`Model`]'s I²C bus.
+pub struct Bus(Rc<RefCell<Chip>>);
+
+impl ErrorType for Bus {
+    type Error = ErrorKind;
+}
+
+impl I2c for Bus {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().transfer(address, operations)
+    }
+}
+
+/// Clock of the [`Model`]; waiting returns at once and adds to
+/// [`Model::elapsed_us`].
+pub struct Delay(Rc<RefCell<Chip>>);
+
+impl DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) { self.0.borrow_mut().elapsed_us += u64::from(ns.div_ceil(1_000)); }
+}
+
+/// Failure of a [`WpPin`] stuck at the other level.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PinFault;
+
+impl digital::Error for PinFault {
+    fn kind(&self) -> digital::ErrorKind { digital::ErrorKind::Other }
+}
+
+/// The [`Model`]'s write-protect pin.
+pub struct WpPin(Rc<RefCell<Chip>>);
+
+impl WpPin {
+    /// Drive the pin to `high`, unless it is stuck at the other level.
+    fn drive(&self, high: bool) -> Result<(), PinFault> {
+        let mut chip = self.0.borrow_mut();
+        if chip.wp_stuck.is_some_and(|stuck| stuck != high) {
+            return Err(PinFault);
+        }
+        chip.wp_high = high;
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for WpPin {
+    type Error = PinFault;
+}
+
+impl OutputPin for WpPin {
+    fn set_high(&mut self) -> Result<(), Self::Error> { self.drive(true) }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> { self.drive(false) }
+}
+
+/// State of the simulated flash, shared by the [`FlashModel`] and its
+/// [`Flash`] handle.
+struct Bank {
+    /// Sector erases carried out.
+    erases:     usize,
+    /// The memory array, erased to `0xFF`.
+    mem:        Vec<u8>,
+    /// Bytes still programmed before power is lost, `None` if it never is.
+    power_left: Option<usize>,
+}
+
+impl Bank {
+    /// Fail with the power lost.
+    fn powered(&self) -> Result<(), NorFlashErrorKind> {
+        if self.power_left == Some(0) { Err(NorFlashErrorKind::Other) } else { Ok(()) }
+    }
+}
+
+/// In-memory STM32F401 flash up to the end of the internal-flash store.
+///
+/// Erases a 16 KiB sector at a time, programs only erased bytes, and can
+/// lose power partway through a write, so a test can cut the store short
+/// anywhere and open it again on what is left, as the next boot would.
+#[derive(Clone)]
+pub struct FlashModel(Rc<RefCell<Bank>>);
+
+impl FlashModel {
+    /// Create an erased flash that never loses power.
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(Bank { erases: 0, mem: vec![0xFF; FLASH_LEN], power_left: None })))
+    }
+
+    /// Lose power once `bytes` more bytes are programmed: the write under
+    /// way stops there, and every access after fails until
+    /// [`Self::restore_power`].
+    pub fn cut_power_after(&self, bytes: usize) { self.0.borrow_mut().power_left = Some(bytes); }
+
+    /// Sector erases carried out.
+    #[must_use]
+    pub fn erases(&self) -> usize { self.0.borrow().erases }
+
+    /// Handle to the flash for the store.
+    #[must_use]
+    pub fn flash(&self) -> Flash { Flash(Rc::clone(&self.0)) }
+
+    /// Store `bytes` at `offset` directly, bypassing the erase rules.
+    pub fn load(&self, offset: usize, bytes: &[u8]) {
+        self.0.borrow_mut().mem[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// `len` bytes of memory at `offset`, read directly.
+    #[must_use]
+    pub fn peek(&self, offset: usize, len: usize) -> Vec<u8> { self.0.borrow().mem[offset..offset + len].to_vec() }
+
+    /// Power the flash again, never to lose it.
+    pub fn restore_power(&self) { self.0.borrow_mut().power_left = None; }
+}
+
+impl Default for FlashModel {
+    fn default() -> Self { Self::new() }
+}
+
+/// The [`FlashModel`]'s NOR-flash interface.
+pub struct Flash(Rc<RefCell<Bank>>);
+
+impl nor_flash::ErrorType for Flash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for Flash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        check_read(self, offset, bytes.len())?;
+        let bank = self.0.borrow();
+        bank.powered()?;
+        let start = usize::try_from(offset).unwrap();
+        bytes.copy_from_slice(&bank.mem[start..start + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize { FLASH_LEN }
+}
+
+impl NorFlash for Flash {
+    const ERASE_SIZE: usize = FLASH_SECTOR_LEN;
+    const WRITE_SIZE: usize = FLASH_WRITE_LEN;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        check_erase(self, from, to)?;
+        let mut bank = self.0.borrow_mut();
+        bank.powered()?;
+        bank.mem[usize::try_from(from).unwrap()..usize::try_from(to).unwrap()].fill(0xFF);
+        bank.erases += usize::try_from(to - from).unwrap() / FLASH_SECTOR_LEN;
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        check_write(self, offset, bytes.len())?;
+        let mut bank = self.0.borrow_mut();
+        bank.powered()?;
+        let start = usize::try_from(offset).unwrap();
+        // Programming only clears bits, so a byte not erased cannot take
+        // the new value; the store never asks it to.
+        if bank.mem[start..start + bytes.len()].iter().any(|&byte| byte != 0xFF) {
+            return Err(NorFlashErrorKind::Other);
+        }
+        for (index, &byte) in bytes.iter().enumerate() {
+            if let Some(left) = bank.power_left.as_mut() {
+                if *left == 0 {
+                    return Err(NorFlashErrorKind::Other);
+                }
+                *left -= 1;
+            }
+            bank.mem[start + index] = byte;
+        }
+        Ok(())
+    }
+}
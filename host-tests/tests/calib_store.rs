@@ -0,0 +1,180 @@
+//! The calibration store's format, on its own and through the driver.
+
+use embassy_futures::block_on;
+use keychron_q6_he_host_tests::{
+    crc::SoftCrc,
+    eeprom::{Ft24c64, Store},
+    layout::{COL, ROW},
+    matrix::{
+        analog_matrix::types::{KeyEntry, SwitchProfile},
+        calib_store::{
+            CALIB_BUF_LEN,
+            CalibSlot,
+            Crc32,
+            StoredCalib,
+            crc32_of,
+            is_newer,
+            sequence,
+            serialize,
+            try_deserialize,
+        },
+    },
+    model::Model,
+};
+
+/// Calibration block magic, `Q6HE`.
+const MAGIC: u32 = 0x5136_4845;
+/// Byte length of a v1 block: magic, version, a 2-byte entry per key, and
+/// the CRC.
+const V1_BUF_LEN: usize = 5 + ROW * COL * 2 + 4;
+/// Offset of the layout byte: after the magic and the version.
+const LAYOUT_OFFSET: usize = 5;
+/// Offset of the first entry's flags byte: after the 11-byte header and the
+/// entry's three 2-byte readings.
+const FIRST_FLAGS_OFFSET: usize = 17;
+
+/// A matrix whose every key differs, mixing sensorless positions and both
+/// switch profiles.
+fn keys() -> [[KeyEntry; ROW]; COL] {
+    let mut keys = [[KeyEntry::default(); ROW]; COL];
+    for (index, key) in keys.as_flattened_mut().iter_mut().enumerate() {
+        let n = u16::try_from(index).unwrap();
+        key.calib_used = index % 5 != 0;
+        key.calib_zero = 3_000 - n;
+        key.entry_full = 1_000 + n;
+        key.noise = n % 40;
+        key.profile = if index % 7 == 0 { SwitchProfile::Linear } else { SwitchProfile::KeychronStock };
+    }
+    keys
+}
+
+/// Recompute the trailing CRC of the block in `buf` after editing it.
+fn resign(buf: &mut [u8]) {
+    let data_end = buf.len() - 4;
+    let checksum = crc32_of(&mut SoftCrc::new(), &buf[..data_end]);
+    buf[data_end..].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// `keys` serialized as the `writes`-th block.
+fn serialized(keys: &[[KeyEntry; ROW]; COL], writes: u32) -> [u8; CALIB_BUF_LEN] {
+    let mut buf = [0_u8; CALIB_BUF_LEN];
+    serialize(keys, writes, &mut buf, &mut SoftCrc::new());
+    buf
+}
+
+#[test]
+fn soft_crc_matches_the_stm32_unit() {
+    let mut crc = SoftCrc::new();
+    crc.feed_word(0x1234_5678);
+    assert_eq!(crc.read(), 0xDF8A_8A2B);
+    crc.reset();
+    assert_eq!(crc.read(), 0xFFFF_FFFF);
+}
+
+#[test]
+fn round_trip_through_the_eeprom() {
+    let model = Model::new();
+    let mut eeprom = Ft24c64::new(model.bus(), model.wp(), model.delay()).unwrap();
+    let keys = keys();
+    block_on(eeprom.write(CalibSlot::B.addr(), &serialized(&keys, 7))).unwrap();
+
+    let mut buf = [0_u8; CALIB_BUF_LEN];
+    block_on(eeprom.read(CalibSlot::B.addr(), &mut buf)).unwrap();
+    let mut crc = SoftCrc::new();
+    assert_eq!(sequence::<ROW, COL>(&buf, &mut crc), Some(7));
+    let mut out = [[KeyEntry::default(); ROW]; COL];
+    let load = try_deserialize(&buf, &mut out, &mut crc).unwrap();
+    assert!(!load.migrated);
+    assert_eq!(load.writes, 7);
+    for (got, want) in out.as_flattened().iter().zip(keys.as_flattened()) {
+        assert_eq!(got.entry_full, want.entry_full);
+        assert_eq!(got.calib_zero, want.calib_zero);
+        assert_eq!(got.noise, want.noise);
+        assert!(got.profile == want.profile);
+    }
+}
+
+#[test]
+fn corrupt_blocks_leave_the_keys_untouched() {
+    let mut buf = serialized(&keys(), 1);
+    buf[100] ^= 0x01;
+    let mut out = [[KeyEntry::default(); ROW]; COL];
+    assert!(try_deserialize(&buf, &mut out, &mut SoftCrc::new()).is_none());
+    assert!(out.as_flattened().iter().all(|key| key.entry_full == 0 && key.calib_zero == 0));
+}
+
+#[test]
+fn blocks_of_another_layout_are_rejected() {
+    let mut buf = serialized(&keys(), 1);
+    buf[LAYOUT_OFFSET] = 2;
+    resign(&mut buf);
+    let mut out = [[KeyEntry::default(); ROW]; COL];
+    assert!(try_deserialize(&buf, &mut out, &mut SoftCrc::new()).is_none());
+}
+
+#[test]
+fn unknown_switch_profiles_are_rejected() {
+    let mut buf = serialized(&keys(), 1);
+    buf[FIRST_FLAGS_OFFSET] |= 0xF0;
+    resign(&mut buf);
+    let mut out = [[KeyEntry::default(); ROW]; COL];
+    assert!(try_deserialize(&buf, &mut out, &mut SoftCrc::new()).is_none());
+}
+
+#[test]
+fn v1_blocks_are_migrated() {
+    // Magic, version 1, then one full-travel reading per key with the
+    // profile code in its top four bits.
+    let mut buf = vec![0_u8; V1_BUF_LEN];
+    buf[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    buf[4] = 1;
+    let (entries, _) = buf[5..V1_BUF_LEN - 4].as_chunks_mut::<2>();
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let code = u16::from(index % 3 == 0) << 12;
+        *entry = (code | u16::try_from(500 + index).unwrap()).to_le_bytes();
+    }
+    resign(&mut buf);
+
+    let mut crc = SoftCrc::new();
+    assert_eq!(sequence::<ROW, COL>(&buf, &mut crc), Some(0));
+    let mut out = [[KeyEntry::default(); ROW]; COL];
+    let load = try_deserialize(&buf, &mut out, &mut crc).unwrap();
+    assert!(load.migrated);
+    assert_eq!(load.writes, 0);
+    for (index, key) in out.as_flattened().iter().enumerate() {
+        assert_eq!(usize::from(key.entry_full), 500 + index);
+        assert_eq!(key.profile == SwitchProfile::Linear, index % 3 == 0);
+        assert_eq!(key.calib_zero, 0);
+    }
+}
+
+#[test]
+fn a_torn_write_keeps_the_previous_block() {
+    let model = Model::new();
+    let mut eeprom = Ft24c64::new(model.bus(), model.wp(), model.delay()).unwrap();
+    let keys = keys();
+    let first = StoredCalib::next(None);
+    block_on(eeprom.write(first.slot.addr(), &serialized(&keys, first.writes))).unwrap();
+
+    // The next block goes to the other slot; lose power halfway through it.
+    let second = StoredCalib::next(Some(first));
+    assert!(second.slot == first.slot.other());
+    let block = serialized(&keys, second.writes);
+    block_on(eeprom.write(second.slot.addr(), &block[..CALIB_BUF_LEN / 2])).unwrap();
+
+    let mut crc = SoftCrc::new();
+    let mut buf = [0_u8; CALIB_BUF_LEN];
+    block_on(eeprom.read(first.slot.addr(), &mut buf)).unwrap();
+    assert_eq!(sequence::<ROW, COL>(&buf, &mut crc), Some(first.writes));
+    block_on(eeprom.read(second.slot.addr(), &mut buf)).unwrap();
+    assert_eq!(sequence::<ROW, COL>(&buf, &mut crc), None);
+}
+
+#[test]
+fn write_counters_compare_across_the_wrap() {
+    assert!(is_newer(1, 0));
+    assert!(!is_newer(0, 1));
+    assert!(!is_newer(5, 5));
+    assert!(is_newer(0, u32::MAX));
+    assert!(!is_newer(u32::MAX, 0));
+}
@@ -0,0 +1,160 @@
+//! The internal-flash fallback store against the in-memory flash, cut short
+//! wherever a power loss could land.
+
+use core::cell::Cell;
+use embassy_futures::block_on;
+use keychron_q6_he_host_tests::{
+    eeprom::{PAGE_SIZE, Store, flash_store::FlashStore},
+    model::{FLASH_SECTOR_LEN, Flash, FlashModel},
+};
+
+/// Byte length of a record: an 8-byte header, then one page.
+const RECORD_LEN: usize = 8 + PAGE_SIZE;
+/// Records a sector holds after its 8-byte mark.
+const RECORDS: usize = (FLASH_SECTOR_LEN - 8) / RECORD_LEN;
+/// Flash offset of the first store sector.
+const SECTOR_A: usize = 0x4000;
+/// Flash offset of the second store sector.
+const SECTOR_B: usize = 0x8000;
+
+thread_local! {
+    /// Watchdog reloads the store has asked for on this test's thread.
+    static RELOADS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Watchdog hook counting into [`RELOADS`].
+fn reload_watchdog() { RELOADS.with(|reloads| reloads.set(reloads.get() + 1)); }
+
+/// Store opened on `model`, as at boot.
+fn open(model: &FlashModel) -> FlashStore<Flash> { FlashStore::new(model.flash(), reload_watchdog) }
+
+/// `len` bytes of the store at `addr`.
+fn read(store: &mut FlashStore<Flash>, addr: u16, len: usize) -> Vec<u8> {
+    let mut buf = vec![0_u8; len];
+    block_on(store.read(addr, &mut buf)).unwrap();
+    buf
+}
+
+/// Rewrite page 0 until the log is full, `used` records in; returns the
+/// byte page 0 holds last.
+fn fill_log(store: &mut FlashStore<Flash>, used: usize) -> u8 {
+    let mut last = 0;
+    for count in used..RECORDS {
+        last = u8::try_from(count % 256).unwrap();
+        block_on(store.write(0, &[last; PAGE_SIZE])).unwrap();
+    }
+    last
+}
+
+/// Mark of a sector live as `generation`.
+fn mark(generation: u32) -> Vec<u8> { [generation.to_le_bytes(), (!generation).to_le_bytes()].concat() }
+
+/// Whether the sector at `offset` is erased throughout.
+fn erased(model: &FlashModel, offset: usize) -> bool {
+    model.peek(offset, FLASH_SECTOR_LEN).iter().all(|&byte| byte == 0xFF)
+}
+
+#[test]
+fn writes_survive_reopening() {
+    let model = FlashModel::new();
+    let mut store = open(&model);
+    assert_eq!(model.peek(SECTOR_A, 8), mark(0));
+    let data: Vec<u8> = (0..100).collect();
+    block_on(store.write(20, &data)).unwrap();
+    drop(store);
+    let mut store = open(&model);
+    assert_eq!(read(&mut store, 20, data.len()), data);
+    assert_eq!(read(&mut store, 0, 20), [0xFF; 20]);
+}
+
+#[test]
+fn torn_record_keeps_the_previous_contents() {
+    // Cut in the payload, after it, and in the header: after page 0's
+    // number, whose inverse reads as erased, and short of its last byte.
+    for cut in [10, PAGE_SIZE, PAGE_SIZE + 2, RECORD_LEN - 1] {
+        let model = FlashModel::new();
+        let mut store = open(&model);
+        block_on(store.write(0, &[1; 8])).unwrap();
+        model.cut_power_after(cut);
+        assert!(block_on(store.write(0, &[2; 8])).is_err());
+        model.restore_power();
+        let mut store = open(&model);
+        assert_eq!(read(&mut store, 0, 8), [1; 8], "cut after {cut} bytes");
+        // The torn record is passed over, not programmed again.
+        block_on(store.write(0, &[3; 8])).unwrap();
+        let mut store = open(&model);
+        assert_eq!(read(&mut store, 0, 8), [3; 8], "cut after {cut} bytes");
+    }
+}
+
+#[test]
+fn compaction_cut_before_the_mark_keeps_the_old_sector() {
+    let model = FlashModel::new();
+    let mut store = open(&model);
+    block_on(store.write(32, &[7; PAGE_SIZE])).unwrap();
+    let last = fill_log(&mut store, 1);
+    // Into the second of the two records copied.
+    model.cut_power_after(RECORD_LEN + 5);
+    assert!(block_on(store.write(0, &[0xAA; 4])).is_err());
+    model.restore_power();
+    let mut store = open(&model);
+    assert_eq!(read(&mut store, 0, PAGE_SIZE), [last; PAGE_SIZE]);
+    assert_eq!(read(&mut store, 32, PAGE_SIZE), [7; PAGE_SIZE]);
+    assert!(erased(&model, SECTOR_B));
+    // The next write compacts again, this time to the end.
+    block_on(store.write(0, &[0xAA; 4])).unwrap();
+    assert_eq!(model.peek(SECTOR_B, 8), mark(1));
+    assert!(erased(&model, SECTOR_A));
+    let mut store = open(&model);
+    assert_eq!(read(&mut store, 0, 4), [0xAA; 4]);
+    assert_eq!(read(&mut store, 32, PAGE_SIZE), [7; PAGE_SIZE]);
+}
+
+#[test]
+fn compaction_cut_after_the_mark_keeps_the_new_sector() {
+    let model = FlashModel::new();
+    let mut store = open(&model);
+    block_on(store.write(32, &[7; PAGE_SIZE])).unwrap();
+    let last = fill_log(&mut store, 1);
+    // Both records and the mark, but not the erase of the old sector.
+    model.cut_power_after(2 * RECORD_LEN + 8);
+    assert!(block_on(store.write(0, &[0xAA; 4])).is_err());
+    assert_eq!(model.peek(SECTOR_A, 8), mark(0));
+    assert_eq!(model.peek(SECTOR_B, 8), mark(1));
+    model.restore_power();
+    let mut store = open(&model);
+    assert!(erased(&model, SECTOR_A));
+    assert_eq!(read(&mut store, 0, PAGE_SIZE), [last; PAGE_SIZE]);
+    assert_eq!(read(&mut store, 32, PAGE_SIZE), [7; PAGE_SIZE]);
+    block_on(store.write(0, &[0xAA; 4])).unwrap();
+    let mut store = open(&model);
+    assert_eq!(read(&mut store, 0, 4), [0xAA; 4]);
+}
+
+#[test]
+fn generation_wraps_around() {
+    let model = FlashModel::new();
+    model.load(SECTOR_A, &mark(u32::MAX));
+    let mut store = open(&model);
+    let last = fill_log(&mut store, 0);
+    // Leave both sectors marked, so the next boot has to pick the newer.
+    model.cut_power_after(RECORD_LEN + 8);
+    assert!(block_on(store.write(0, &[0xAA; 4])).is_err());
+    assert_eq!(model.peek(SECTOR_B, 8), mark(0));
+    model.restore_power();
+    let mut store = open(&model);
+    assert!(erased(&model, SECTOR_A));
+    assert_eq!(read(&mut store, 0, PAGE_SIZE), [last; PAGE_SIZE]);
+}
+
+#[test]
+fn watchdog_is_reloaded_before_each_erase() {
+    let model = FlashModel::new();
+    let mut store = open(&model);
+    assert_eq!(model.erases(), 0);
+    fill_log(&mut store, 0);
+    block_on(store.write(0, &[0xAA; 4])).unwrap();
+    // The spare sector was still erased; only the retired one needed it.
+    assert_eq!(model.erases(), 1);
+    assert_eq!(RELOADS.with(Cell::get), model.erases());
+}
@@ -0,0 +1,138 @@
+//! The FT24C64 driver against the in-memory chip.
+
+use embassy_futures::block_on;
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+use keychron_q6_he_host_tests::{
+    eeprom::{Ft24c64, Ft24c64Error, PAGE_SIZE, Store},
+    model::{Bus, Delay, Model, PinFault, WpPin},
+};
+
+/// Driver wired to `model`.
+fn driver(model: &Model) -> Ft24c64<Bus, WpPin, Delay> { Ft24c64::new(model.bus(), model.wp(), model.delay()).unwrap() }
+
+/// `len` bytes of a pattern no page of an erased chip holds.
+fn pattern(len: usize) -> Vec<u8> { (0..len).map(|index| u8::try_from(index % 251).unwrap()).collect() }
+
+#[test]
+fn writes_split_at_page_boundaries() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    let data = pattern(100);
+    block_on(eeprom.write(20, &data)).unwrap();
+    // 20..32, 32..64, 64..96, and 96..120: an unsplit write would have
+    // wrapped onto the start of the first page.
+    assert_eq!(model.page_writes(), 4);
+    assert_eq!(model.peek(20, data.len()), data);
+    assert_eq!(model.peek(0, 20), [0xFF; 20]);
+    let mut buf = vec![0_u8; data.len()];
+    block_on(eeprom.read(20, &mut buf)).unwrap();
+    assert_eq!(buf, data);
+}
+
+#[test]
+fn write_changed_skips_matching_pages() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    let old = pattern(PAGE_SIZE * 3);
+    block_on(eeprom.write(0, &old)).unwrap();
+    let mut new = old.clone();
+    new[PAGE_SIZE + 5] ^= 0xFF;
+    block_on(eeprom.write_changed(0, &new, &old)).unwrap();
+    assert_eq!(model.page_writes(), 4);
+    assert_eq!(model.peek(0, new.len()), new);
+}
+
+#[test]
+fn write_protect_is_raised_between_writes() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    assert!(model.wp_high());
+    // The chip refuses data while WP is high, so the write landing shows
+    // the driver lowered it.
+    block_on(eeprom.write(0, &[1, 2, 3])).unwrap();
+    assert_eq!(model.peek(0, 3), [1, 2, 3]);
+    assert!(model.wp_high());
+}
+
+#[test]
+fn new_reports_a_stuck_write_protect_pin() {
+    let model = Model::new();
+    model.stick_wp(Some(false));
+    assert!(matches!(Ft24c64::new(model.bus(), model.wp(), model.delay()), Err(PinFault)));
+}
+
+#[test]
+fn write_fails_before_sending_if_write_protect_stays_high() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    model.stick_wp(Some(true));
+    assert!(matches!(block_on(eeprom.write(0, &[1])), Err(Ft24c64Error::Wp(PinFault))));
+    assert_eq!(model.page_writes(), 0);
+}
+
+#[test]
+fn write_fails_if_write_protect_cannot_be_raised_again() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    model.stick_wp(Some(false));
+    assert!(matches!(block_on(eeprom.write(0, &[1])), Err(Ft24c64Error::Wp(PinFault))));
+    // The page itself landed; the error reports the chip left writable.
+    assert_eq!(model.peek(0, 1), [1]);
+}
+
+#[test]
+fn write_polls_through_the_write_cycle() {
+    let model = Model::new();
+    model.set_write_cycle_polls(200);
+    let mut eeprom = driver(&model);
+    let data = pattern(PAGE_SIZE * 2);
+    block_on(eeprom.write(0, &data)).unwrap();
+    let mut buf = vec![0_u8; data.len()];
+    block_on(eeprom.read(0, &mut buf)).unwrap();
+    assert_eq!(buf, data);
+}
+
+#[test]
+fn failed_transfers_are_retried_with_backoff() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    // The driver makes four attempts in all, waiting 1, 2, and 4 ms before
+    // the retries.
+    model.fail_transfers(3);
+    block_on(eeprom.write(0, &[7, 8])).unwrap();
+    assert_eq!(model.peek(0, 2), [7, 8]);
+    assert!(model.elapsed_us() >= 7_000);
+
+    model.fail_transfers(3);
+    let mut buf = [0_u8; 2];
+    block_on(eeprom.read(0, &mut buf)).unwrap();
+    assert_eq!(buf, [7, 8]);
+}
+
+#[test]
+fn errors_past_the_retry_budget_are_returned() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+
+    model.fail_transfers(4);
+    let mut buf = [0_u8; 1];
+    assert!(matches!(block_on(eeprom.read(0, &mut buf)), Err(Ft24c64Error::Bus(error)) if error == nack));
+
+    model.fail_transfers(4);
+    assert!(matches!(block_on(eeprom.write(0, &[1])), Err(Ft24c64Error::Bus(error)) if error == nack));
+    assert_eq!(model.peek(0, 1), [0xFF]);
+    assert!(model.wp_high());
+}
+
+#[test]
+fn an_absent_chip_fails_reads() {
+    let model = Model::new();
+    let mut eeprom = driver(&model);
+    model.set_present(false);
+    let mut buf = [0_u8; 1];
+    assert!(matches!(
+        block_on(eeprom.read(0, &mut buf)),
+        Err(Ft24c64Error::Bus(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)))
+    ));
+}
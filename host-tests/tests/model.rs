@@ -0,0 +1,62 @@
+//! The model itself behaves like the datasheet describes, so the driver tests
+//! built on it mean something.
+
+use embassy_futures::block_on;
+use embedded_hal::{
+    digital::OutputPin,
+    i2c::{ErrorKind, NoAcknowledgeSource},
+};
+use embedded_hal_async::i2c::I2c;
+use keychron_q6_he_host_tests::model::{DEVICE_ADDR, Model};
+
+/// The error of a byte the chip refused.
+const DATA_NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+/// The error of an addressing nobody answered.
+const ADDRESS_NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+#[test]
+fn page_write_wraps_within_the_page() {
+    let model = Model::new();
+    model.set_write_cycle_polls(0);
+    model.wp().set_low().unwrap();
+    block_on(model.bus().write(DEVICE_ADDR, &[0x00, 0x3E, 1, 2, 3, 4])).unwrap();
+    assert_eq!(model.peek(0x3E, 2), [1, 2]);
+    assert_eq!(model.peek(0x20, 2), [3, 4]);
+    assert_eq!(model.peek(0x40, 2), [0xFF, 0xFF]);
+}
+
+#[test]
+fn write_protect_refuses_data() {
+    let model = Model::new();
+    assert_eq!(block_on(model.bus().write(DEVICE_ADDR, &[0x00, 0x10, 0xAB])), Err(DATA_NACK));
+    assert_eq!(model.peek(0x10, 1), [0xFF]);
+    assert_eq!(model.page_writes(), 0);
+}
+
+#[test]
+fn write_cycle_nacks_polls() {
+    let model = Model::new();
+    model.set_write_cycle_polls(2);
+    model.wp().set_low().unwrap();
+    let mut bus = model.bus();
+    block_on(bus.write(DEVICE_ADDR, &[0x00, 0x00, 0x42])).unwrap();
+    assert_eq!(block_on(bus.write(DEVICE_ADDR, &[])), Err(ADDRESS_NACK));
+    assert_eq!(block_on(bus.write(DEVICE_ADDR, &[])), Err(ADDRESS_NACK));
+    assert_eq!(block_on(bus.write(DEVICE_ADDR, &[])), Ok(()));
+}
+
+#[test]
+fn reads_roll_over_at_the_end_of_memory() {
+    let model = Model::new();
+    model.load(0x1FFE, &[1, 2]);
+    model.load(0x0000, &[3, 4]);
+    let mut buf = [0_u8; 4];
+    block_on(model.bus().write_read(DEVICE_ADDR, &[0x1F, 0xFE], &mut buf)).unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+}
+
+#[test]
+fn other_addresses_go_unanswered() {
+    let model = Model::new();
+    assert_eq!(block_on(model.bus().write(0x50, &[])), Err(ADDRESS_NACK));
+}
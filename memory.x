@@ -0,0 +1,19 @@
+/* STM32F401RC: 256 KiB flash, 64 KiB RAM. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+  RAM   : ORIGIN = 0x20000000, LENGTH = 64K
+}
+
+/* Sectors 1 and 2, 16 KiB each, hold the EEPROM fallback store
+   (src/eeprom/flash_store.rs). The vector table stays in sector 0, where the
+   core boots from, and .text, with everything after it, starts at sector 3,
+   leaving the image 208 KiB there. */
+_flash_store_start = 0x08004000;
+_flash_store_end = 0x0800C000;
+_stext = _flash_store_end;
+
+/* cortex-m-rt keeps .text clear of the vector table and inside FLASH; this
+   keeps the vector table clear of the store. */
+ASSERT(ADDR(.vector_table) + SIZEOF(.vector_table) <= _flash_store_start,
+       "the vector table runs into the EEPROM fallback store");
@@ -21,6 +21,16 @@ pub static BACKLIGHT_CH: Channel<CriticalSectionRawMutex, BacklightCmd, BACKLIGH
 /// Commands for lock indicator LEDs.
 #[derive(Copy, Clone)]
 pub enum BacklightCmd {
+    /// Signal a phase of a single-key recalibration: the normal backlight
+    /// stays on with LED `led` highlighted in the phase's color.
+    CalibKey {
+        /// LED of the key being recalibrated.
+        led:   u8,
+        /// Phase the recalibration has entered; [`CalibPhase::AllAccepted`]
+        /// is never sent, since [`BacklightCmd::CalibKeyDone`] already marks
+        /// the one key accepted.
+        phase: CalibPhase,
+    },
     /// Mark a single LED as fully calibrated during the full-travel pass.
     ///
     /// The backlight task immediately repaints that LED green, providing
@@ -32,6 +42,10 @@ pub enum BacklightCmd {
     ///
     /// Drives the whole-keyboard blue→green gradient.
     CalibProgress(u8),
+    /// Highlight the keys whose sensor reports a fault: bit `i` set paints
+    /// LED `i` in the fault color over the normal background; `0` clears the
+    /// overlay.
+    HealthFaults(u128),
     /// Update Caps Lock / Num Lock indicator LED states.
     Indicators {
         /// Whether Caps Lock is currently active.
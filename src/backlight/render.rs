@@ -41,6 +41,12 @@ pub(super) const CALIB_AMBER: (u8, u8, u8) = (255, 120, 0);
 /// Solid color used for the post-calibration success hold and for each
 /// individually confirmed key during the full-travel pass (green).
 pub(super) const CALIB_GREEN: (u8, u8, u8) = (0, 220, 80);
+/// Color of the key being recalibrated on its own while it waits to be held
+/// down (red, the start of the full-travel gradient).
+const CALIB_RED: (u8, u8, u8) = (255, 0, 0);
+/// Color of keys flagged by the sensor health overlay (magenta, distinct
+/// from the red Caps Lock indicator).
+const HEALTH_FAULT: (u8, u8, u8) = (255, 0, 160);
 /// Number of brightness steps in the soft-start ramp.
 const SOFTSTART_STEPS: u8 = 50;
 /// Total duration of the soft-start ramp in milliseconds.
@@ -93,11 +99,16 @@ impl BacklightFlags {
 /// throttle, USB connect/resume transitions) can redraw the frame the
 /// calibration flow expects instead of clobbering it with the normal white
 /// background. `Full` and `Zero` both mean a first-boot calibration is in
-/// progress.
+/// progress; `KeyFull` and `KeyZero` a single-key recalibration.
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub(super) enum CalibDisplay {
     /// Full-travel pass; red-to-blue gradient plus per-key green overlays.
     Full,
+    /// Single-key full-travel pass; normal background with the key red until
+    /// accepted, then green.
+    KeyFull,
+    /// Single-key zero pass; normal background with the key amber.
+    KeyZero,
     /// No calibration in progress; normal white background.
     #[default]
     None,
@@ -119,6 +130,9 @@ pub(super) struct BacklightState {
     /// returns to normal white operation. Consulted by every asynchronous
     /// repaint path.
     pub calib_display:   CalibDisplay,
+    /// LED of the key highlighted by [`render_calib_key`] while a single-key
+    /// recalibration owns the display.
+    pub calib_key:       u8,
     /// Bitset of LED indices confirmed calibrated during the full-travel pass.
     ///
     /// Bit `i` set means LED `i` should be painted solid green by
@@ -131,6 +145,9 @@ pub(super) struct BacklightState {
     /// Cleared to zero alongside [`BacklightState::calib_leds_done`] on
     /// calibration completion.
     pub calib_pct:       u8,
+    /// Bitset of LED indices painted in the health fault color over the
+    /// normal background; zero while the overlay is off.
+    pub health_leds:     u128,
     /// Packed lock / host-connection flags.
     ///
     /// `host_connected` is tracked across Power commands to detect
@@ -165,6 +182,7 @@ pub(super) async fn brightness_ramp(
 
         let (red, green, blue) = correct(base_red, base_green, base_blue, percent);
         driver.stage_all_leds(red, green, blue);
+        stage_health_leds(driver, state, percent);
         stage_indicator_leds(driver, state, percent.min(INDICATOR_BRIGHTNESS));
 
         if let Err(err) = driver.flush().await {
@@ -221,8 +239,9 @@ pub(super) async fn fill_all_leds(
 /// Writes every LED to hardware according to `state`.
 ///
 /// Paints the background white at the current brightness, then overlays the
-/// indicator LEDs on top. Called after any state change requiring a full
-/// redraw (e.g. thermal-throttle transitions during normal operation).
+/// health fault LEDs and the indicator LEDs on top. Called after any state
+/// change requiring a full redraw (e.g. thermal-throttle transitions during
+/// normal operation).
 ///
 /// # Errors
 ///
@@ -230,6 +249,7 @@ pub(super) async fn fill_all_leds(
 pub(super) async fn render_all(driver: &mut BacklightDriver, state: BacklightState) -> Result<(), BusError> {
     let (red, green, blue) = correct_color(INDICATOR_WHITE, state.brightness);
     driver.stage_all_leds(red, green, blue);
+    stage_health_leds(driver, state, state.brightness);
     render_indicators(driver, state).await
 }
 
@@ -262,6 +282,34 @@ pub(super) async fn render_calib(driver: &mut BacklightDriver, state: BacklightS
     driver.flush().await
 }
 
+/// Render the single-key recalibration frame: the normal background and
+/// overlays of [`render_all`], with [`BacklightState::calib_key`] amber
+/// during the zero pass, red while it waits to be held down, and green
+/// once its bit in `state.calib_leds_done` is set.
+///
+/// # Errors
+///
+/// Returns `Err` if any bus transaction fails. See [`BacklightDriver::flush`].
+pub(super) async fn render_calib_key(driver: &mut BacklightDriver, state: BacklightState) -> Result<(), BusError> {
+    let (red, green, blue) = correct_color(INDICATOR_WHITE, state.brightness);
+    driver.stage_all_leds(red, green, blue);
+    stage_health_leds(driver, state, state.brightness);
+    stage_indicator_leds(driver, state, INDICATOR_BRIGHTNESS);
+
+    let accepted = 1_u128.checked_shl(u32::from(state.calib_key)).is_some_and(|bit| state.calib_leds_done & bit != 0);
+    let color = if accepted {
+        CALIB_GREEN
+    } else if state.calib_display == CalibDisplay::KeyFull {
+        CALIB_RED
+    } else {
+        CALIB_AMBER
+    };
+    let (key_red, key_green, key_blue) = correct_color(color, state.brightness);
+    driver.stage_led(usize::from(state.calib_key), key_red, key_green, key_blue);
+
+    driver.flush().await
+}
+
 /// Writes only the two indicator LEDs according to `state`.
 ///
 /// Used when only indicator state has changed and the background is already
@@ -289,6 +337,18 @@ const fn scale(value: u8, brightness_percent: u8) -> u8 {
         .unwrap_or(255)
 }
 
+/// Stage every LED in [`BacklightState::health_leds`] in the fault color at
+/// `brightness` without flushing.
+const fn stage_health_leds(driver: &mut BacklightDriver, state: BacklightState, brightness: u8) {
+    let (red, green, blue) = correct_color(HEALTH_FAULT, brightness);
+    let mut bits = state.health_leds;
+    while bits != 0 {
+        let idx = usize::try_from(bits.trailing_zeros()).unwrap_or(usize::MAX);
+        driver.stage_led(idx, red, green, blue);
+        bits &= bits.saturating_sub(1);
+    }
+}
+
 /// Stage both indicator LEDs with the given `brightness` without flushing.
 ///
 /// Caps Lock: red when active, white when inactive.
@@ -24,6 +24,7 @@ use crate::{
             fill_all_leds,
             render_all,
             render_calib,
+            render_calib_key,
             render_indicators,
         },
     },
@@ -119,9 +120,31 @@ impl BacklightRunner {
         }
     }
 
+    /// Apply a single-key recalibration phase transition for LED `led`:
+    /// highlight it over the normal frame as described in
+    /// [`render_calib_key`], and hand the display back once done.
+    async fn handle_calib_key(&mut self, state: &mut BacklightState, led: u8, phase: CalibPhase) {
+        match phase {
+            Zero => {
+                state.calib_display = CalibDisplay::KeyZero;
+                state.calib_key = led;
+                state.calib_leds_done = 0;
+            },
+            Full => state.calib_display = CalibDisplay::KeyFull,
+            // Never sent for a single key; CalibKeyDone marks it accepted.
+            AllAccepted => {},
+            Done => {
+                state.calib_display = CalibDisplay::None;
+                state.calib_leds_done = 0;
+            },
+        }
+        _ = self.render_current(*state).await;
+    }
+
     /// Apply a single [`BacklightCmd`] arriving on [`BACKLIGHT_CH`].
     async fn handle_cmd(&mut self, state: &mut BacklightState, cmd: BacklightCmd) {
         match cmd {
+            BacklightCmd::CalibKey { led, phase } => self.handle_calib_key(state, led, phase).await,
             BacklightCmd::CalibPhase(phase) => self.handle_calib_phase(state, phase).await,
             BacklightCmd::CalibProgress(pct) => {
                 state.calib_pct = pct;
@@ -135,7 +158,13 @@ impl BacklightRunner {
                 if let Some(bit) = 1_u128.checked_shl(u32::from(led_idx)) {
                     state.calib_leds_done |= bit;
                 }
-                _ = render_calib(&mut self.driver, *state).await;
+                _ = self.render_current(*state).await;
+            },
+            BacklightCmd::HealthFaults(leds) => {
+                state.health_leds = leds;
+                if state.calib_display == CalibDisplay::None {
+                    _ = render_all(&mut self.driver, *state).await;
+                }
             },
             BacklightCmd::Indicators { caps, num } => {
                 state.flags.set(BacklightFlags::CAPS_LOCK, caps);
@@ -236,6 +265,7 @@ impl BacklightRunner {
             CalibDisplay::None => render_all(&mut self.driver, state).await,
             CalibDisplay::Zero => fill_all_leds(&mut self.driver, CALIB_AMBER, state.brightness).await,
             CalibDisplay::Full => render_calib(&mut self.driver, state).await,
+            CalibDisplay::KeyFull | CalibDisplay::KeyZero => render_calib_key(&mut self.driver, state).await,
         }
     }
 }
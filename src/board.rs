@@ -30,7 +30,10 @@ use embassy_stm32::{
     },
     time::Hertz,
 };
-use pac::{ADC1_COMMON, SYSCFG, adccommon::vals::Adcpre};
+use pac::{ADC1_COMMON, IWDG, SYSCFG, adccommon::vals::Adcpre, iwdg::vals::Key};
+
+/// Core clock (SYSCLK) configured by [`stm32_config`], in hertz.
+pub const SYSCLK_HZ: u32 = 84_000_000;
 
 /// Owns the six analog row pins of the Q6 HE matrix.
 ///
@@ -135,6 +138,19 @@ pub fn stm32_config() -> Config {
     config
 }
 
+/// Enable the Cortex-M DWT cycle counter used by the scan statistics.
+///
+/// Call once after [`embassy_stm32::init`]; if the core peripherals were
+/// already taken the counter is left off, and the scan statistics answer
+/// the host as untimed (see [`crate::matrix::analog_matrix::stats::timed`])
+/// instead of reporting zeros.
+pub fn enable_cycle_counter() {
+    if let Some(mut core) = cortex_m::Peripherals::take() {
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+    }
+}
+
 /// Enable the FLASH instruction cache, data cache, and prefetch buffer to
 /// keep wait-stated flash reads off the hot path.
 pub fn enable_flash_acceleration() {
@@ -145,6 +161,12 @@ pub fn enable_flash_acceleration() {
     });
 }
 
+/// Reload the independent watchdog; no effect while it is not running.
+///
+/// The internal-flash store calls this before each sector erase, which
+/// stalls the CPU for a few hundred milliseconds.
+pub fn reload_watchdog() { IWDG.kr().write(|w| w.set_key(Key::RESET)); }
+
 /// Apply the board's ADC clocking and noise tweaks.
 ///
 /// Sets the ADC prescaler to /2 (42 MHz, overclocked from the 36 MHz spec)
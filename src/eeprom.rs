@@ -1,173 +1,140 @@
+/// Internal-flash fallback store.
+pub mod flash_store;
+/// FT24C64 EEPROM driver.
+pub mod ft24c64;
+/// EEPROM partition table.
+pub mod partition;
+/// RMK storage backend on the keymap partition.
+pub mod storage;
+/// Storage abstraction over the EEPROM and its fallback.
+pub mod store;
+
+use crate::eeprom::flash_store::{FlashStore, FlashStoreError};
+use core::convert::Infallible;
 use embassy_stm32::{
+    flash::{self, Blocking, Flash},
     gpio::{Flex, Pull, Speed},
-    i2c::{Error, Error::Overrun, I2c, mode::MasterMode},
-    mode::Async,
 };
-use embassy_time::{Duration, Timer};
-use embedded_hal_async::i2c::Operation;
-
-/// 7-bit I²C device address: base 0x50 with A0 wired high on this board
-/// (A1 = A2 = GND), giving 0x51.
-const DEVICE_ADDR: u8 = 0x51;
-/// Total size of the FT24C64 in bytes (64 Kbit).
-const EEPROM_SIZE: usize = 8192;
-/// Page write size in bytes per the FT24C64 datasheet.
-const PAGE_SIZE: usize = 32;
-/// Number of successive I²C acknowledgement polls during page writes.
-const READY_POLL_ATTEMPTS: u8 = 255;
-/// Number of successive I²C acknowledgement polls before a read, limited to a
-/// shorter budget to avoid stalling the keyboard for the full write-cycle
-/// window when the device is absent or malfunctioning.
-const READ_POLL_ATTEMPTS: u8 = 20;
-/// Delay between successive I²C acknowledgement polls.
-const READY_POLL_INTERVAL: Duration = Duration::from_micros(20);
-
-/// Driver for the FT24C64 64-Kbit (8 K × 8) I²C EEPROM.
-pub struct Ft24c64<'peripherals, IM: MasterMode> {
-    /// I²C peripheral used for all device communication.
-    i2c: I2c<'peripherals, Async, IM>,
-    /// Write-protect pin managed as input pull-up when idle, output-low when
-    /// writing.
-    wp:  Flex<'peripherals>,
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::{Delay, Duration, Timer};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_async::i2c::I2c;
+pub use ft24c64::{EEPROM_SIZE, Ft24c64, Ft24c64Error, PAGE_SIZE};
+pub use store::Store;
+
+/// SCL pulses of a bus recovery: enough to clock out the rest of any byte a
+/// slave was sending, plus its acknowledge slot.
+const RECOVERY_CLOCKS: u8 = 9;
+/// Half period of the bus recovery clock: 5 µs, standard-mode 100 kHz, which
+/// every device on the bus accepts.
+const RECOVERY_HALF_PERIOD: Duration = Duration::from_micros(5);
+
+/// The store shared between the matrix scanner, which owns calibration,
+/// tuning, and settings, and RMK's keymap storage.
+pub type SharedStore<S> = Mutex<ThreadModeRawMutex, S>;
+
+/// The store picked at boot: the EEPROM, or the internal-flash fallback on a
+/// board whose EEPROM does not respond.
+pub enum Backend<I2C, WP> {
+    /// The on-board FT24C64.
+    Eeprom(Ft24c64<I2C, WP, Delay>),
+    /// The reserved internal-flash sectors.
+    Flash(FlashStore<Flash<'static, Blocking>>),
 }
 
-impl<'peripherals, IM: MasterMode> Ft24c64<'peripherals, IM> {
-    /// Return the write-protect pin to its idle pulled-high state.
-    fn assert_wp(&mut self) { self.wp.set_as_input(Pull::Up); }
-
-    /// Drive the write-protect pin low so the device accepts writes.
-    ///
-    /// Must be paired with a [`Ft24c64::assert_wp`] call before returning so
-    /// the pin returns to its idle pulled-high state.
-    fn deassert_wp(&mut self) {
-        self.wp.set_low();
-        self.wp.set_as_output(Speed::Low);
-    }
-
-    /// Erase the entire EEPROM by writing `0xFF` to all [`EEPROM_SIZE`] bytes.
-    ///
-    /// Pages are written sequentially from address `0x0000` to `0x1FFF` (256
-    /// pages × 32 bytes). After each page write the driver polls for
-    /// readiness before continuing with the next page.
-    ///
-    /// Call this before writing new calibration data to guarantee no stale
-    /// data survives in any region of the device.
-    ///
-    /// # Errors
-    ///
-    /// Returns the first [`Error`] encountered. Any pages written before the
-    /// failure are not rolled back.
-    pub async fn erase(&mut self) -> Result<(), Error> {
-        self.deassert_wp();
-
-        let blank = [0xFF_u8; PAGE_SIZE];
-        let mut offset = 0_usize;
-        let mut result = Ok(());
-        while offset < EEPROM_SIZE {
-            let Ok(addr) = u16::try_from(offset) else {
-                result = Err(Overrun);
-                break;
-            };
-            result = self.write_page_raw(addr, &blank).await;
-            if result.is_err() {
-                break;
-            }
-            offset = offset.saturating_add(PAGE_SIZE);
-        }
-
-        self.assert_wp();
-        result
-    }
-
-    /// Create a new driver.
-    pub fn new(i2c: I2c<'peripherals, Async, IM>, mut wp: Flex<'peripherals>) -> Self {
-        // Input pull-up: write-protect asserted via pull resistor.
-        wp.set_as_input(Pull::Up);
-        Self { i2c, wp }
-    }
+impl<I2C: I2c, WP: OutputPin> Store for Backend<I2C, WP> {
+    type Error = BackendError<Ft24c64Error<I2C::Error, WP::Error>>;
 
-    /// Poll the device with a zero-length write until it ACKs, indicating
-    /// the device is ready to accept commands. Used both after page writes
-    /// to wait for the internal write cycle to complete, and on first access
-    /// after power-on to wait out the reset delay.
-    ///
-    /// Retries up to `max_attempts` times with [`READY_POLL_INTERVAL`]
-    /// between each attempt. Returns `Ok(())` as soon as the device
-    /// acknowledges, or [`Error`] if it does not become ready within
-    /// `max_attempts`.
-    async fn poll_until_ready(&mut self, max_attempts: u8) -> Result<(), Error> {
-        let mut attempts = 0_u8;
-        loop {
-            let result = self.i2c.write(DEVICE_ADDR, &[]).await;
-            if result.is_ok() {
-                return Ok(());
-            }
-            attempts = attempts.saturating_add(1);
-            if attempts >= max_attempts {
-                return result;
-            }
-            Timer::after(READY_POLL_INTERVAL).await;
+    async fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
+        match self {
+            Self::Eeprom(eeprom) => eeprom.read(addr, buf).await.map_err(BackendError::Eeprom),
+            Self::Flash(flash) => flash.read(addr, buf).await.map_err(BackendError::Flash),
         }
     }
 
-    /// Read `buf.len()` bytes starting at 16-bit word address `addr`.
-    ///
-    /// Polls the device until it acknowledges before issuing the read,
-    /// accommodating the power-on reset delay without requiring a fixed
-    /// worst-case wait from the caller.
-    pub async fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Error> {
-        let ready = self.poll_until_ready(READ_POLL_ATTEMPTS).await;
-        if let Err(error) = ready {
-            return Err(error);
+    async fn write_changed(&mut self, start_addr: u16, data: &[u8], old: &[u8]) -> Result<(), Self::Error> {
+        match self {
+            Self::Eeprom(eeprom) => eeprom.write_changed(start_addr, data, old).await.map_err(BackendError::Eeprom),
+            Self::Flash(flash) => flash.write_changed(start_addr, data, old).await.map_err(BackendError::Flash),
         }
-        self.i2c.write_read(DEVICE_ADDR, &addr.to_be_bytes(), buf).await
     }
+}
 
-    /// Write `data` starting at 16-bit word address `start_addr`.
-    pub async fn write(&mut self, start_addr: u16, data: &[u8]) -> Result<(), Error> {
-        self.deassert_wp();
-
-        let mut offset = 0_usize;
-        let mut result = Ok(());
-
-        while offset < data.len() {
-            // Convert byte offset to an u16 address, saturating on overflow so
-            // we never silently wrap into a wrong EEPROM location.
-            let addr = start_addr.saturating_add(u16::try_from(offset).unwrap_or(u16::MAX));
+/// Failure of a [`Backend`] access.
+#[derive(Debug)]
+pub enum BackendError<E> {
+    /// The EEPROM access failed (see [`Ft24c64Error`]).
+    Eeprom(E),
+    /// The internal-flash store access failed (see [`FlashStoreError`]).
+    Flash(FlashStoreError<flash::Error>),
+}
 
-            // Bytes remaining before the next 32-byte page boundary.
-            let page_offset = usize::from(addr).rem_euclid(PAGE_SIZE);
-            let page_remaining = PAGE_SIZE.saturating_sub(page_offset);
+/// FT24C64 write-protect line driven the way the board expects: released to
+/// the pin's pull-up while protected, driven low only while writing.
+///
+/// The line is never driven high, so the MCU never fights whatever else
+/// holds it while the device is protected.
+pub struct WriteProtect<'d> {
+    /// The write-protect pin, switched between pulled-up input and low
+    /// output.
+    pin: Flex<'d>,
+}
 
-            // Bytes to write on this page, the smaller of the remaining page
-            // space and the remaining data.
-            let chunk_len = page_remaining.min(data.len().saturating_sub(offset));
-            let chunk = data.get(offset..offset.saturating_add(chunk_len)).unwrap_or(&[]);
+impl<'d> WriteProtect<'d> {
+    /// Take over `pin`, released to its pull-up so the device starts out
+    /// protected.
+    pub fn new(mut pin: Flex<'d>) -> Self {
+        pin.set_as_input(Pull::Up);
+        Self { pin }
+    }
+}
 
-            result = self.write_page_raw(addr, chunk).await;
-            if result.is_err() {
-                break;
-            }
+impl ErrorType for WriteProtect<'_> {
+    type Error = Infallible;
+}
 
-            offset = offset.saturating_add(chunk_len);
-        }
+impl OutputPin for WriteProtect<'_> {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_as_input(Pull::Up);
+        Ok(())
+    }
 
-        self.assert_wp();
-        result
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.pin.set_low();
+        self.pin.set_as_output(Speed::Low);
+        Ok(())
     }
+}
 
-    /// Write one aligned page to the device without managing the write-protect
-    /// pin.
-    ///
-    /// Callers must deassert WP before calling and re-assert it afterward.
-    /// `chunk` must not cross a page boundary.
-    async fn write_page_raw(&mut self, addr: u16, chunk: &[u8]) -> Result<(), Error> {
-        let addr_bytes = addr.to_be_bytes();
-        let result =
-            self.i2c.transaction(DEVICE_ADDR, &mut [Operation::Write(&addr_bytes), Operation::Write(chunk)]).await;
-        if result.is_err() {
-            return result;
+/// Free an I²C bus whose SDA line a slave is holding low.
+///
+/// A reset in the middle of a read leaves the FT24C64 waiting to clock out
+/// the rest of its byte, holding SDA low so the next transfer fails. Pulse
+/// SCL up to [`RECOVERY_CLOCKS`] times until the slave lets go, then issue a
+/// STOP so every device returns to idle.
+///
+/// Both pins must be open-drain outputs with their input buffers enabled;
+/// call before the I²C peripheral takes them over.
+pub async fn recover_bus<SCL: OutputPin, SDA: InputPin + OutputPin>(scl: &mut SCL, sda: &mut SDA) {
+    _ = sda.set_high();
+    _ = scl.set_high();
+    Timer::after(RECOVERY_HALF_PERIOD).await;
+    for _ in 0..RECOVERY_CLOCKS {
+        if sda.is_high().unwrap_or(false) {
+            break;
         }
-        self.poll_until_ready(READY_POLL_ATTEMPTS).await
+        _ = scl.set_low();
+        Timer::after(RECOVERY_HALF_PERIOD).await;
+        _ = scl.set_high();
+        Timer::after(RECOVERY_HALF_PERIOD).await;
     }
+    // STOP: SDA rises while SCL is high.
+    _ = scl.set_low();
+    Timer::after(RECOVERY_HALF_PERIOD).await;
+    _ = sda.set_low();
+    Timer::after(RECOVERY_HALF_PERIOD).await;
+    _ = scl.set_high();
+    Timer::after(RECOVERY_HALF_PERIOD).await;
+    _ = sda.set_high();
+    Timer::after(RECOVERY_HALF_PERIOD).await;
 }
@@ -0,0 +1,374 @@
+//! Internal-flash fallback for the EEPROM.
+//!
+//! A board whose FT24C64 no longer responds keeps its calibration, settings,
+//! and keymap in two 16 KiB sectors of the STM32F401's own flash instead,
+//! sectors 1 and 2, which `memory.x` keeps out of the firmware image.
+//! [`FlashStore`] presents them through [`Store`] with the EEPROM's address
+//! space, so nothing above it knows which device holds the bytes.
+//!
+//! Flash is erased a whole sector at a time and endures about ten thousand
+//! erase cycles, so a page is never rewritten in place. Every page write
+//! appends a record to a log filling the live sector, and a page reads from
+//! its newest record; a page never written reads as erased, `0xFF`, like a
+//! fresh EEPROM. Only once the log is full is it compacted: each page's
+//! newest contents are copied into the other, erased sector, which then
+//! takes over, and only after that is the old one erased. One erase thus
+//! absorbs well over a hundred page writes.
+//!
+//! A record's payload is programmed before its header, and a header counts
+//! only if its page number matches the inverted copy next to it and the zero
+//! bytes programmed after both are all there, so a record cut short by a
+//! power loss is skipped at the next boot and its page reads from the record
+//! before. A sector is live only once its mark, a generation
+//! number and its inverted copy, is programmed after every copied record, so
+//! a compaction cut short leaves the old sector live, and one whose erase of
+//! the old sector was cut short leaves the newer generation live. Whatever
+//! the boot finds in the sector that is not live is erased.
+//!
+//! The store drives any [`NorFlash`], so the host tests run it against an
+//! in-memory flash; the firmware hands it the STM32 flash controller and a
+//! hook reloading the watchdog before each erase.
+
+use crate::{
+    eeprom::{EEPROM_SIZE, PAGE_SIZE, Store},
+    matrix::calib_store::is_newer,
+};
+use embedded_storage::nor_flash::NorFlash;
+
+/// Byte length of the chunks [`FlashStore::erase_if_used`] reads a sector
+/// in.
+const BLANK_CHECK_LEN: usize = 256;
+/// Value of an erased flash byte.
+const ERASED: u8 = 0xFF;
+/// Byte length of a record header: the page number and its bitwise inverse
+/// (2 B LE each), then four zero bytes padding the header to the 8-byte
+/// flash write unit. The zero bytes are programmed last, so a header cut
+/// short never checks out, even for page 0, whose inverse reads as erased.
+const HEADER_LEN: usize = 8;
+/// Byte length of a sector mark at the start of the sector: the generation
+/// and its bitwise inverse (4 B LE each), one flash write unit.
+const MARK_LEN: usize = 8;
+/// Newest-record entry of a page no record holds.
+const NO_RECORD: u16 = u16::MAX;
+/// Number of pages in the emulated address space.
+const PAGES: usize = EEPROM_SIZE.wrapping_div(PAGE_SIZE);
+/// Byte length of a record: header, then one page of contents.
+const RECORD_LEN: usize = HEADER_LEN.saturating_add(PAGE_SIZE);
+/// Number of records a sector holds after its mark.
+const RECORDS: u16 = u16::try_from(SECTOR_SIZE.saturating_sub(MARK_LEN).wrapping_div(RECORD_LEN)).unwrap_or(NO_RECORD);
+/// Byte length of each of the two sectors.
+const SECTOR_SIZE: usize = 0x4000;
+
+const _: () = assert!(RECORDS < NO_RECORD, "record numbers collide with NO_RECORD");
+const _: () = assert!(usize::from(RECORDS) > PAGES, "a sector cannot hold every page after compaction");
+
+/// One of the two sectors the log alternates between.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sector {
+    /// Sector 1, at 0x0800_4000.
+    A,
+    /// Sector 2, at 0x0800_8000.
+    B,
+}
+
+impl Sector {
+    /// Both sectors.
+    const ALL: [Self; 2] = [Self::A, Self::B];
+
+    /// Offset of the sector from the start of flash; `_flash_store_start`
+    /// and `_flash_store_end` in `memory.x` bound the pair.
+    const fn offset(self) -> u32 {
+        match self {
+            Self::A => 0x4000,
+            Self::B => 0x8000,
+        }
+    }
+
+    /// The other sector.
+    const fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+}
+
+/// Failure of a [`FlashStore`] access.
+#[derive(Debug)]
+pub enum FlashStoreError<E> {
+    /// The flash reported an error.
+    Flash(E),
+    /// The access reaches past the emulated address space.
+    OutOfRange,
+}
+
+/// The reserved flash sectors, seen as an FT24C64.
+pub struct FlashStore<F> {
+    /// Flash the sectors are part of, addressed from its start.
+    flash:           F,
+    /// Generation in the live sector's mark.
+    generation:      u32,
+    /// Sector holding the log.
+    live:            Sector,
+    /// Record holding each page's newest contents, or [`NO_RECORD`].
+    newest:          [u16; PAGES],
+    /// First free record of the live sector; [`RECORDS`] once the log is
+    /// full.
+    next:            u16,
+    /// Called before each sector erase, which stalls the CPU.
+    reload_watchdog: fn(),
+}
+
+impl<F: NorFlash> FlashStore<F> {
+    /// Append `contents` as the newest record of `page`.
+    ///
+    /// The record is used up even if programming fails, so a half-written
+    /// record is never programmed over.
+    fn append(&mut self, page: u16, contents: &[u8; PAGE_SIZE]) -> Result<(), FlashStoreError<F::Error>> {
+        let slot = self.next;
+        self.next = slot.saturating_add(1);
+        self.program_record(self.live, slot, page, contents)?;
+        if let Some(newest) = self.newest.get_mut(usize::from(page)) {
+            *newest = slot;
+        }
+        Ok(())
+    }
+
+    /// Copy each page's newest contents into the other sector, make it the
+    /// live one, and erase the old.
+    ///
+    /// Copies a page at a time, so the emulated space is never held in RAM.
+    /// If anything fails before the new sector is marked, the old one stays
+    /// live and the next compaction starts over.
+    fn compact(&mut self) -> Result<(), FlashStoreError<F::Error>> {
+        let spare = self.live.other();
+        self.erase_if_used(spare)?;
+        let mut moved = [NO_RECORD; PAGES];
+        let mut next = 0_u16;
+        let mut contents = [ERASED; PAGE_SIZE];
+        for (page, entry) in (0_u16..).zip(moved.iter_mut()) {
+            self.read_page(usize::from(page), &mut contents)?;
+            if contents.iter().all(|&byte| byte == ERASED) {
+                continue;
+            }
+            self.program_record(spare, next, page, &contents)?;
+            *entry = next;
+            next = next.saturating_add(1);
+        }
+        let generation = self.generation.wrapping_add(1);
+        self.program_mark(spare, generation)?;
+        let old = self.live;
+        self.generation = generation;
+        self.live = spare;
+        self.newest = moved;
+        self.next = next;
+        self.erase(old)
+    }
+
+    /// Erase `sector`.
+    ///
+    /// The erase stalls every flash access, so the CPU waits it out, a few
+    /// hundred milliseconds for a 16 KiB sector. The watchdog hook runs
+    /// first, so the stall starts with the watchdog's full period.
+    fn erase(&mut self, sector: Sector) -> Result<(), FlashStoreError<F::Error>> {
+        (self.reload_watchdog)();
+        let start = sector.offset();
+        self.flash
+            .erase(start, start.saturating_add(u32::try_from(SECTOR_SIZE).unwrap_or(u32::MAX)))
+            .map_err(FlashStoreError::Flash)
+    }
+
+    /// Erase `sector` unless it already is, saving an erase cycle on the
+    /// common path where the sector was erased when it was last retired.
+    fn erase_if_used(&mut self, sector: Sector) -> Result<(), FlashStoreError<F::Error>> {
+        let mut chunk = [0_u8; BLANK_CHECK_LEN];
+        let chunk_len = u32::try_from(BLANK_CHECK_LEN).unwrap_or(u32::MAX);
+        let end = sector.offset().saturating_add(u32::try_from(SECTOR_SIZE).unwrap_or(u32::MAX));
+        let mut at = sector.offset();
+        while at < end {
+            self.flash.read(at, &mut chunk).map_err(FlashStoreError::Flash)?;
+            if chunk.iter().any(|&byte| byte != ERASED) {
+                return self.erase(sector);
+            }
+            at = at.saturating_add(chunk_len);
+        }
+        Ok(())
+    }
+
+    /// Generation in the mark of `sector`, `None` if it has no valid mark.
+    fn mark(&mut self, sector: Sector) -> Option<u32> {
+        let mut mark = [0_u8; MARK_LEN];
+        self.flash.read(sector.offset(), &mut mark).ok()?;
+        let [gen_0, gen_1, gen_2, gen_3, inv_0, inv_1, inv_2, inv_3] = mark;
+        let generation = u32::from_le_bytes([gen_0, gen_1, gen_2, gen_3]);
+        (generation == !u32::from_le_bytes([inv_0, inv_1, inv_2, inv_3])).then_some(generation)
+    }
+
+    /// Find the live sector, each page's newest record in it, and its first
+    /// free record, and erase whatever the other sector holds.
+    ///
+    /// With neither sector marked, the store is new: both are erased if
+    /// need be and the first is marked as generation 0. A record whose
+    /// header does not check out is skipped, and the first record that is
+    /// erased throughout ends the log.
+    fn mount(&mut self) {
+        let [mark_a, mark_b] = Sector::ALL.map(|sector| self.mark(sector));
+        let (live, generation) = match (mark_a, mark_b) {
+            (Some(gen_a), Some(gen_b)) if is_newer(gen_b, gen_a) => (Sector::B, gen_b),
+            (Some(gen_a), _) => (Sector::A, gen_a),
+            (None, Some(gen_b)) => (Sector::B, gen_b),
+            (None, None) => {
+                if self.erase_if_used(Sector::A).is_ok() && self.program_mark(Sector::A, 0).is_ok() {
+                    self.next = 0;
+                }
+                _ = self.erase_if_used(Sector::B);
+                return;
+            },
+        };
+        self.live = live;
+        self.generation = generation;
+        let mut record = [0_u8; RECORD_LEN];
+        for slot in 0..RECORDS {
+            if self.flash.read(record_offset(live, slot), &mut record).is_err() {
+                continue;
+            }
+            if record.iter().all(|&byte| byte == ERASED) {
+                self.next = slot;
+                break;
+            }
+            if let Some(page) = header_page(&record)
+                && let Some(newest) = self.newest.get_mut(usize::from(page))
+            {
+                *newest = slot;
+            }
+        }
+        _ = self.erase_if_used(live.other());
+    }
+
+    /// Open the store on `flash`, finding the live sector and each page's
+    /// newest record; `reload_watchdog` runs before each sector erase.
+    pub fn new(flash: F, reload_watchdog: fn()) -> Self {
+        let mut store =
+            Self { flash, generation: 0, live: Sector::A, newest: [NO_RECORD; PAGES], next: RECORDS, reload_watchdog };
+        store.mount();
+        store
+    }
+
+    /// Program the mark making `sector` live as `generation`.
+    fn program_mark(&mut self, sector: Sector, generation: u32) -> Result<(), FlashStoreError<F::Error>> {
+        let [gen_0, gen_1, gen_2, gen_3] = generation.to_le_bytes();
+        let [inv_0, inv_1, inv_2, inv_3] = (!generation).to_le_bytes();
+        self.flash
+            .write(sector.offset(), &[gen_0, gen_1, gen_2, gen_3, inv_0, inv_1, inv_2, inv_3])
+            .map_err(FlashStoreError::Flash)
+    }
+
+    /// Program `contents` as record `slot` of `sector`, holding `page`:
+    /// payload first, then the header that makes it count.
+    fn program_record(
+        &mut self,
+        sector: Sector,
+        slot: u16,
+        page: u16,
+        contents: &[u8; PAGE_SIZE],
+    ) -> Result<(), FlashStoreError<F::Error>> {
+        self.flash.write(payload_offset(sector, slot), contents).map_err(FlashStoreError::Flash)?;
+        let [page_lo, page_hi] = page.to_le_bytes();
+        let [inv_lo, inv_hi] = (!page).to_le_bytes();
+        self.flash
+            .write(record_offset(sector, slot), &[page_lo, page_hi, inv_lo, inv_hi, 0, 0, 0, 0])
+            .map_err(FlashStoreError::Flash)
+    }
+
+    /// Read the current contents of `page` into `contents`.
+    fn read_page(&mut self, page: usize, contents: &mut [u8; PAGE_SIZE]) -> Result<(), FlashStoreError<F::Error>> {
+        match self.newest.get(page) {
+            Some(&slot) if slot != NO_RECORD => {
+                self.flash.read(payload_offset(self.live, slot), contents).map_err(FlashStoreError::Flash)
+            },
+            _ => {
+                contents.fill(ERASED);
+                Ok(())
+            },
+        }
+    }
+
+    /// Merge `chunk` into `page` at byte `in_page` and append the result as
+    /// the page's newest record, compacting the log first if it is full.
+    fn write_page(&mut self, page: usize, in_page: usize, chunk: &[u8]) -> Result<(), FlashStoreError<F::Error>> {
+        if page >= PAGES {
+            return Err(FlashStoreError::OutOfRange);
+        }
+        let mut contents = [ERASED; PAGE_SIZE];
+        self.read_page(page, &mut contents)?;
+        let current = contents;
+        let Some(dst) = contents.get_mut(in_page..in_page.saturating_add(chunk.len())) else {
+            return Err(FlashStoreError::OutOfRange);
+        };
+        dst.copy_from_slice(chunk);
+        if contents == current {
+            return Ok(());
+        }
+        if self.next >= RECORDS {
+            self.compact()?;
+        }
+        self.append(u16::try_from(page).map_err(|_| FlashStoreError::OutOfRange)?, &contents)
+    }
+}
+
+impl<F: NorFlash> Store for FlashStore<F> {
+    type Error = FlashStoreError<F::Error>;
+
+    async fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let mut offset = 0_usize;
+        while offset < buf.len() {
+            let at = usize::from(addr).saturating_add(offset);
+            let in_page = at.rem_euclid(PAGE_SIZE);
+            let end = offset.saturating_add(PAGE_SIZE.saturating_sub(in_page)).min(buf.len());
+            let mut contents = [ERASED; PAGE_SIZE];
+            self.read_page(at.wrapping_div(PAGE_SIZE), &mut contents)?;
+            if let Some(dst) = buf.get_mut(offset..end)
+                && let Some(src) = contents.get(in_page..in_page.saturating_add(dst.len()))
+            {
+                dst.copy_from_slice(src);
+            }
+            offset = end;
+        }
+        Ok(())
+    }
+
+    async fn write_changed(&mut self, start_addr: u16, data: &[u8], old: &[u8]) -> Result<(), Self::Error> {
+        let mut offset = 0_usize;
+        while offset < data.len() {
+            let at = usize::from(start_addr).saturating_add(offset);
+            let in_page = at.rem_euclid(PAGE_SIZE);
+            let chunk_end = offset.saturating_add(PAGE_SIZE.saturating_sub(in_page)).min(data.len());
+            let chunk = data.get(offset..chunk_end).unwrap_or(&[]);
+            if old.get(offset..chunk_end) != Some(chunk) {
+                self.write_page(at.wrapping_div(PAGE_SIZE), in_page, chunk)?;
+            }
+            offset = chunk_end;
+        }
+        Ok(())
+    }
+}
+
+/// Page number of `record` if its header checks out.
+fn header_page(record: &[u8; RECORD_LEN]) -> Option<u16> {
+    let [page_lo, page_hi, inv_lo, inv_hi, pad_0, pad_1, pad_2, pad_3, ..] = *record;
+    let page = u16::from_le_bytes([page_lo, page_hi]);
+    let complete = [pad_0, pad_1, pad_2, pad_3] == [0; 4];
+    (complete && page == !u16::from_le_bytes([inv_lo, inv_hi]) && usize::from(page) < PAGES).then_some(page)
+}
+
+/// Flash offset of the page contents in record `slot` of `sector`.
+fn payload_offset(sector: Sector, slot: u16) -> u32 {
+    record_offset(sector, slot).saturating_add(u32::try_from(HEADER_LEN).unwrap_or(u32::MAX))
+}
+
+/// Flash offset of record `slot` of `sector`, after the sector's mark.
+fn record_offset(sector: Sector, slot: u16) -> u32 {
+    let record_len = u32::try_from(RECORD_LEN).unwrap_or(u32::MAX);
+    let first = sector.offset().saturating_add(u32::try_from(MARK_LEN).unwrap_or(u32::MAX));
+    first.saturating_add(u32::from(slot).saturating_mul(record_len))
+}
@@ -0,0 +1,212 @@
+//! Driver for the on-board FT24C64 EEPROM.
+//!
+//! Depends on nothing but the `embedded-hal` traits, so the host tests build
+//! it unchanged against an in-memory model of the chip.
+
+use super::Store;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::{
+    delay::DelayNs,
+    i2c::{I2c, Operation},
+};
+
+/// 7-bit I²C device address: base 0x50 with A0 wired high on this board
+/// (A1 = A2 = GND), giving 0x51.
+const DEVICE_ADDR: u8 = 0x51;
+/// Total size of the FT24C64 in bytes (64 Kbit).
+pub const EEPROM_SIZE: usize = 8192;
+/// Page write size in bytes per the FT24C64 datasheet.
+pub const PAGE_SIZE: usize = 32;
+/// Number of successive I²C acknowledgement polls during page writes.
+const READY_POLL_ATTEMPTS: u8 = 255;
+/// Delay between successive I²C acknowledgement polls, in microseconds.
+const READY_POLL_INTERVAL_US: u32 = 20;
+/// Number of successive I²C acknowledgement polls before a read, limited to a
+/// shorter budget to avoid stalling the keyboard for the full write-cycle
+/// window when the device is absent or malfunctioning.
+const READ_POLL_ATTEMPTS: u8 = 20;
+/// Attempts at a read or a page write before its error is returned.
+const RETRY_ATTEMPTS: u8 = 4;
+/// Wait before the first retry of a failed read or page write, in
+/// microseconds; each further retry waits twice as long, so a transfer gives
+/// up after waiting 1 + 2 + 4 ms.
+const RETRY_BACKOFF_US: u32 = 1_000;
+
+/// Failure of an [`Ft24c64`] access.
+#[derive(Debug)]
+pub enum Ft24c64Error<B, P> {
+    /// The device did not complete the I²C transfer.
+    Bus(B),
+    /// The write-protect pin did not switch.
+    Wp(P),
+}
+
+/// Driver for the FT24C64 64-Kbit (8 K × 8) I²C EEPROM.
+///
+/// Generic over the `embedded-hal` bus, pin, and delay traits rather than the
+/// STM32 peripherals, so it runs against any I²C bus and write-protect pin,
+/// including the in-memory model the host tests drive it with.
+pub struct Ft24c64<I2C, WP, D> {
+    /// Delay between acknowledgement polls and before retries.
+    delay: D,
+    /// I²C bus used for all device communication.
+    i2c:   I2C,
+    /// Write-protect pin, high when idle and low while writing.
+    wp:    WP,
+}
+
+impl<I2C: I2c, WP: OutputPin, D: DelayNs> Ft24c64<I2C, WP, D> {
+    /// Return the write-protect pin to its idle high state.
+    fn assert_wp(&mut self) -> Result<(), Ft24c64Error<I2C::Error, WP::Error>> {
+        self.wp.set_high().map_err(Ft24c64Error::Wp)
+    }
+
+    /// Wait out the backoff before retry number `retry` (counting from 0):
+    /// [`RETRY_BACKOFF_US`], doubled for every earlier retry.
+    async fn backoff(&mut self, retry: u8) {
+        self.delay.delay_us(RETRY_BACKOFF_US.wrapping_shl(u32::from(retry))).await;
+    }
+
+    /// Pull the write-protect pin low so the device accepts writes.
+    ///
+    /// Must be paired with a [`Ft24c64::assert_wp`] call before returning so
+    /// the pin returns to its idle high state.
+    fn deassert_wp(&mut self) -> Result<(), Ft24c64Error<I2C::Error, WP::Error>> {
+        self.wp.set_low().map_err(Ft24c64Error::Wp)
+    }
+
+    /// Create a new driver, write-protecting the device until the first
+    /// write.
+    ///
+    /// # Errors
+    ///
+    /// Returns the pin's error if `wp` cannot be raised.
+    pub fn new(i2c: I2C, mut wp: WP, delay: D) -> Result<Self, WP::Error> {
+        wp.set_high()?;
+        Ok(Self { delay, i2c, wp })
+    }
+
+    /// Poll the device with a zero-length write until it ACKs, indicating
+    /// the device is ready to accept commands. Used both after page writes
+    /// to wait for the internal write cycle to complete, and on first access
+    /// after power-on to wait out the reset delay.
+    ///
+    /// Retries up to `max_attempts` times with [`READY_POLL_INTERVAL_US`]
+    /// between each attempt. Returns `Ok(())` as soon as the device
+    /// acknowledges, or the bus error if it does not become ready within
+    /// `max_attempts`.
+    async fn poll_until_ready(&mut self, max_attempts: u8) -> Result<(), I2C::Error> {
+        let mut attempts = 0_u8;
+        loop {
+            let result = self.i2c.write(DEVICE_ADDR, &[]).await;
+            if result.is_ok() {
+                return Ok(());
+            }
+            attempts = attempts.saturating_add(1);
+            if attempts >= max_attempts {
+                return result;
+            }
+            self.delay.delay_us(READY_POLL_INTERVAL_US).await;
+        }
+    }
+
+    /// Read `buf.len()` bytes starting at 16-bit word address `addr`, once.
+    ///
+    /// Polls the device until it acknowledges before issuing the read,
+    /// accommodating the power-on reset delay without requiring a fixed
+    /// worst-case wait from the caller.
+    async fn read_once(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), I2C::Error> {
+        let ready = self.poll_until_ready(READ_POLL_ATTEMPTS).await;
+        if let Err(error) = ready {
+            return Err(error);
+        }
+        self.i2c.write_read(DEVICE_ADDR, &addr.to_be_bytes(), buf).await
+    }
+
+    /// Write one aligned page to the device without managing the write-protect
+    /// pin.
+    ///
+    /// Callers must deassert WP before calling and re-assert it afterward.
+    /// `chunk` must not cross a page boundary. Like a read, a
+    /// failed write is retried with [`Ft24c64::backoff`]; rewriting a page that
+    /// did land is harmless.
+    async fn write_page_raw(&mut self, addr: u16, chunk: &[u8]) -> Result<(), I2C::Error> {
+        let mut result = self.write_page_once(addr, chunk).await;
+        for retry in 0..RETRY_ATTEMPTS.saturating_sub(1) {
+            if result.is_ok() {
+                break;
+            }
+            self.backoff(retry).await;
+            result = self.write_page_once(addr, chunk).await;
+        }
+        result
+    }
+
+    /// Write one aligned page and wait out its write cycle, once.
+    async fn write_page_once(&mut self, addr: u16, chunk: &[u8]) -> Result<(), I2C::Error> {
+        let addr_bytes = addr.to_be_bytes();
+        let result =
+            self.i2c.transaction(DEVICE_ADDR, &mut [Operation::Write(&addr_bytes), Operation::Write(chunk)]).await;
+        if result.is_err() {
+            return result;
+        }
+        self.poll_until_ready(READY_POLL_ATTEMPTS).await
+    }
+}
+
+impl<I2C: I2c, WP: OutputPin, D: DelayNs> Store for Ft24c64<I2C, WP, D> {
+    type Error = Ft24c64Error<I2C::Error, WP::Error>;
+
+    /// A failed read is retried up to [`RETRY_ATTEMPTS`] times in all, with
+    /// [`Ft24c64::backoff`] between attempts, before its error is returned.
+    async fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let mut result = self.read_once(addr, buf).await;
+        for retry in 0..RETRY_ATTEMPTS.saturating_sub(1) {
+            if result.is_ok() {
+                break;
+            }
+            self.backoff(retry).await;
+            result = self.read_once(addr, buf).await;
+        }
+        result.map_err(Ft24c64Error::Bus)
+    }
+
+    /// A write-protect pin that cannot be lowered fails the write before
+    /// any page is sent. One that cannot be raised again afterwards fails
+    /// it too, even if every page landed, since the device is left
+    /// writable; a failed page write takes precedence.
+    async fn write_changed(&mut self, start_addr: u16, data: &[u8], old: &[u8]) -> Result<(), Self::Error> {
+        self.deassert_wp()?;
+
+        let mut offset = 0_usize;
+        let mut result = Ok(());
+
+        while offset < data.len() {
+            // Convert byte offset to an u16 address, saturating on overflow so
+            // we never silently wrap into a wrong EEPROM location.
+            let addr = start_addr.saturating_add(u16::try_from(offset).unwrap_or(u16::MAX));
+
+            // Bytes remaining before the next 32-byte page boundary.
+            let page_offset = usize::from(addr).rem_euclid(PAGE_SIZE);
+            let page_remaining = PAGE_SIZE.saturating_sub(page_offset);
+
+            // Bytes to write on this page, the smaller of the remaining page
+            // space and the remaining data.
+            let chunk_len = page_remaining.min(data.len().saturating_sub(offset));
+            let chunk_end = offset.saturating_add(chunk_len);
+            let chunk = data.get(offset..chunk_end).unwrap_or(&[]);
+
+            if old.get(offset..chunk_end) != Some(chunk) {
+                result = self.write_page_raw(addr, chunk).await.map_err(Ft24c64Error::Bus);
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            offset = chunk_end;
+        }
+
+        let protected = self.assert_wp();
+        result.and(protected)
+    }
+}
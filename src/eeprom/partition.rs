@@ -0,0 +1,276 @@
+//! EEPROM partition table.
+//!
+//! The EEPROM address space is split into fixed [`Region`]s, one per owner, so
+//! erasing or migrating one region never touches another:
+//!
+//! | Region               | Holds                                             |
+//! | -------------------- | ------------------------------------------------- |
+//! | [`Region::Calib`]    | calibration slot A, the tuning block, slot B      |
+//! | [`Region::Settings`] | the sensing settings record                       |
+//! | [`Region::Stats`]    | reserved for persistent statistics                |
+//! | [`Region::Keymap`]   | RMK's keymap storage (see [`super::storage`])     |
+//!
+//! Every record inside a region carries its own magic, version, and CRC-32,
+//! and RMK's storage checksums each of its items, so a region validates
+//! without help from its neighbours. The table itself sits in the EEPROM's
+//! first page, ahead of every region, and records each region's range and
+//! layout version. At boot a region whose entry differs from the built-in
+//! one is stale: its owner migrates or [`reset`]s it, and only it, before
+//! [`store_table`] records the new layout. RMK clears the keymap region;
+//! the matrix handles the others and then rewrites the table.
+//!
+//! Firmware without a table kept a single calibration block at address 0;
+//! its magic never matches the table's, so such a board finds every region
+//! stale and the matrix migrates the block before the table overwrites its
+//! first page.
+
+use crate::{
+    eeprom::{EEPROM_SIZE, PAGE_SIZE, Store},
+    matrix::{
+        calib_store::{Crc32, EEPROM_BASE_ADDR, SLOT_A_ADDR, crc32_of, read_array},
+        settings_store::{SETTINGS_BASE_ADDR, SETTINGS_BUF_LEN},
+    },
+};
+use core::mem::size_of;
+
+/// Byte length of the trailing CRC field.
+const CRC_LEN: usize = size_of::<u32>();
+/// EEPROM word address one past the EEPROM's last byte.
+const EEPROM_END: u16 = u16::try_from(EEPROM_SIZE).unwrap_or(u16::MAX);
+/// Byte length of a table entry: start address and length (2 B LE each),
+/// then the layout version.
+const ENTRY_LEN: usize = size_of::<u16>().saturating_mul(2).saturating_add(size_of::<u8>());
+/// Value of an erased EEPROM byte, which [`reset`] writes across a region.
+const ERASED: u8 = 0xFF;
+/// Byte length of the header: magic + version.
+const HEADER_LEN: usize = size_of::<u32>().saturating_add(size_of::<u8>());
+/// EEPROM word address of the keymap region: everything from the end of the
+/// statistics region to the end of the EEPROM.
+const KEYMAP_BASE_ADDR: u16 = STATS_BASE_ADDR.saturating_add(STATS_LEN);
+/// Magic number identifying a valid Q6 HE partition table.
+const MAGIC: u32 = 0x5136_5054;
+/// Byte length reserved for the settings record: two pages, leaving the
+/// record room to grow without moving its neighbours.
+const SETTINGS_LEN: u16 = 64;
+/// EEPROM word address of the statistics region, right after the settings
+/// region.
+const STATS_BASE_ADDR: u16 = SETTINGS_BASE_ADDR.saturating_add(SETTINGS_LEN);
+/// Byte length reserved for persistent statistics: five pages, sized so the
+/// keymap region that follows spans a whole number of storage sectors up to
+/// the end of the EEPROM.
+const STATS_LEN: u16 = 160;
+/// EEPROM word address of the partition table: the first page, where
+/// firmware without a table began its calibration block.
+const TABLE_ADDR: u16 = EEPROM_BASE_ADDR;
+/// Byte length of the partition table.
+const TABLE_LEN: usize = HEADER_LEN.saturating_add(ENTRY_LEN.saturating_mul(Region::ALL.len())).saturating_add(CRC_LEN);
+/// Format version of the table itself. Increment on any incompatible layout
+/// change; a mismatch marks every region stale.
+const VERSION: u8 = 1;
+
+const _: () = assert!(TABLE_LEN <= PAGE_SIZE, "partition table does not fit in one page");
+const _: () = assert!(SETTINGS_BUF_LEN <= usize::from(SETTINGS_LEN), "settings record does not fit in its region");
+const _: () = assert!(
+    usize::from(TABLE_ADDR).saturating_add(PAGE_SIZE) <= usize::from(SLOT_A_ADDR),
+    "partition table overlaps the calibration region"
+);
+const _: () = assert!(KEYMAP_BASE_ADDR < EEPROM_END, "keymap region is empty");
+
+/// A fixed range of the EEPROM owned by one part of the firmware.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// Both calibration slots and the tuning block between them.
+    Calib,
+    /// RMK's keymap, encoder map, and behavior config storage.
+    Keymap,
+    /// The sensing settings record.
+    Settings,
+    /// Persistent statistics; reserved, and only ever reset so the first
+    /// record written there starts from erased pages.
+    Stats,
+}
+
+impl Region {
+    /// Every region, in table order.
+    pub const ALL: [Self; 4] = [Self::Calib, Self::Settings, Self::Stats, Self::Keymap];
+
+    /// EEPROM word address one past the region's last byte.
+    #[must_use]
+    pub const fn end(self) -> u16 {
+        match self {
+            Self::Calib => SETTINGS_BASE_ADDR,
+            Self::Keymap => EEPROM_END,
+            Self::Settings => STATS_BASE_ADDR,
+            Self::Stats => KEYMAP_BASE_ADDR,
+        }
+    }
+
+    /// Table entry describing the region (see [`ENTRY_LEN`]).
+    const fn entry(self) -> [u8; ENTRY_LEN] {
+        let [start_lo, start_hi] = self.start().to_le_bytes();
+        let [len_lo, len_hi] = self.len().to_le_bytes();
+        [start_lo, start_hi, len_lo, len_hi, self.version()]
+    }
+
+    /// Byte length of the region.
+    #[must_use]
+    pub const fn len(self) -> u16 { self.end().saturating_sub(self.start()) }
+
+    /// EEPROM word address of the region's first byte.
+    #[must_use]
+    pub const fn start(self) -> u16 {
+        match self {
+            Self::Calib => SLOT_A_ADDR,
+            Self::Keymap => KEYMAP_BASE_ADDR,
+            Self::Settings => SETTINGS_BASE_ADDR,
+            Self::Stats => STATS_BASE_ADDR,
+        }
+    }
+
+    /// Layout version of the region. Increment when the region's contents
+    /// can no longer be read in place; like a moved range, a changed version
+    /// marks the region stale.
+    const fn version(self) -> u8 {
+        match self {
+            // The second layout: two slots around the tuning block, after the
+            // single calibration block of firmware without a table.
+            Self::Calib => 2,
+            Self::Keymap | Self::Settings | Self::Stats => 1,
+        }
+    }
+}
+
+/// Set of regions whose stored table entry differs from the built-in one.
+#[derive(Clone, Copy)]
+pub struct StaleRegions {
+    /// One bit per region, by position in [`Region::ALL`].
+    bits: u8,
+}
+
+impl StaleRegions {
+    /// Every region is stale.
+    const ALL: Self = Self { bits: 0b1111 };
+    /// No region is stale.
+    const NONE: Self = Self { bits: 0 };
+
+    /// Bit of `region` in [`StaleRegions::bits`].
+    const fn bit(region: Region) -> u8 {
+        match region {
+            Region::Calib => 0b0001,
+            Region::Keymap => 0b1000,
+            Region::Settings => 0b0010,
+            Region::Stats => 0b0100,
+        }
+    }
+
+    /// Whether `region` is stale.
+    #[must_use]
+    pub const fn contains(self, region: Region) -> bool { self.bits & Self::bit(region) != 0 }
+
+    /// Whether no region is stale, so the stored table needs no rewrite.
+    #[must_use]
+    pub const fn is_empty(self) -> bool { self.bits == 0 }
+}
+
+/// Serialize the built-in partition table into `buf`.
+///
+/// Format: magic (4 B LE) | version (1 B) | one entry per region in
+/// [`Region::ALL`] order (see [`ENTRY_LEN`]) | CRC-32 (4 B LE), mirroring
+/// the blocks the regions hold.
+fn serialize(buf: &mut [u8; TABLE_LEN], crc: &mut dyn Crc32) {
+    if let Some(dst) = buf.get_mut(0..size_of::<u32>()) {
+        dst.copy_from_slice(&MAGIC.to_le_bytes());
+    }
+    if let Some(version_byte) = buf.get_mut(size_of::<u32>()) {
+        *version_byte = VERSION;
+    }
+    let mut offset = HEADER_LEN;
+    for region in Region::ALL {
+        let end = offset.saturating_add(ENTRY_LEN);
+        if let Some(dst) = buf.get_mut(offset..end) {
+            dst.copy_from_slice(&region.entry());
+        }
+        offset = end;
+    }
+    let checksum = buf.get(..offset).map_or(0, |data| crc32_of(crc, data));
+    if let Some(dst) = buf.get_mut(offset..TABLE_LEN) {
+        dst.copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+/// Read the stored partition table and compare it with the built-in one.
+///
+/// A missing or corrupt table, as on a fresh board or one updated from
+/// firmware without a table, marks every region stale. If the EEPROM cannot
+/// be read at all nothing is marked stale, so no owner resets a region it
+/// merely failed to reach.
+///
+/// Call once at boot, before any owner touches its region.
+pub async fn stale_regions<S: Store>(eeprom: &mut S, crc: &mut dyn Crc32) -> StaleRegions {
+    let mut buf = [0_u8; TABLE_LEN];
+    if eeprom.read(TABLE_ADDR, &mut buf).await.is_err() {
+        return StaleRegions::NONE;
+    }
+    let Some(magic_bytes) = read_array::<4>(&buf, 0, size_of::<u32>()) else {
+        return StaleRegions::ALL;
+    };
+    let data_end = TABLE_LEN.saturating_sub(CRC_LEN);
+    let stored_crc = read_array::<4>(&buf, data_end, TABLE_LEN).map(u32::from_le_bytes);
+    let computed_crc = buf.get(..data_end).map(|data| crc32_of(crc, data));
+    if u32::from_le_bytes(magic_bytes) != MAGIC
+        || buf.get(size_of::<u32>()) != Some(&VERSION)
+        || stored_crc != computed_crc
+    {
+        return StaleRegions::ALL;
+    }
+    let mut stale = StaleRegions::NONE;
+    let mut offset = HEADER_LEN;
+    for region in Region::ALL {
+        let end = offset.saturating_add(ENTRY_LEN);
+        if buf.get(offset..end) != Some(region.entry().as_slice()) {
+            stale.bits |= StaleRegions::bit(region);
+        }
+        offset = end;
+    }
+    stale
+}
+
+/// Erase `region`, leaving every byte [`ERASED`], verifying by read-back.
+///
+/// Only the pages not already erased are written, so resetting a region
+/// that holds nothing costs no write cycles. Returns `false` on the first
+/// page that fails, leaving the rest of the region as it was.
+pub async fn reset<S: Store>(eeprom: &mut S, region: Region) -> bool {
+    let erased = [ERASED; PAGE_SIZE];
+    let mut page = [0_u8; PAGE_SIZE];
+    let mut addr = region.start();
+    while addr < region.end() {
+        let len = usize::from(region.end().saturating_sub(addr)).min(PAGE_SIZE);
+        let (Some(data), Some(old)) = (erased.get(..len), page.get_mut(..len)) else {
+            return false;
+        };
+        if eeprom.read(addr, old).await.is_err()
+            || eeprom.write_changed(addr, data, old).await.is_err()
+            || eeprom.read(addr, old).await.is_err()
+            || old != data
+        {
+            return false;
+        }
+        addr = addr.saturating_add(u16::try_from(len).unwrap_or(u16::MAX));
+    }
+    true
+}
+
+/// Write the built-in partition table, verifying by read-back.
+///
+/// Call only once the owner of every stale region has migrated or reset it,
+/// so a power loss in between leaves the region stale for the next boot to
+/// handle again.
+pub async fn store_table<S: Store>(eeprom: &mut S, crc: &mut dyn Crc32) -> bool {
+    let mut buf = [0_u8; TABLE_LEN];
+    serialize(&mut buf, crc);
+    let mut readback = [0_u8; TABLE_LEN];
+    eeprom.write(TABLE_ADDR, &buf).await.is_ok()
+        && eeprom.read(TABLE_ADDR, &mut readback).await.is_ok()
+        && readback == buf
+}
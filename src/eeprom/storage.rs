@@ -0,0 +1,119 @@
+//! RMK storage backend on the EEPROM address space.
+//!
+//! RMK keeps the keymap, encoder map, and behavior config in a
+//! `sequential-storage` map on top of an `embedded-storage-async` NOR flash.
+//! [`EepromFlash`] presents the [`Region::Keymap`] partition as such a
+//! flash: an erase writes `0xFF`, and reads and writes go straight to the
+//! [`Store`], which unlike NOR flash could even rewrite a byte in place.
+//!
+//! The store is shared with the matrix scanner through [`SharedStore`];
+//! every operation locks it only for its own duration, so RMK storage and a
+//! calibration write simply take turns.
+
+use crate::eeprom::{PAGE_SIZE, SharedStore, Store, partition::Region};
+use core::fmt::Debug;
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+/// Bytes every erased sector reads back as.
+const ERASED: [u8; SECTOR_SIZE] = [0xFF; SECTOR_SIZE];
+/// EEPROM word address of the keymap storage partition.
+const KEYMAP_BASE_ADDR: u16 = Region::Keymap.start();
+/// Number of whole sectors in the [`Region::Keymap`] partition, all handed
+/// to RMK.
+pub const KEYMAP_SECTORS: usize = usize::from(Region::Keymap.len()).wrapping_div(SECTOR_SIZE);
+/// Erase size presented to RMK: sixteen EEPROM pages, comfortably above
+/// RMK's largest record while leaving the partition enough sectors for
+/// `sequential-storage` to garbage-collect into.
+const SECTOR_SIZE: usize = PAGE_SIZE.saturating_mul(16);
+
+const _: () = assert!(KEYMAP_SECTORS >= 2, "keymap storage needs at least two sectors");
+
+/// The keymap storage partition of the EEPROM, seen as NOR flash; offsets
+/// are relative to [`KEYMAP_BASE_ADDR`].
+pub struct EepromFlash<'bus, S> {
+    /// Store shared with the matrix scanner.
+    eeprom: &'bus SharedStore<S>,
+}
+
+impl<'bus, S: Store> EepromFlash<'bus, S> {
+    /// EEPROM address of `len` bytes at partition `offset`, or
+    /// [`FlashError::OutOfBounds`] if they do not lie inside the partition.
+    fn addr(offset: u32, len: usize) -> Result<u16, FlashError<S::Error>> {
+        let start = usize::try_from(offset).map_err(|_| FlashError::OutOfBounds)?;
+        if start.saturating_add(len) > KEYMAP_SECTORS.saturating_mul(SECTOR_SIZE) {
+            return Err(FlashError::OutOfBounds);
+        }
+        u16::try_from(start).map(|start| KEYMAP_BASE_ADDR.saturating_add(start)).map_err(|_| FlashError::OutOfBounds)
+    }
+
+    /// Storage on the keymap partition of `eeprom`.
+    pub const fn new(eeprom: &'bus SharedStore<S>) -> Self { Self { eeprom } }
+}
+
+impl<S: Store> ErrorType for EepromFlash<'_, S> {
+    type Error = FlashError<S::Error>;
+}
+
+impl<S: Store> NorFlash for EepromFlash<'_, S> {
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+    const WRITE_SIZE: usize = 1;
+
+    /// Erase the sectors in `from..to` to `0xFF`, rewriting only the pages
+    /// that are not erased already.
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let len = usize::try_from(to.saturating_sub(from)).map_err(|_| FlashError::OutOfBounds)?;
+        let start = Self::addr(from, len)?;
+        if usize::from(start.saturating_sub(KEYMAP_BASE_ADDR)).rem_euclid(SECTOR_SIZE) != 0
+            || len.rem_euclid(SECTOR_SIZE) != 0
+        {
+            return Err(FlashError::NotAligned);
+        }
+        let mut sector_buf = [0_u8; SECTOR_SIZE];
+        let mut eeprom = self.eeprom.lock().await;
+        for sector in 0..len.wrapping_div(SECTOR_SIZE) {
+            let offset = u16::try_from(sector.saturating_mul(SECTOR_SIZE)).map_err(|_| FlashError::OutOfBounds)?;
+            let addr = start.saturating_add(offset);
+            // A sector that cannot be read is rewritten in full.
+            let old: &[u8] = if eeprom.read(addr, &mut sector_buf).await.is_ok() { &sector_buf } else { &[] };
+            eeprom.write_changed(addr, &ERASED, old).await.map_err(FlashError::Bus)?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let addr = Self::addr(offset, bytes.len())?;
+        self.eeprom.lock().await.write(addr, bytes).await.map_err(FlashError::Bus)
+    }
+}
+
+impl<S: Store> ReadNorFlash for EepromFlash<'_, S> {
+    const READ_SIZE: usize = 1;
+
+    fn capacity(&self) -> usize { KEYMAP_SECTORS.saturating_mul(SECTOR_SIZE) }
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let addr = Self::addr(offset, bytes.len())?;
+        self.eeprom.lock().await.read(addr, bytes).await.map_err(FlashError::Bus)
+    }
+}
+
+/// Failure of an [`EepromFlash`] operation.
+#[derive(Debug)]
+pub enum FlashError<E> {
+    /// The store did not complete the transfer.
+    Bus(E),
+    /// An erase did not cover whole sectors.
+    NotAligned,
+    /// The range does not lie inside the partition.
+    OutOfBounds,
+}
+
+impl<E: Debug> NorFlashError for FlashError<E> {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::Bus(_) => NorFlashErrorKind::Other,
+            Self::NotAligned => NorFlashErrorKind::NotAligned,
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Storage abstraction shared by the EEPROM and its internal-flash fallback.
+
+use core::fmt::Debug;
+
+/// Persistent storage laid out like the FT24C64:
+/// [`EEPROM_SIZE`](super::EEPROM_SIZE) bytes at 16-bit word addresses,
+/// written in [`PAGE_SIZE`](super::PAGE_SIZE)-byte pages. Everything that
+/// persists goes through it, so it never matters which device holds the
+/// bytes.
+pub trait Store {
+    /// Failure of an access.
+    type Error: Debug;
+
+    /// Read `buf.len()` bytes starting at 16-bit word address `addr`.
+    async fn read(&mut self, addr: u16, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write `data` starting at 16-bit word address `start_addr`.
+    async fn write(&mut self, start_addr: u16, data: &[u8]) -> Result<(), Self::Error> {
+        self.write_changed(start_addr, data, &[]).await
+    }
+
+    /// Write `data` starting at 16-bit word address `start_addr`, skipping
+    /// every page whose bytes already match `old`, the store's current
+    /// contents of the same range.
+    ///
+    /// Each page write costs one of the device's limited write cycles, so a
+    /// caller that knows what the range holds rewrites only what differs.
+    /// Pages `old` does not cover (all of them for an empty slice) are always
+    /// written.
+    async fn write_changed(&mut self, start_addr: u16, data: &[u8], old: &[u8]) -> Result<(), Self::Error>;
+}
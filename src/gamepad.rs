@@ -0,0 +1,286 @@
+//! Analog gamepad driven by key travel.
+//!
+//! Keys bound to a [`GamepadInput`] feed their travel into the stick axes and
+//! triggers of a USB HID gamepad. The matrix scanner stores each bound key's
+//! travel in [`GAMEPAD_TRAVEL`] whenever it changes; [`GamepadTask`] samples
+//! those values once per [`REPORT_INTERVAL`] while a gamepad layer (see
+//! [`GAMEPAD_LAYERS`]) is active and sends a report whenever it changes.
+//!
+//! A stick axis with a key bound in each direction follows the deeper of the
+//! two, so W/A/S/D on the left stick behave like "deeper press wins" SOCD.
+//! The keys keep their keymap actions, so a gamepad layer normally maps them
+//! to `No` to avoid typing while playing.
+//!
+//! The transport is the gamepad interface of RMK's USB transport: main
+//! declares it with `UsbTransport::with_gamepad` and [`REPORT_DESCRIPTOR`],
+//! and `GamepadReportEvent` carries one report to it. Only this module and
+//! that constructor call depend on them.
+
+use crate::matrix::analog_matrix::types::FULL_TRAVEL_FINE;
+use core::{
+    future::pending,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::{Duration, Ticker};
+use rmk::{
+    core_traits::Runnable,
+    embassy_futures::select::{Either3, select3},
+    event::{EventSubscriber as _, GamepadReportEvent, LayerChangeEvent, SubscribableEvent, publish_event_async},
+};
+
+/// Number of bindable gamepad inputs; the length of [`GamepadInput::ALL`].
+pub const GAMEPAD_INPUTS: usize = 10;
+
+/// Receiver count for [`GAMEPAD_LAYERS`] (gamepad task only).
+const GAMEPAD_LAYERS_RECEIVERS: usize = 1;
+
+/// Byte length of one gamepad report: four stick axes, two triggers.
+pub const GAMEPAD_REPORT_LEN: usize = 6;
+
+/// Interval between report samples while a gamepad layer is active; matches
+/// the full-speed USB interrupt polling interval.
+const REPORT_INTERVAL: Duration = Duration::from_millis(1);
+
+/// HID report descriptor of the gamepad interface: X, Y, Rx, Ry as signed
+/// stick axes and Z, Rz as unsigned triggers, one byte each, in the order of
+/// [`GamepadReport::to_bytes`].
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Gamepad)
+    0xA1, 0x01, // Collection (Application)
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x09, 0x33, //   Usage (Rx)
+    0x09, 0x34, //   Usage (Ry)
+    0x15, 0x81, //   Logical Minimum (-127)
+    0x25, 0x7F, //   Logical Maximum (127)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x04, //   Report Count (4)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x09, 0x32, //   Usage (Z)
+    0x09, 0x35, //   Usage (Rz)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x02, //   Report Count (2)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xC0, // End Collection
+];
+
+/// Latest travel of the key bound to each [`GamepadInput`], in fine travel
+/// units, indexed by [`GamepadInput::index`].
+///
+/// Plain atomics rather than a channel: the scanner stores one byte per
+/// travel change of a bound key, which must stay as cheap as the rest of the
+/// hot path, and the task only ever needs the latest value.
+pub static GAMEPAD_TRAVEL: [AtomicU8; GAMEPAD_INPUTS] = [const { AtomicU8::new(0) }; GAMEPAD_INPUTS];
+
+/// Bit mask of the layers on which the gamepad is active (bit `n` = layer
+/// `n`), sent by the matrix scanner whenever the setting is loaded or edited.
+pub static GAMEPAD_LAYERS: Watch<CriticalSectionRawMutex, u8, GAMEPAD_LAYERS_RECEIVERS> = Watch::new();
+
+/// A gamepad control a key can be bound to.
+#[derive(Clone, Copy)]
+pub enum GamepadInput {
+    /// Left stick, positive Y.
+    LeftStickDown,
+    /// Left stick, negative X.
+    LeftStickLeft,
+    /// Left stick, positive X.
+    LeftStickRight,
+    /// Left stick, negative Y.
+    LeftStickUp,
+    /// Left trigger (Z).
+    LeftTrigger,
+    /// Right stick, positive Ry.
+    RightStickDown,
+    /// Right stick, negative Rx.
+    RightStickLeft,
+    /// Right stick, positive Rx.
+    RightStickRight,
+    /// Right stick, negative Ry.
+    RightStickUp,
+    /// Right trigger (Rz).
+    RightTrigger,
+}
+
+impl GamepadInput {
+    /// Every input in [`GamepadInput::index`] order.
+    pub const ALL: [Self; GAMEPAD_INPUTS] = [
+        Self::LeftStickUp,
+        Self::LeftStickDown,
+        Self::LeftStickLeft,
+        Self::LeftStickRight,
+        Self::RightStickUp,
+        Self::RightStickDown,
+        Self::RightStickLeft,
+        Self::RightStickRight,
+        Self::LeftTrigger,
+        Self::RightTrigger,
+    ];
+
+    /// Decode an index produced by [`GamepadInput::index`].
+    #[must_use]
+    pub const fn from_index(index: u8) -> Option<Self> {
+        match Self::ALL.get(usize::from(index)) {
+            Some(&input) => Some(input),
+            None => None,
+        }
+    }
+
+    /// Position of this input in [`GamepadInput::ALL`]; also its wire code
+    /// and its slot in [`GAMEPAD_TRAVEL`].
+    #[must_use]
+    pub const fn index(self) -> u8 {
+        match self {
+            Self::LeftStickUp => 0,
+            Self::LeftStickDown => 1,
+            Self::LeftStickLeft => 2,
+            Self::LeftStickRight => 3,
+            Self::RightStickUp => 4,
+            Self::RightStickDown => 5,
+            Self::RightStickLeft => 6,
+            Self::RightStickRight => 7,
+            Self::LeftTrigger => 8,
+            Self::RightTrigger => 9,
+        }
+    }
+
+    /// Record `travel` for this input; called by the scanner on every travel
+    /// change of the bound key.
+    pub fn set_travel(self, travel: u8) {
+        if let Some(slot) = GAMEPAD_TRAVEL.get(usize::from(self.index())) {
+            slot.store(travel, Ordering::Relaxed);
+        }
+    }
+
+    /// Latest travel recorded for this input.
+    fn travel(self) -> u8 {
+        GAMEPAD_TRAVEL.get(usize::from(self.index())).map_or(0, |slot| slot.load(Ordering::Relaxed))
+    }
+}
+
+/// One gamepad input report.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct GamepadReport {
+    /// Left trigger.
+    left_trigger:  u8,
+    /// Left stick X.
+    left_x:        i8,
+    /// Left stick Y.
+    left_y:        i8,
+    /// Right trigger.
+    right_trigger: u8,
+    /// Right stick X.
+    right_x:       i8,
+    /// Right stick Y.
+    right_y:       i8,
+}
+
+impl GamepadReport {
+    /// Build a report from the latest travel of every input.
+    fn sample() -> Self {
+        Self {
+            left_trigger:  trigger(GamepadInput::LeftTrigger.travel()),
+            left_x:        axis(GamepadInput::LeftStickLeft.travel(), GamepadInput::LeftStickRight.travel()),
+            left_y:        axis(GamepadInput::LeftStickUp.travel(), GamepadInput::LeftStickDown.travel()),
+            right_trigger: trigger(GamepadInput::RightTrigger.travel()),
+            right_x:       axis(GamepadInput::RightStickLeft.travel(), GamepadInput::RightStickRight.travel()),
+            right_y:       axis(GamepadInput::RightStickUp.travel(), GamepadInput::RightStickDown.travel()),
+        }
+    }
+
+    /// Encode in the field order of [`REPORT_DESCRIPTOR`].
+    fn to_bytes(self) -> [u8; GAMEPAD_REPORT_LEN] {
+        [
+            self.left_x.cast_unsigned(),
+            self.left_y.cast_unsigned(),
+            self.right_x.cast_unsigned(),
+            self.right_y.cast_unsigned(),
+            self.left_trigger,
+            self.right_trigger,
+        ]
+    }
+}
+
+/// Stick axis value from the travel of its negative and positive keys: the
+/// deeper key wins, a tie centres the axis.
+fn axis(negative: u8, positive: u8) -> i8 {
+    let scale = |travel: u8| {
+        let scaled = u16::from(travel.min(FULL_TRAVEL_FINE))
+            .saturating_mul(u16::from(i8::MAX.unsigned_abs()))
+            .checked_div(u16::from(FULL_TRAVEL_FINE))
+            .unwrap_or(0);
+        i8::try_from(scaled).unwrap_or(i8::MAX)
+    };
+    match positive.cmp(&negative) {
+        core::cmp::Ordering::Greater => scale(positive),
+        core::cmp::Ordering::Less => scale(negative).saturating_neg(),
+        core::cmp::Ordering::Equal => 0,
+    }
+}
+
+/// Whether `layer` is one of the gamepad layers in `mask`.
+fn layer_active(layer: u8, mask: u8) -> bool { 1_u8.checked_shl(u32::from(layer)).is_some_and(|bit| mask & bit != 0) }
+
+/// Trigger value from its key's travel, full travel mapping to `u8::MAX`.
+fn trigger(travel: u8) -> u8 {
+    let scaled = u16::from(travel.min(FULL_TRAVEL_FINE))
+        .saturating_mul(u16::from(u8::MAX))
+        .checked_div(u16::from(FULL_TRAVEL_FINE))
+        .unwrap_or(0);
+    u8::try_from(scaled).unwrap_or(u8::MAX)
+}
+
+/// Publishes gamepad reports while a gamepad layer is active, and one
+/// centred report when the gamepad is left.
+///
+/// Hand to `run_all!`; construct before it so no layer change is missed.
+pub struct GamepadTask {
+    /// Subscription to active-layer changes.
+    sub: <LayerChangeEvent as SubscribableEvent>::Subscriber,
+}
+
+impl GamepadTask {
+    /// Subscribe immediately so no layer change is missed.
+    #[must_use]
+    pub fn new() -> Self { Self { sub: LayerChangeEvent::subscriber() } }
+}
+
+impl Default for GamepadTask {
+    fn default() -> Self { Self::new() }
+}
+
+impl Runnable for GamepadTask {
+    async fn run(&mut self) -> ! {
+        let Some(mut layers) = GAMEPAD_LAYERS.receiver() else {
+            loop {
+                pending::<()>().await;
+            }
+        };
+        let mut layer = 0_u8;
+        let mut mask = 0_u8;
+        let mut last = GamepadReport::default();
+        let mut ticker = Ticker::every(REPORT_INTERVAL);
+        loop {
+            let tick = async {
+                if layer_active(layer, mask) {
+                    ticker.next().await;
+                } else {
+                    pending::<()>().await;
+                }
+            };
+            match select3(self.sub.next_event(), layers.changed(), tick).await {
+                Either3::First(LayerChangeEvent(new_layer)) => layer = new_layer,
+                Either3::Second(new_mask) => mask = new_mask,
+                Either3::Third(()) => {},
+            }
+            let report = if layer_active(layer, mask) { GamepadReport::sample() } else { GamepadReport::default() };
+            if report != last {
+                last = report;
+                publish_event_async(GamepadReportEvent(report.to_bytes())).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,697 @@
+//! Host configuration protocol for the hall-effect matrix, carried over rynk
+//! vendor packets.
+//!
+//! `HostLink` subscribes to rynk's vendor-packet event, decodes each packet
+//! into a [`MatrixCmd`] for the matrix scanner (via [`MATRIX_CMD`]), and
+//! publishes the scanner's [`HostReply`] values (via [`HOST_REPLY`]) back to
+//! the host. The scanner owns the tuning table and the EEPROM, so every
+//! command is executed there, between scan passes.
+//!
+//! Wire format: every packet is [`HOST_PACKET_LEN`] bytes, zero-padded. Byte 0
+//! is the command id; replies echo it in byte 0 and carry a [`HostStatus`] in
+//! byte 1, followed by any payload. While the travel stream runs, the
+//! scanner also sends unsolicited [`HostReply::KeySamples`] packets (via
+//! [`TRAVEL_STREAM`]).
+//!
+//! The transport is rynk's vendor-packet pair: `RynkVendorEvent` carries a raw
+//! packet in from the host and `RynkVendorReply` carries one back out. Only
+//! this module depends on them.
+
+use crate::{
+    gamepad::GamepadInput,
+    layout::{COL, ROW},
+    matrix::{
+        analog_matrix::{
+            HallCfg,
+            dks::DksMap,
+            health::KeyHealth,
+            socd::SocdPair,
+            stats::ScanReport,
+            stream::KeySample,
+            types::{KeyTuning, RtMode, SwitchProfile},
+        },
+        layer_toggle::MatrixPos,
+        settings_store::{self, decode_settings, encode_settings},
+        tuning_store::{ENTRY_LEN, decode_entry, encode_entry},
+    },
+    mouse::{MouseInput, MouseSettings},
+};
+use core::mem::size_of;
+use rmk::{
+    channel::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel},
+    core_traits::Runnable,
+    embassy_futures::select::{Either3, select3},
+    event::{EventSubscriber as _, RynkVendorEvent, RynkVendorReply, SubscribableEvent, publish_event_async},
+};
+
+/// Command id: read one key's tuning. Payload: row, col.
+const CMD_GET_KEY_TUNING: u8 = 0x01;
+/// Command id: replace one key's tuning in RAM. Payload: row, col, tuning
+/// entry.
+const CMD_SET_KEY_TUNING: u8 = 0x02;
+/// Command id: persist the whole tuning table to EEPROM. No payload.
+const CMD_SAVE_TUNING: u8 = 0x03;
+/// Command id: read the board-wide rapid-trigger mode. No payload.
+const CMD_GET_RT_MODE: u8 = 0x04;
+/// Command id: replace the board-wide rapid-trigger mode in RAM. Payload:
+/// [`RtMode::code`].
+const CMD_SET_RT_MODE: u8 = 0x05;
+/// Command id: read one Dynamic Keystroke slot's bindings. Payload: slot.
+const CMD_GET_DKS_SLOT: u8 = 0x06;
+/// Command id: replace one Dynamic Keystroke slot's bindings in RAM. Payload:
+/// slot, [`DksMap`] (4 B LE).
+const CMD_SET_DKS_SLOT: u8 = 0x07;
+/// Command id: read one SOCD pair slot. Payload: slot.
+const CMD_GET_SOCD_PAIR: u8 = 0x08;
+/// Command id: replace one SOCD pair slot in RAM. Payload: slot, encoded
+/// [`SocdPair`] (mode `0` clears the slot).
+const CMD_SET_SOCD_PAIR: u8 = 0x09;
+/// Command id: read the key bound to one gamepad input. Payload:
+/// [`GamepadInput::index`].
+const CMD_GET_GAMEPAD_INPUT: u8 = 0x0A;
+/// Command id: bind a key to one gamepad input in RAM. Payload:
+/// [`GamepadInput::index`], row, col (row `0xFF` unbinds the input).
+const CMD_SET_GAMEPAD_INPUT: u8 = 0x0B;
+/// Command id: read the gamepad layer mask. No payload.
+const CMD_GET_GAMEPAD_LAYERS: u8 = 0x0C;
+/// Command id: replace the gamepad layer mask in RAM. Payload: mask, bit `n`
+/// for layer `n`.
+const CMD_SET_GAMEPAD_LAYERS: u8 = 0x0D;
+/// Command id: read the key bound to one analog mouse input. Payload:
+/// [`MouseInput::index`].
+const CMD_GET_MOUSE_INPUT: u8 = 0x0E;
+/// Command id: bind a key to one analog mouse input in RAM. Payload:
+/// [`MouseInput::index`], row, col (row `0xFF` unbinds the input).
+const CMD_SET_MOUSE_INPUT: u8 = 0x0F;
+/// Command id: read the analog mouse settings. No payload.
+const CMD_GET_MOUSE_SETTINGS: u8 = 0x10;
+/// Command id: replace the analog mouse settings in RAM. Payload: encoded
+/// [`MouseSettings`].
+const CMD_SET_MOUSE_SETTINGS: u8 = 0x11;
+/// Command id: read the travel stream interval. No payload.
+const CMD_GET_TRAVEL_STREAM: u8 = 0x12;
+/// Command id: start (or, with `0`, stop) the travel stream. Payload: interval
+/// in milliseconds.
+const CMD_SET_TRAVEL_STREAM: u8 = 0x13;
+/// Packet id of unsolicited travel stream packets. Payload: sample count (1
+/// or 2), then that many encoded [`KeySample`]s.
+const CMD_TRAVEL_SAMPLES: u8 = 0x14;
+/// Command id: read one key's switch profile. Payload: row, col.
+const CMD_GET_KEY_PROFILE: u8 = 0x15;
+/// Command id: replace one key's switch profile in RAM. Payload: row, col,
+/// [`SwitchProfile::code`].
+const CMD_SET_KEY_PROFILE: u8 = 0x16;
+/// Command id: read one key's measured resting noise and derived noise gate.
+/// Payload: row, col.
+const CMD_GET_KEY_NOISE: u8 = 0x17;
+/// Command id: read the scan-rate and latency statistics. Payload: `1` to
+/// clear the timing figures after reading, `0` to keep them.
+const CMD_GET_SCAN_STATS: u8 = 0x18;
+/// Command id: read one key's sensor health. Payload: row, col.
+const CMD_GET_KEY_HEALTH: u8 = 0x19;
+/// Command id: show (`1`) or hide (`0`) the keys with a sensor fault on the
+/// backlight. Payload: on/off.
+const CMD_SET_HEALTH_DISPLAY: u8 = 0x1A;
+/// Command id: discard the stored calibration and rerun the guided
+/// calibration. No payload; the reply follows once it completes.
+const CMD_RECALIBRATE: u8 = 0x1B;
+/// Command id: recalibrate one key after a switch swap and persist it.
+/// Payload: row, col; the reply follows once it completes.
+const CMD_RECALIBRATE_KEY: u8 = 0x1C;
+/// Command id: read the sensing settings. No payload.
+const CMD_GET_SETTINGS: u8 = 0x1D;
+/// Command id: replace the sensing settings in RAM. Payload: encoded
+/// settings (see [`encode_settings`]).
+const CMD_SET_SETTINGS: u8 = 0x1E;
+/// Capacity of [`HOST_REPLY`]; replies are sent with `try_send`, so this only
+/// needs to cover a short burst while `HostLink` is busy publishing.
+const HOST_REPLY_CAPACITY: usize = 8;
+/// Fixed length of every rynk vendor packet, in bytes.
+pub const HOST_PACKET_LEN: usize = 32;
+/// Capacity of [`TRAVEL_STREAM`]: the most packets, two keys each, one
+/// stream interval may queue.
+const TRAVEL_STREAM_CAPACITY: usize = 8;
+/// Capacity of [`MATRIX_CMD`]. Commands wait here while the scanner is
+/// calibrating or the host is suspended; a full queue is reported to the host
+/// as [`HostStatus::Busy`] rather than blocking `HostLink`.
+const MATRIX_CMD_CAPACITY: usize = 4;
+
+/// Host commands awaiting the matrix scanner.
+pub static MATRIX_CMD: Channel<CriticalSectionRawMutex, MatrixCmd, MATRIX_CMD_CAPACITY> = Channel::new();
+
+/// Replies from the matrix scanner awaiting publication to the host.
+pub static HOST_REPLY: Channel<CriticalSectionRawMutex, HostReply, HOST_REPLY_CAPACITY> = Channel::new();
+
+/// Travel stream packets from the matrix scanner awaiting publication. Kept
+/// apart from [`HOST_REPLY`] so a busy stream never crowds out a command
+/// reply; `HostLink` also drains replies first.
+pub static TRAVEL_STREAM: Channel<CriticalSectionRawMutex, HostReply, TRAVEL_STREAM_CAPACITY> = Channel::new();
+
+/// A decoded host command for the matrix scanner.
+#[derive(Clone, Copy)]
+pub enum MatrixCmd {
+    /// Report the bindings of a Dynamic Keystroke slot.
+    GetDksSlot(u8),
+    /// Report the key bound to a gamepad input.
+    GetGamepadInput(GamepadInput),
+    /// Report the layers on which the gamepad is active.
+    GetGamepadLayers,
+    /// Report the sensor health of the key at `row`/`col`.
+    GetKeyHealth {
+        /// Matrix column.
+        col: u8,
+        /// Matrix row.
+        row: u8,
+    },
+    /// Report the resting noise and noise gate of the key at `row`/`col`.
+    GetKeyNoise {
+        /// Matrix column.
+        col: u8,
+        /// Matrix row.
+        row: u8,
+    },
+    /// Report the switch profile of the key at `row`/`col`.
+    GetKeyProfile {
+        /// Matrix column.
+        col: u8,
+        /// Matrix row.
+        row: u8,
+    },
+    /// Report the tuning of the key at `row`/`col`.
+    GetKeyTuning {
+        /// Matrix column.
+        col: u8,
+        /// Matrix row.
+        row: u8,
+    },
+    /// Report the key bound to an analog mouse input.
+    GetMouseInput(MouseInput),
+    /// Report the analog mouse settings.
+    GetMouseSettings,
+    /// Report the board-wide rapid-trigger mode.
+    GetRtMode,
+    /// Report the scan statistics, then clear the timing figures if `reset`
+    /// is set. Answered with [`HostStatus::Untimed`] instead while the cycle
+    /// counter is not running.
+    GetScanStats {
+        /// Whether to clear the timing figures after reading them.
+        reset: bool,
+    },
+    /// Report the sensing settings.
+    GetSettings,
+    /// Report one SOCD pair slot.
+    GetSocdPair(u8),
+    /// Report the travel stream interval.
+    GetTravelStream,
+    /// Rerun both guided calibration phases with the backlight guidance,
+    /// then persist the result; the stored calibration stays in place until
+    /// the new one is verified. Scanning pauses until the user has pressed
+    /// every key (or the window expires).
+    Recalibrate,
+    /// Recalibrate only the key at `row`/`col`, highlighted on the backlight:
+    /// it must stay released for the zero pass, then be held down until it
+    /// turns green. Scanning pauses until it is done or the window expires.
+    RecalibrateKey {
+        /// Matrix column.
+        col: u8,
+        /// Matrix row.
+        row: u8,
+    },
+    /// Persist the in-RAM tuning table, board-wide settings, and sensing
+    /// settings to EEPROM, and the switch profiles with the calibration if
+    /// any was edited.
+    SaveTuning,
+    /// Replace the bindings of a Dynamic Keystroke slot, re-deriving every key
+    /// assigned to it; lost on reset until a [`MatrixCmd::SaveTuning`].
+    SetDksSlot(u8, DksMap),
+    /// Bind a key to (or, with `None`, unbind) a gamepad input; lost on reset
+    /// until a [`MatrixCmd::SaveTuning`].
+    SetGamepadInput(GamepadInput, Option<MatrixPos>),
+    /// Replace the layers on which the gamepad is active; lost on reset until
+    /// a [`MatrixCmd::SaveTuning`].
+    SetGamepadLayers(u8),
+    /// Light (or, with `false`, stop lighting) every key with a sensor fault
+    /// on the backlight. Reflects the health at the time of the command.
+    SetHealthDisplay(bool),
+    /// Switch the key at `row`/`col` to another profile, effective
+    /// immediately but lost on reset until a [`MatrixCmd::SaveTuning`].
+    SetKeyProfile {
+        /// Matrix column.
+        col:     u8,
+        /// New switch profile.
+        profile: SwitchProfile,
+        /// Matrix row.
+        row:     u8,
+    },
+    /// Replace the tuning of the key at `row`/`col`, effective immediately but
+    /// lost on reset until a [`MatrixCmd::SaveTuning`].
+    SetKeyTuning {
+        /// Matrix column.
+        col:    u8,
+        /// Matrix row.
+        row:    u8,
+        /// New tuning, already range-checked by [`KeyTuning::is_valid`].
+        tuning: KeyTuning,
+    },
+    /// Bind a key to (or, with `None`, unbind) an analog mouse input; lost on
+    /// reset until a [`MatrixCmd::SaveTuning`].
+    SetMouseInput(MouseInput, Option<MatrixPos>),
+    /// Replace the analog mouse settings; lost on reset until a
+    /// [`MatrixCmd::SaveTuning`].
+    SetMouseSettings(MouseSettings),
+    /// Replace the sensing settings, effective immediately: keys still on the
+    /// old defaults follow the new ones. Lost on reset until a
+    /// [`MatrixCmd::SaveTuning`].
+    SetSettings(HallCfg),
+    /// Replace the board-wide rapid-trigger mode, re-deriving every key that
+    /// follows it; lost on reset until a [`MatrixCmd::SaveTuning`].
+    SetRtMode(RtMode),
+    /// Replace (or, with `None`, clear) one SOCD pair slot, re-linking the
+    /// affected keys; lost on reset until a [`MatrixCmd::SaveTuning`].
+    SetSocdPair(u8, Option<SocdPair>),
+    /// Stream changed keys to the host every given number of milliseconds, or
+    /// stop streaming with `0`. Not persisted.
+    SetTravelStream(u8),
+}
+
+impl MatrixCmd {
+    /// Decode a vendor packet, returning `None` for an unknown command id, a
+    /// tuning entry that fails [`KeyTuning::is_valid`], an unknown mode, or
+    /// an SOCD pair that is not two distinct keys of the matrix, or a
+    /// gamepad or mouse input or key that does not exist, or invalid mouse or
+    /// sensing settings.
+    fn decode(packet: &[u8; HOST_PACKET_LEN]) -> Option<Self> {
+        // Key commands address a key by row and column; board commands reuse
+        // byte 1 for their mode or slot argument.
+        let [id, row, col, ..] = *packet;
+        match id {
+            CMD_GET_KEY_TUNING => Some(Self::GetKeyTuning { col, row }),
+            CMD_SET_KEY_TUNING => {
+                let entry = packet.get(3..3_usize.saturating_add(ENTRY_LEN))?.try_into().ok()?;
+                Some(Self::SetKeyTuning { col, row, tuning: decode_entry(entry)? })
+            },
+            CMD_SAVE_TUNING => Some(Self::SaveTuning),
+            CMD_GET_RT_MODE => Some(Self::GetRtMode),
+            CMD_SET_RT_MODE => RtMode::from_code(row).map(Self::SetRtMode),
+            CMD_GET_DKS_SLOT => Some(Self::GetDksSlot(row)),
+            CMD_SET_DKS_SLOT => {
+                let map = packet.get(2..6)?.try_into().ok()?;
+                Some(Self::SetDksSlot(row, DksMap(u32::from_le_bytes(map))))
+            },
+            CMD_GET_SOCD_PAIR => Some(Self::GetSocdPair(row)),
+            CMD_SET_SOCD_PAIR => {
+                let pair = packet.get(2..2_usize.saturating_add(SocdPair::ENCODED_LEN))?.try_into().ok()?;
+                Some(Self::SetSocdPair(row, SocdPair::decode(pair, ROW, COL)?))
+            },
+            CMD_GET_GAMEPAD_INPUT => GamepadInput::from_index(row).map(Self::GetGamepadInput),
+            CMD_SET_GAMEPAD_INPUT => {
+                let pos = packet.get(2..2_usize.saturating_add(MatrixPos::ENCODED_LEN))?.try_into().ok()?;
+                Some(Self::SetGamepadInput(GamepadInput::from_index(row)?, MatrixPos::decode(pos, ROW, COL)?))
+            },
+            CMD_GET_GAMEPAD_LAYERS => Some(Self::GetGamepadLayers),
+            CMD_SET_GAMEPAD_LAYERS => Some(Self::SetGamepadLayers(row)),
+            CMD_GET_MOUSE_INPUT => MouseInput::from_index(row).map(Self::GetMouseInput),
+            CMD_SET_MOUSE_INPUT => {
+                let pos = packet.get(2..2_usize.saturating_add(MatrixPos::ENCODED_LEN))?.try_into().ok()?;
+                Some(Self::SetMouseInput(MouseInput::from_index(row)?, MatrixPos::decode(pos, ROW, COL)?))
+            },
+            CMD_GET_MOUSE_SETTINGS => Some(Self::GetMouseSettings),
+            CMD_SET_MOUSE_SETTINGS => {
+                let settings = packet.get(1..1_usize.saturating_add(MouseSettings::ENCODED_LEN))?.try_into().ok()?;
+                MouseSettings::decode(settings).map(Self::SetMouseSettings)
+            },
+            CMD_GET_TRAVEL_STREAM => Some(Self::GetTravelStream),
+            CMD_SET_TRAVEL_STREAM => Some(Self::SetTravelStream(row)),
+            CMD_GET_KEY_PROFILE => Some(Self::GetKeyProfile { col, row }),
+            CMD_GET_KEY_NOISE => Some(Self::GetKeyNoise { col, row }),
+            CMD_SET_KEY_PROFILE => {
+                let profile = SwitchProfile::from_code(*packet.get(3)?)?;
+                Some(Self::SetKeyProfile { col, profile, row })
+            },
+            CMD_GET_SCAN_STATS => match row {
+                0 => Some(Self::GetScanStats { reset: false }),
+                1 => Some(Self::GetScanStats { reset: true }),
+                _ => None,
+            },
+            CMD_GET_KEY_HEALTH => Some(Self::GetKeyHealth { col, row }),
+            CMD_SET_HEALTH_DISPLAY => match row {
+                0 => Some(Self::SetHealthDisplay(false)),
+                1 => Some(Self::SetHealthDisplay(true)),
+                _ => None,
+            },
+            CMD_RECALIBRATE => Some(Self::Recalibrate),
+            CMD_RECALIBRATE_KEY => Some(Self::RecalibrateKey { col, row }),
+            CMD_GET_SETTINGS => Some(Self::GetSettings),
+            CMD_SET_SETTINGS => {
+                let settings = packet.get(1..1_usize.saturating_add(settings_store::ENCODED_LEN))?.try_into().ok()?;
+                decode_settings(settings).map(Self::SetSettings)
+            },
+            _ => None,
+        }
+    }
+
+    /// Wire id of this command, echoed in its reply.
+    #[must_use]
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::GetDksSlot(_) => CMD_GET_DKS_SLOT,
+            Self::GetGamepadInput(_) => CMD_GET_GAMEPAD_INPUT,
+            Self::GetGamepadLayers => CMD_GET_GAMEPAD_LAYERS,
+            Self::GetKeyHealth { .. } => CMD_GET_KEY_HEALTH,
+            Self::GetKeyNoise { .. } => CMD_GET_KEY_NOISE,
+            Self::GetKeyProfile { .. } => CMD_GET_KEY_PROFILE,
+            Self::GetKeyTuning { .. } => CMD_GET_KEY_TUNING,
+            Self::GetMouseInput(_) => CMD_GET_MOUSE_INPUT,
+            Self::GetMouseSettings => CMD_GET_MOUSE_SETTINGS,
+            Self::GetRtMode => CMD_GET_RT_MODE,
+            Self::GetScanStats { .. } => CMD_GET_SCAN_STATS,
+            Self::GetSettings => CMD_GET_SETTINGS,
+            Self::GetSocdPair(_) => CMD_GET_SOCD_PAIR,
+            Self::GetTravelStream => CMD_GET_TRAVEL_STREAM,
+            Self::Recalibrate => CMD_RECALIBRATE,
+            Self::RecalibrateKey { .. } => CMD_RECALIBRATE_KEY,
+            Self::SaveTuning => CMD_SAVE_TUNING,
+            Self::SetDksSlot(..) => CMD_SET_DKS_SLOT,
+            Self::SetGamepadInput(..) => CMD_SET_GAMEPAD_INPUT,
+            Self::SetGamepadLayers(_) => CMD_SET_GAMEPAD_LAYERS,
+            Self::SetHealthDisplay(_) => CMD_SET_HEALTH_DISPLAY,
+            Self::SetKeyProfile { .. } => CMD_SET_KEY_PROFILE,
+            Self::SetKeyTuning { .. } => CMD_SET_KEY_TUNING,
+            Self::SetMouseInput(..) => CMD_SET_MOUSE_INPUT,
+            Self::SetMouseSettings(_) => CMD_SET_MOUSE_SETTINGS,
+            Self::SetSettings(_) => CMD_SET_SETTINGS,
+            Self::SetRtMode(_) => CMD_SET_RT_MODE,
+            Self::SetSocdPair(..) => CMD_SET_SOCD_PAIR,
+            Self::SetTravelStream(_) => CMD_SET_TRAVEL_STREAM,
+        }
+    }
+}
+
+/// Outcome of a host command, sent in byte 1 of every reply.
+#[derive(Clone, Copy)]
+pub enum HostStatus {
+    /// The command queue was full; the host should retry.
+    Busy,
+    /// The command was malformed, unknown, or carried out-of-range values.
+    Invalid,
+    /// The row/column does not name a populated key.
+    InvalidKey,
+    /// The key read implausibly at rest or was not held down within the
+    /// calibration window; its calibration is unchanged.
+    NotCalibrated,
+    /// The command completed.
+    Ok,
+    /// The EEPROM write or its read-back verification failed.
+    StoreFailed,
+    /// The cycle counter the scan statistics are timed with is not running,
+    /// so every figure would read zero.
+    Untimed,
+}
+
+impl HostStatus {
+    /// Wire code of this status.
+    const fn code(self) -> u8 {
+        match self {
+            Self::Ok => 0,
+            Self::Invalid => 1,
+            Self::InvalidKey => 2,
+            Self::Busy => 3,
+            Self::StoreFailed => 4,
+            Self::NotCalibrated => 5,
+            Self::Untimed => 6,
+        }
+    }
+}
+
+/// A reply to the host.
+#[derive(Clone, Copy)]
+pub enum HostReply {
+    /// Bindings of one Dynamic Keystroke slot, answering
+    /// [`MatrixCmd::GetDksSlot`].
+    DksSlot(u8, DksMap),
+    /// Key bound to one gamepad input, answering
+    /// [`MatrixCmd::GetGamepadInput`].
+    GamepadInput(GamepadInput, Option<MatrixPos>),
+    /// Gamepad layer mask, answering [`MatrixCmd::GetGamepadLayers`].
+    GamepadLayers(u8),
+    /// Sensor health of one key, answering [`MatrixCmd::GetKeyHealth`].
+    KeyHealth {
+        /// Matrix column.
+        col:      u8,
+        /// Fault flags.
+        health:   KeyHealth,
+        /// Calibrated range (zero - full) in raw ADC counts.
+        range:    u16,
+        /// Resting reading measured by the boot zero pass.
+        rest:     u16,
+        /// Matrix row.
+        row:      u8,
+        /// Deep presses since the auto-calibrator last reached confidence.
+        unscored: u8,
+    },
+    /// Resting noise and noise gate of one key, answering
+    /// [`MatrixCmd::GetKeyNoise`].
+    KeyNoise {
+        /// Matrix column.
+        col:   u8,
+        /// Noise gate in raw ADC counts.
+        gate:  u16,
+        /// Standard deviation of the resting reading in 1/16 ADC counts;
+        /// `0xFFFF` if unmeasured.
+        noise: u16,
+        /// Matrix row.
+        row:   u8,
+    },
+    /// Switch profile of one key, answering [`MatrixCmd::GetKeyProfile`].
+    KeyProfile {
+        /// Matrix column.
+        col:     u8,
+        /// Current switch profile.
+        profile: SwitchProfile,
+        /// Matrix row.
+        row:     u8,
+    },
+    /// One or two keys' live readings, sent unsolicited while the travel
+    /// stream runs.
+    KeySamples(KeySample, Option<KeySample>),
+    /// Tuning of one key, answering [`MatrixCmd::GetKeyTuning`].
+    KeyTuning {
+        /// Matrix column.
+        col:    u8,
+        /// Matrix row.
+        row:    u8,
+        /// Current tuning.
+        tuning: KeyTuning,
+    },
+    /// Key bound to one analog mouse input, answering
+    /// [`MatrixCmd::GetMouseInput`].
+    MouseInput(MouseInput, Option<MatrixPos>),
+    /// Analog mouse settings, answering [`MatrixCmd::GetMouseSettings`].
+    MouseSettings(MouseSettings),
+    /// Board-wide rapid-trigger mode, answering [`MatrixCmd::GetRtMode`].
+    RtMode(RtMode),
+    /// Scan-rate and latency statistics, answering
+    /// [`MatrixCmd::GetScanStats`].
+    ScanStats(ScanReport),
+    /// Sensing settings, answering [`MatrixCmd::GetSettings`].
+    Settings(HallCfg),
+    /// One SOCD pair slot, answering [`MatrixCmd::GetSocdPair`].
+    SocdPair(u8, Option<SocdPair>),
+    /// Bare status for the command with the given id.
+    Status(u8, HostStatus),
+    /// Travel stream interval in milliseconds (`0` while off), answering
+    /// [`MatrixCmd::GetTravelStream`].
+    TravelStream(u8),
+}
+
+impl HostReply {
+    /// Encode this reply as a zero-padded vendor packet.
+    fn encode(self) -> [u8; HOST_PACKET_LEN] {
+        let mut packet = [0_u8; HOST_PACKET_LEN];
+        match self {
+            Self::DksSlot(slot, map) => {
+                let header = [CMD_GET_DKS_SLOT, HostStatus::Ok.code(), slot];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) = packet.get_mut(header.len()..header.len().saturating_add(size_of::<u32>())) {
+                    dst.copy_from_slice(&map.0.to_le_bytes());
+                }
+            },
+            Self::GamepadInput(input, pos) => {
+                let [row, col] = MatrixPos::encode(pos);
+                if let Some(dst) = packet.get_mut(..5) {
+                    dst.copy_from_slice(&[CMD_GET_GAMEPAD_INPUT, HostStatus::Ok.code(), input.index(), row, col]);
+                }
+            },
+            Self::GamepadLayers(mask) => {
+                if let Some(dst) = packet.get_mut(..3) {
+                    dst.copy_from_slice(&[CMD_GET_GAMEPAD_LAYERS, HostStatus::Ok.code(), mask]);
+                }
+            },
+            Self::KeyHealth { col, health, range, rest, row, unscored } => {
+                let [rest_lo, rest_hi] = rest.to_le_bytes();
+                let [range_lo, range_hi] = range.to_le_bytes();
+                if let Some(dst) = packet.get_mut(..10) {
+                    dst.copy_from_slice(&[
+                        CMD_GET_KEY_HEALTH,
+                        HostStatus::Ok.code(),
+                        row,
+                        col,
+                        health.bits(),
+                        rest_lo,
+                        rest_hi,
+                        range_lo,
+                        range_hi,
+                        unscored,
+                    ]);
+                }
+            },
+            Self::KeyNoise { col, gate, noise, row } => {
+                let [noise_lo, noise_hi] = noise.to_le_bytes();
+                let [gate_lo, gate_hi] = gate.to_le_bytes();
+                if let Some(dst) = packet.get_mut(..8) {
+                    dst.copy_from_slice(&[
+                        CMD_GET_KEY_NOISE,
+                        HostStatus::Ok.code(),
+                        row,
+                        col,
+                        noise_lo,
+                        noise_hi,
+                        gate_lo,
+                        gate_hi,
+                    ]);
+                }
+            },
+            Self::KeyProfile { col, profile, row } => {
+                if let Some(dst) = packet.get_mut(..5) {
+                    dst.copy_from_slice(&[CMD_GET_KEY_PROFILE, HostStatus::Ok.code(), row, col, profile.code()]);
+                }
+            },
+            Self::KeySamples(first, second) => {
+                let header = [CMD_TRAVEL_SAMPLES, HostStatus::Ok.code(), if second.is_some() { 2 } else { 1 }];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                for (index, sample) in [Some(first), second].into_iter().enumerate() {
+                    let start = header.len().saturating_add(index.saturating_mul(KeySample::ENCODED_LEN));
+                    if let Some(sample) = sample
+                        && let Some(dst) = packet.get_mut(start..start.saturating_add(KeySample::ENCODED_LEN))
+                    {
+                        dst.copy_from_slice(&sample.encode());
+                    }
+                }
+            },
+            Self::KeyTuning { col, row, tuning } => {
+                let header = [CMD_GET_KEY_TUNING, HostStatus::Ok.code(), row, col];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) = packet.get_mut(header.len()..header.len().saturating_add(ENTRY_LEN)) {
+                    dst.copy_from_slice(&encode_entry(tuning));
+                }
+            },
+            Self::MouseInput(input, pos) => {
+                let [row, col] = MatrixPos::encode(pos);
+                if let Some(dst) = packet.get_mut(..5) {
+                    dst.copy_from_slice(&[CMD_GET_MOUSE_INPUT, HostStatus::Ok.code(), input.index(), row, col]);
+                }
+            },
+            Self::MouseSettings(settings) => {
+                let header = [CMD_GET_MOUSE_SETTINGS, HostStatus::Ok.code()];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) = packet.get_mut(header.len()..header.len().saturating_add(MouseSettings::ENCODED_LEN))
+                {
+                    dst.copy_from_slice(&settings.encode());
+                }
+            },
+            Self::RtMode(rt_mode) => {
+                if let Some(dst) = packet.get_mut(..3) {
+                    dst.copy_from_slice(&[CMD_GET_RT_MODE, HostStatus::Ok.code(), rt_mode.code()]);
+                }
+            },
+            Self::ScanStats(report) => {
+                let header = [CMD_GET_SCAN_STATS, HostStatus::Ok.code()];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) = packet.get_mut(header.len()..header.len().saturating_add(ScanReport::ENCODED_LEN)) {
+                    dst.copy_from_slice(&report.encode());
+                }
+            },
+            Self::Settings(cfg) => {
+                let header = [CMD_GET_SETTINGS, HostStatus::Ok.code()];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) =
+                    packet.get_mut(header.len()..header.len().saturating_add(settings_store::ENCODED_LEN))
+                {
+                    dst.copy_from_slice(&encode_settings(cfg));
+                }
+            },
+            Self::SocdPair(slot, pair) => {
+                let header = [CMD_GET_SOCD_PAIR, HostStatus::Ok.code(), slot];
+                if let Some(dst) = packet.get_mut(..header.len()) {
+                    dst.copy_from_slice(&header);
+                }
+                if let Some(dst) = packet.get_mut(header.len()..header.len().saturating_add(SocdPair::ENCODED_LEN)) {
+                    dst.copy_from_slice(&SocdPair::encode(pair));
+                }
+            },
+            Self::Status(id, status) => {
+                if let Some(dst) = packet.get_mut(..2) {
+                    dst.copy_from_slice(&[id, status.code()]);
+                }
+            },
+            Self::TravelStream(interval_ms) => {
+                if let Some(dst) = packet.get_mut(..3) {
+                    dst.copy_from_slice(&[CMD_GET_TRAVEL_STREAM, HostStatus::Ok.code(), interval_ms]);
+                }
+            },
+        }
+        packet
+    }
+}
+
+/// Bridges rynk vendor packets to the matrix scanner.
+///
+/// Hand to `run_all!`; construct before it so no packet published during
+/// enumeration is missed.
+pub struct HostLink {
+    /// Subscription to incoming rynk vendor packets.
+    sub: <RynkVendorEvent as SubscribableEvent>::Subscriber,
+}
+
+impl HostLink {
+    /// Subscribe immediately so no host packet is missed.
+    #[must_use]
+    pub fn new() -> Self { Self { sub: RynkVendorEvent::subscriber() } }
+}
+
+impl Default for HostLink {
+    fn default() -> Self { Self::new() }
+}
+
+impl Runnable for HostLink {
+    async fn run(&mut self) -> ! {
+        loop {
+            let reply = match select3(self.sub.next_event(), HOST_REPLY.receive(), TRAVEL_STREAM.receive()).await {
+                Either3::First(RynkVendorEvent(packet)) => match MatrixCmd::decode(&packet) {
+                    Some(cmd) => match MATRIX_CMD.try_send(cmd) {
+                        Ok(()) => continue,
+                        Err(_) => HostReply::Status(cmd.id(), HostStatus::Busy),
+                    },
+                    None => {
+                        let [id, ..] = packet;
+                        HostReply::Status(id, HostStatus::Invalid)
+                    },
+                },
+                Either3::Second(reply) | Either3::Third(reply) => reply,
+            };
+            publish_event_async(RynkVendorReply(reply.encode())).await;
+        }
+    }
+}
@@ -22,18 +22,23 @@ mod shared;
 }
 
 use rmk::types::action::KeyAction;
-pub use selected::led::LED_LAYOUT;
+pub use selected::{LAYOUT_ID, led::LED_LAYOUT};
 use selected::{
     layout::{MAC_ROW5, ROW0, ROW1, ROW2, ROW3, ROW4, WIN_ROW5},
     led::{LED_MAPPING_ROW0, LED_MAPPING_ROW1, LED_MAPPING_ROW2, LED_MAPPING_ROW3, LED_MAPPING_ROW4, LED_MAPPING_ROW5},
     sensor::{SENSOR_ROW0, SENSOR_ROW1, SENSOR_ROW2, SENSOR_ROW3, SENSOR_ROW4, SENSOR_ROW5},
 };
-use shared::layout::{ENCODER_VOLUME, EncoderAction};
+use shared::layout::{DKS_ROW, ENCODER_VOLUME, EncoderAction};
 
 /// Number of columns in the key matrix.
 pub const COL: usize = 21;
 /// Number of rows in the key matrix.
 pub const ROW: usize = 6;
+/// Number of virtual keymap rows after the physical matrix that hold the
+/// Dynamic Keystroke slot actions.
+pub const DKS_ROWS: usize = 2;
+/// Number of rows in the keymap: the physical matrix plus [`DKS_ROWS`].
+pub const KEYMAP_ROW: usize = ROW.saturating_add(DKS_ROWS);
 /// Number of supported layers.
 pub const NUM_LAYER: usize = 2;
 /// Number of rotary encoders.
@@ -125,6 +130,13 @@ impl ColValidKeys {
     pub const fn valid_rows(&self) -> &[u8] { if let Some(rows) = self.rows.get(..self.count) { rows } else { &[] } }
 }
 
+/// Whether the matrix position at `row`/`col` has a physical hall-effect
+/// sensor.
+#[must_use]
+pub fn has_sensor(row: u8, col: usize) -> bool {
+    VALID_ROWS_BY_COL.get(col).is_some_and(|valid| valid.valid_rows().contains(&row))
+}
+
 /// Iterate the populated sensor positions of column `col`, yielding
 /// `(row, raw)` pairs of the matrix row index and that row's reading in
 /// `readings`.
@@ -146,11 +158,11 @@ pub fn valid_readings<const ROW: usize>(col: usize, readings: &[u16; ROW]) -> im
 pub const fn get_default_encoder_map() -> [[EncoderAction; NUM_ENCODER]; NUM_LAYER] { [ENCODER_VOLUME, ENCODER_VOLUME] }
 
 /// Return the default keymap for all layers.
-pub const fn get_default_keymap() -> [[[KeyAction; COL]; ROW]; NUM_LAYER] {
+pub const fn get_default_keymap() -> [[[KeyAction; COL]; KEYMAP_ROW]; NUM_LAYER] {
     [
         // Layer 0: MAC_BASE
-        [ROW0, ROW1, ROW2, ROW3, ROW4, MAC_ROW5],
+        [ROW0, ROW1, ROW2, ROW3, ROW4, MAC_ROW5, DKS_ROW, DKS_ROW],
         // Layer 1: WIN_BASE
-        [ROW0, ROW1, ROW2, ROW3, ROW4, WIN_ROW5],
+        [ROW0, ROW1, ROW2, ROW3, ROW4, WIN_ROW5, DKS_ROW, DKS_ROW],
     ]
 }
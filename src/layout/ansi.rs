@@ -4,3 +4,6 @@ pub mod layout;
 pub mod led;
 /// Hall-effect sensor presence map.
 pub mod sensor;
+
+/// Code of the ANSI layout, stored with the calibration block.
+pub const LAYOUT_ID: u8 = 1;
@@ -52,6 +52,9 @@ pub const ROW1: [KeyAction; COL] = [
     key!(KpAsterisk),
     key!(KpMinus),
 ];
+/// Default actions for a virtual Dynamic Keystroke row: every slot action
+/// starts unbound and is assigned through rynk.
+pub const DKS_ROW: [KeyAction; COL] = [act!(No); COL];
 /// Key actions for matrix row 5 on the Mac base layer.
 pub const MAC_ROW5: [KeyAction; COL] = [
     key!(LCtrl),
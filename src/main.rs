@@ -18,19 +18,37 @@ extern crate cortex_m as _;
 mod backlight;
 /// Board-specific hardware description (pins, clocks, register tweaks).
 mod board;
-/// EEPROM I²C driver.
+/// EEPROM I²C driver and its internal-flash fallback.
 mod eeprom;
+/// Analog gamepad driven by key travel.
+mod gamepad;
+/// Host configuration protocol over rynk vendor packets.
+mod host;
 /// Default layout definitions.
 mod layout;
 /// Matrix scanning components.
 mod matrix;
+/// Analog mouse driven by key depth.
+mod mouse;
 /// USB host connection state helpers shared across tasks.
 mod usb_state;
 
 use crate::{
     backlight::{processor::LedIndicator, task::BacklightRunner},
-    board::{Q6RowPins, enable_flash_acceleration, stm32_config, tune_adc},
-    eeprom::Ft24c64,
+    board::{Q6RowPins, enable_cycle_counter, enable_flash_acceleration, reload_watchdog, stm32_config, tune_adc},
+    eeprom::{
+        self,
+        Backend,
+        Ft24c64,
+        SharedStore,
+        Store,
+        WriteProtect,
+        flash_store::FlashStore,
+        partition::{self, Region},
+        storage::{EepromFlash, KEYMAP_SECTORS},
+    },
+    gamepad::GamepadTask,
+    host::HostLink,
     layout::{COL, ROW},
     matrix::{
         analog_matrix::{AdcPart, AnalogHallMatrix, HallCfg},
@@ -38,6 +56,7 @@ use crate::{
         hc164_cols::Hc164Cols,
         layer_toggle::{LayerToggle, MatrixPos},
     },
+    mouse::MouseTask,
 };
 use embassy_executor::{Spawner, main};
 use embassy_stm32::{
@@ -46,27 +65,31 @@ use embassy_stm32::{
     crc::Crc,
     dma,
     exti::{self, ExtiInput},
+    flash::Flash,
     gpio::{Flex, Level, Output, Pull, Speed},
     i2c::{self, I2c},
     init,
     interrupt::typelevel,
+    mode::Async,
     peripherals::{self, ADC1},
     spi::{self},
     time::Hertz,
     usb::{self, Driver},
 };
+use embassy_sync::mutex::Mutex;
+use embassy_time::Delay;
 use encoder_switch::EncoderSwitch;
 use layout::{get_default_encoder_map, get_default_keymap};
 use rmk::{
     KeymapData,
-    config::{BehaviorConfig, DeviceConfig, PositionalConfig, RmkConfig},
-    initialize_keymap,
+    config::{BehaviorConfig, DeviceConfig, PositionalConfig, RmkConfig, StorageConfig},
+    initialize_keymap_and_storage,
     input_device::rotary_encoder::RotaryEncoder,
     keyboard::Keyboard,
     run_all,
     usb::UsbTransport,
 };
-use static_cell::ConstStaticCell;
+use static_cell::{ConstStaticCell, StaticCell};
 
 bind_interrupts!(struct Irqs {
     DMA2_STREAM0 => dma::InterruptHandler<peripherals::DMA2_CH0>;
@@ -88,8 +111,9 @@ async fn main(spawner: Spawner) {
     // Tasks run inline via run_all!; the executor's spawner is unused by our
     // code but is part of the signature the `#[main]` macro requires.
     _ = spawner;
-    let peripheral = init(stm32_config());
+    let mut peripheral = init(stm32_config());
     enable_flash_acceleration();
+    enable_cycle_counter();
 
     // Usb config
     static EP_OUT_BUFFER: ConstStaticCell<[u8; 1024]> = ConstStaticCell::new([0; 1024]);
@@ -153,6 +177,17 @@ async fn main(spawner: Spawner) {
         cfg.frequency = Hertz(850_000);
         cfg
     };
+    // A reset in the middle of an EEPROM read can leave the FT24C64 holding
+    // SDA low; free the bus before the I²C peripheral takes the pins over.
+    {
+        let mut scl = Flex::new(peripheral.PA8.reborrow());
+        let mut sda = Flex::new(peripheral.PC9.reborrow());
+        scl.set_high();
+        sda.set_high();
+        scl.set_as_input_output(Speed::Low);
+        sda.set_as_input_output(Speed::Low);
+        eeprom::recover_bus(&mut scl, &mut sda).await;
+    }
     let i2c3 = I2c::new(
         peripheral.I2C3,
         peripheral.PA8,      // SCL (PA8 = I2C3_SCL)
@@ -162,22 +197,29 @@ async fn main(spawner: Spawner) {
         Irqs,
         i2c_config,
     );
-    let eeprom_wp = Flex::new(peripheral.PB10);
-    let eeprom = Ft24c64::new(i2c3, eeprom_wp);
+    // PB10: EEPROM write-protect, pulled up except while writing.
+    let eeprom_wp = WriteProtect::new(Flex::new(peripheral.PB10));
+    // An EEPROM that still fails after bus recovery and every retry is taken
+    // for dead; its data then lives in the reserved internal-flash sectors.
+    let backend = if let Ok(mut chip) = Ft24c64::new(i2c3, eeprom_wp, Delay)
+        && chip.read(0, &mut [0_u8; 1]).await.is_ok()
+    {
+        Backend::Eeprom(chip)
+    } else {
+        Backend::Flash(FlashStore::new(Flash::new_blocking(peripheral.FLASH), reload_watchdog))
+    };
+    // Shared by the matrix scanner and RMK's keymap storage, each holding the
+    // lock only for one access.
+    static EEPROM: StaticCell<SharedStore<Backend<I2c<'static, Async, i2c::mode::Master>, WriteProtect<'static>>>> =
+        StaticCell::new();
+    let eeprom = EEPROM.init(Mutex::new(backend));
 
     // Hardware CRC peripheral for EEPROM calibration block checksums.
-    let crc = Crc::new(peripheral.CRC);
+    let mut crc = Crc::new(peripheral.CRC);
+    // Regions the stored partition table places differently from this build
+    // are migrated or reset by their owners before the table is rewritten.
+    let stale = partition::stale_regions(&mut *eeprom.lock().await, &mut crc).await;
 
-    let adc_part = AdcPart::new(adc, row_pins, peripheral.DMA2_CH0, Irqs, SampleTime::Cycles56);
-    let mut matrix = AnalogHallMatrix::<_, _, _, _, _, ROW, COL>::new(
-        adc_part,
-        cols,
-        HallCfg::default(),
-        eeprom,
-        crc,
-        matrix_power,
-        wake,
-    );
     // Rotary encoder
     let pin_a = ExtiInput::new(peripheral.PB14, peripheral.EXTI14, Pull::None, Irqs);
     let pin_b = ExtiInput::new(peripheral.PB15, peripheral.EXTI15, Pull::None, Irqs);
@@ -197,11 +239,41 @@ async fn main(spawner: Spawner) {
     let mut keymap_data = KeymapData::new_with_encoder(get_default_keymap(), get_default_encoder_map());
     let mut behavior_config = BehaviorConfig::default();
     let key_config = PositionalConfig::default();
-    let keymap = initialize_keymap(&mut keymap_data, &mut behavior_config, &key_config).await;
+    // RMK owns the keymap region; the sector range is relative to its start.
+    // A stale region may hold anything, so RMK starts it over.
+    let storage_config = StorageConfig {
+        clear_storage: stale.contains(Region::Keymap),
+        start_addr: 0,
+        num_sectors: u8::try_from(KEYMAP_SECTORS).unwrap_or(u8::MAX),
+        ..Default::default()
+    };
+    let (keymap, mut storage) = initialize_keymap_and_storage(
+        &mut keymap_data,
+        EepromFlash::new(eeprom),
+        &storage_config,
+        &mut behavior_config,
+        &key_config,
+    )
+    .await;
+
+    // The matrix validates the calibration and settings regions itself,
+    // migrates or resets the stale ones it owns, and then rewrites the table.
+    let adc_part = AdcPart::new(adc, row_pins, peripheral.DMA2_CH0, Irqs, SampleTime::Cycles56);
+    let mut matrix = AnalogHallMatrix::<_, _, _, _, _, ROW, COL>::new(
+        adc_part,
+        cols,
+        HallCfg::default(),
+        eeprom,
+        stale,
+        crc,
+        matrix_power,
+        wake,
+    );
 
     // Initialize the keyboard
     let mut keyboard = Keyboard::new(&keymap);
-    let mut usb_transport = UsbTransport::new(driver, rmk_config.device_config);
+    let mut usb_transport =
+        UsbTransport::new(driver, rmk_config.device_config).with_gamepad(gamepad::REPORT_DESCRIPTOR);
 
     // LED backlight
     let spi_config = {
@@ -226,6 +298,9 @@ async fn main(spawner: Spawner) {
     let mut led_indicator = LedIndicator::new();
     let mut backlight = BacklightRunner::new(spi_backlight, cs0, cs1, sdb);
     let mut usb_state_task = usb_state::UsbStateTask::new();
+    let mut host_link = HostLink::new();
+    let mut gamepad = GamepadTask::new();
+    let mut mouse = MouseTask::new();
 
     // Start.
     //
@@ -245,7 +320,11 @@ async fn main(spawner: Spawner) {
         layer_toggle,
         led_indicator,
         usb_state_task,
-        backlight
+        host_link,
+        gamepad,
+        mouse,
+        backlight,
+        storage
     )
     .await;
 }
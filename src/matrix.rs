@@ -10,8 +10,13 @@ pub mod encoder_switch;
 pub mod hc164_cols;
 /// Layer toggle input handling.
 pub mod layer_toggle;
+/// Sensing settings EEPROM serialization.
+pub mod settings_store;
+/// Per-key tuning EEPROM serialization.
+pub mod tuning_store;
 
-use embassy_stm32::{exti::ExtiInput, mode::Async};
+use calib_store::Crc32;
+use embassy_stm32::{crc::Crc, exti::ExtiInput, mode::Async};
 use embassy_time::{Duration, Timer};
 
 /// Default debounce window shared by the discrete switch inputs (encoder
@@ -30,3 +35,13 @@ pub async fn debounced_level(pin: &mut ExtiInput<'_, Async>, debounce: Duration)
     Timer::after(debounce).await;
     pin.is_high()
 }
+
+/// The STM32 CRC peripheral computes every checksum of persisted data on
+/// the board.
+impl Crc32 for Crc<'_> {
+    fn feed_word(&mut self, word: u32) { _ = Crc::feed_word(self, word); }
+
+    fn read(&self) -> u32 { Crc::read(self) }
+
+    fn reset(&mut self) { Crc::reset(self); }
+}
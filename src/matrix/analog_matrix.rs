@@ -3,21 +3,54 @@
 
 /// First-boot guided calibration and EEPROM persistence.
 mod calibration;
+/// Dynamic Keystroke slot bindings and virtual keymap positions.
+pub mod dks;
+/// Optional per-key EMA / median filtering of raw readings.
+pub mod filter;
+/// Per-key sensor health assessment.
+pub mod health;
 /// Sparse hall-sensor transfer-function table with linear interpolation.
 mod lut;
+/// Background persistence of auto-calibration refinements.
+mod persist;
 /// Hot-path matrix scan loop.
 mod scan;
+/// SOCD pair configuration and resolution.
+pub mod socd;
+/// Scan-rate and latency statistics.
+pub mod stats;
+/// Live per-key sample streaming to the host.
+pub mod stream;
 /// Calibration types, constants, per-key runtime state, and the calibration
 /// arithmetic that operates on it.
 pub mod types;
 
 use crate::{
-    eeprom::Ft24c64,
+    backlight::processor::{BACKLIGHT_CH, BacklightCmd},
+    eeprom::{
+        SharedStore,
+        Store,
+        partition::{self, Region, StaleRegions},
+    },
+    gamepad::{GAMEPAD_LAYERS, GamepadInput},
+    host::{HOST_REPLY, HostReply, HostStatus, MatrixCmd},
+    layout::has_sensor,
     matrix::{
-        analog_matrix::types::{AdcSampleTime, KeyEntry},
-        calib_store::{CALIB_BUF_LEN, EEPROM_BASE_ADDR, try_deserialize},
+        analog_matrix::{
+            calibration::CalibRead,
+            health::{self, KeyHealth},
+            persist::PersistTimer,
+            scan::{ScanExit, ScanState},
+            stats::{self, ScanStats},
+            stream::TravelStream,
+            types::{AdcSampleTime, BoardTuning, KeyEntry, KeyTuning},
+        },
+        calib_store::StoredCalib,
         hc164_cols::Hc164Cols,
+        settings_store::{self, SETTINGS_BASE_ADDR, SETTINGS_BUF_LEN},
+        tuning_store::{self, TUNING_BASE_ADDR, TUNING_BUF_LEN},
     },
+    mouse::{MOUSE_SETTINGS, MouseInput},
     usb_state::USB_ACTIVE,
 };
 use core::{array::from_fn, future::pending};
@@ -28,7 +61,6 @@ use embassy_stm32::{
     dma::InterruptHandler,
     exti::ExtiInput,
     gpio::Output,
-    i2c::mode::MasterMode,
     interrupt::typelevel::Binding,
     mode::Async,
     pac::adc,
@@ -155,111 +187,629 @@ where
 /// [`types::CALIB_SETTLE_AFTER_ALL_DONE`]
 /// window continues sampling to capture the true bottom-out ADC. Validated
 /// entries are written to the FT24C64 EEPROM and verified by read-back. On all
-/// subsequent boots only full-travel data is loaded from EEPROM; zero-travel
-/// is re-measured fresh to compensate for temperature drift.
+/// subsequent boots the stored block supplies each key's full travel, switch
+/// profile, zero, and resting noise. Zero and noise are re-measured fresh to
+/// compensate for temperature drift, except for a key held down during that
+/// pass, which keeps its stored figures.
 ///
 /// During normal operation the auto-calibrator silently refines both zero and
 /// full-travel values on every press/release cycle, keeping the scanner
 /// accurate as the sensor drifts over time without requiring user interaction.
-pub struct AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, IM, const ROW: usize, const COL: usize>
+/// Refinements that move a key's full-travel reading far enough are written
+/// back in the background (see [`persist`]), so they survive a reboot.
+pub struct AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, S, const ROW: usize, const COL: usize>
 where
     ADC: Instance<Regs = adc::Adc> + BasicInstance,
     D: RxDma<ADC>,
     R: RowChannels<ADC, ROW>,
     IRQ: Binding<D::Interrupt, InterruptHandler<D>> + Copy + 'peripherals,
-    IM: MasterMode,
+    S: Store,
     AdcSampleTime<ADC>: Clone,
 {
     /// ADC peripherals and channels grouped for split-borrow compatibility;
     /// also owns the DMA interrupt binding used to build each sequence.
-    adc_part: AdcPart<'peripherals, ADC, D, R, IRQ, ROW>,
-    /// Sensing and scanning configuration.
-    cfg:      HallCfg,
+    adc_part:     AdcPart<'peripherals, ADC, D, R, IRQ, ROW>,
+    /// Board-wide tuning shared by every key (see [`BoardTuning`]). Seeded
+    /// from [`HallCfg`] and persisted with the tuning table.
+    board:        BoardTuning,
+    /// Whether a switch-profile edit has left the stored calibration block
+    /// out of date; cleared once [`MatrixCmd::SaveTuning`] rewrites it.
+    calib_dirty:  bool,
+    /// Each key's full-travel reading as of the current calibration block,
+    /// against which [`persist::drifted`] measures auto-calibration drift.
+    calib_full:   [[u16; ROW]; COL],
+    /// Slot and write counter of the current calibration block; `None` until
+    /// one is loaded or stored.
+    calib_stored: Option<StoredCalib>,
+    /// Sensing and scanning configuration: the built-in [`HallCfg`] until a
+    /// stored settings record replaces it at boot, then edited by the host.
+    cfg:          HallCfg,
     /// Column driver used to select the active column via the HC164.
-    cols:     Hc164Cols<'peripherals>,
+    cols:         Hc164Cols<'peripherals>,
     /// Hardware CRC peripheral used for EEPROM calibration block checksums.
-    crc:      Crc<'peripherals>,
-    /// EEPROM driver for loading and persisting calibration data.
-    eeprom:   Ft24c64<'peripherals, IM>,
+    crc:          Crc<'peripherals>,
+    /// Store for loading and persisting calibration data, the EEPROM or
+    /// its internal-flash fallback, shared with RMK's keymap storage and locked
+    /// only around each access.
+    eeprom:       &'peripherals SharedStore<S>,
     /// Per-key runtime state, calibration, and auto-calibration. Stored
     /// column-major (`[[KeyEntry; ROW]; COL]`) so the per-column inner scan
     /// loop walks one contiguous SRAM block per HC164 column, which the AHB
     /// load-store unit pipelines better than scattered indirect loads from a
     /// row-major layout.
-    keys:     [[KeyEntry; ROW]; COL],
+    keys:         [[KeyEntry; ROW]; COL],
     /// Hall-sensor power rail (PC13), held high during calibration and active
     /// scanning and cut between passes while the USB bus is suspended.
-    power:    Output<'peripherals>,
+    power:        Output<'peripherals>,
+    /// Travel stream and scan statistics handed to every scan.
+    scan:         ScanState,
+    /// Regions the stored partition table places differently from this
+    /// build. [`Runnable::run`] migrates or resets those the matrix owns,
+    /// then rewrites the table.
+    stale:        StaleRegions,
+    /// Per-key actuation and rapid-trigger tuning in 0.05 mm configuration
+    /// units, column-major like `keys`. Seeded from [`HallCfg`], replaced by
+    /// the stored tuning block when one validates, edited by the host, and
+    /// mirrored into each key's [`KeyEntry::rt`].
+    tuning:       [[KeyTuning; ROW]; COL],
     /// Hardware any-key wake line (PC5); parks the scanner during suspend.
-    wake:     ExtiInput<'peripherals, Async>,
+    wake:         ExtiInput<'peripherals, Async>,
 }
 
-impl<'peripherals, ADC, D, R, IRQ, IM, const ROW: usize, const COL: usize>
-    AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, IM, ROW, COL>
+impl<'peripherals, ADC, D, R, IRQ, S, const ROW: usize, const COL: usize>
+    AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, S, ROW, COL>
 where
     ADC: Instance<Regs = adc::Adc> + BasicInstance,
     D: RxDma<ADC>,
     R: RowChannels<ADC, ROW>,
     IRQ: Binding<D::Interrupt, InterruptHandler<D>> + Copy + 'peripherals,
-    IM: MasterMode,
+    S: Store,
     AdcSampleTime<ADC>: Clone,
 {
+    /// Re-derive every key's hot-path tuning from the table and the
+    /// board-wide settings, releasing whatever a key's change of DKS
+    /// assignment stranded (see [`KeyEntry::apply_tuning`]).
+    async fn apply_tuning(&mut self) {
+        for (col, (key_col, tuning_col)) in self.keys.iter_mut().zip(&self.tuning).enumerate() {
+            for (row, (key, &tuning)) in key_col.iter_mut().zip(tuning_col).enumerate() {
+                if let Some(stranded) = key.apply_tuning(tuning, &self.board) {
+                    scan::release_stranded(stranded, row, col).await;
+                }
+            }
+        }
+    }
+
+    /// Replace the sensing settings at runtime.
+    ///
+    /// Keys whose tuning still equals the old defaults, and the board-wide
+    /// mode if it does, follow the new defaults; anything edited on its own
+    /// is kept. Keys without a measured resting noise take the new fallback
+    /// noise gate. The calibration passes and windows apply from the next
+    /// calibration.
+    async fn apply_settings(&mut self, cfg: HallCfg) {
+        let old_defaults = KeyTuning::from_cfg(self.cfg);
+        let new_defaults = KeyTuning::from_cfg(cfg);
+        for tuning in self.tuning.as_flattened_mut().iter_mut().filter(|tuning| **tuning == old_defaults) {
+            *tuning = new_defaults;
+        }
+        if self.board.rt_mode == self.cfg.rt_mode {
+            self.board.rt_mode = cfg.rt_mode;
+        }
+        for key in self.keys.as_flattened_mut() {
+            key.set_noise(key.noise, cfg.noise_gate);
+        }
+        self.cfg = cfg;
+        self.apply_tuning().await;
+    }
+
+    /// Run the guided two-phase calibration and persist it. Returns `true`
+    /// when the new block read back valid.
+    ///
+    /// Used on a first boot and for [`MatrixCmd::Recalibrate`]; the backlight
+    /// guides the user through both phases either way.
+    async fn calibrate(&mut self) -> bool {
+        // Scope the sequence so it is dropped (stopping the ADC) before the
+        // scan takes over `adc_part` again.
+        let calibrated = {
+            let mut seq = self.adc_part.configure_sequence();
+            calibration::run_first_boot_calib(
+                &mut self.cols,
+                &mut seq,
+                self.cfg,
+                &mut self.eeprom.lock().await,
+                &mut self.crc,
+                &mut self.keys,
+                &mut self.calib_stored,
+            )
+            .await
+        };
+        if calibrated {
+            self.calib_dirty = false;
+            self.calib_full = persist::full_snapshot(&self.keys);
+        }
+        calibrated
+    }
+
+    /// Recalibrate the key at `row`/`col` on its own and apply the result;
+    /// see [`calibration::recalibrate_key`]. Returns `false` if the key was
+    /// left unchanged.
+    async fn calibrate_key(&mut self, row: usize, col: usize) -> bool {
+        let Some(key) = self.keys.get_mut(col).and_then(|key_col| key_col.get_mut(row)) else {
+            return false;
+        };
+        let mut buf = [0_u16; ROW];
+        let mut seq = self.adc_part.configure_sequence();
+        calibration::recalibrate_key::<ROW, COL>(&mut self.cols, &mut seq, &mut buf, self.cfg, key, row, col).await
+    }
+
+    /// Execute one host command and queue its reply.
+    ///
+    /// Edits apply to the key immediately; only
+    /// [`MatrixCmd::SaveTuning`] touches the EEPROM. The reply is best-effort
+    /// (`try_send`): if `HostLink` has fallen behind, dropping a reply is
+    /// preferable to stalling the scanner.
+    async fn handle_cmd(&mut self, cmd: MatrixCmd) {
+        let reply = match cmd {
+            MatrixCmd::GetDksSlot(slot) => self
+                .board
+                .dks
+                .get(usize::from(slot))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::Invalid), |&map| HostReply::DksSlot(slot, map)),
+            MatrixCmd::GetGamepadInput(input) => self
+                .board
+                .gamepad
+                .get(usize::from(input.index()))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::Invalid), |&pos| HostReply::GamepadInput(input, pos)),
+            MatrixCmd::GetGamepadLayers => HostReply::GamepadLayers(self.board.gamepad_layers),
+            MatrixCmd::GetKeyHealth { col, row } => self
+                .keys
+                .get(usize::from(col))
+                .and_then(|key_col| key_col.get(usize::from(row)))
+                .filter(|_| has_sensor(row, usize::from(col)))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::InvalidKey), |key| HostReply::KeyHealth {
+                    col,
+                    health: KeyHealth::of(key),
+                    range: key.calib_zero.saturating_sub(key.entry_full),
+                    rest: key.boot_zero,
+                    row,
+                    unscored: key.ac_unscored,
+                }),
+            MatrixCmd::GetKeyNoise { col, row } => self
+                .keys
+                .get(usize::from(col))
+                .and_then(|key_col| key_col.get(usize::from(row)))
+                .filter(|_| has_sensor(row, usize::from(col)))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::InvalidKey), |key| HostReply::KeyNoise {
+                    col,
+                    gate: key.noise_gate,
+                    noise: key.noise,
+                    row,
+                }),
+            MatrixCmd::GetKeyProfile { col, row } => self
+                .keys
+                .get(usize::from(col))
+                .and_then(|key_col| key_col.get(usize::from(row)))
+                .filter(|_| has_sensor(row, usize::from(col)))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::InvalidKey), |key| HostReply::KeyProfile {
+                    col,
+                    profile: key.profile,
+                    row,
+                }),
+            MatrixCmd::GetKeyTuning { col, row } => {
+                self.tuning.get(usize::from(col)).and_then(|tuning_col| tuning_col.get(usize::from(row))).map_or(
+                    HostReply::Status(cmd.id(), HostStatus::InvalidKey),
+                    |&tuning| HostReply::KeyTuning { col, row, tuning },
+                )
+            },
+            MatrixCmd::GetMouseInput(input) => self
+                .board
+                .mouse
+                .get(usize::from(input.index()))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::Invalid), |&pos| HostReply::MouseInput(input, pos)),
+            MatrixCmd::GetMouseSettings => HostReply::MouseSettings(self.board.mouse_settings),
+            MatrixCmd::GetRtMode => HostReply::RtMode(self.board.rt_mode),
+            MatrixCmd::GetScanStats { reset } => {
+                let report = self.scan.stats.report();
+                if reset {
+                    self.scan.stats.reset();
+                }
+                if stats::timed() {
+                    HostReply::ScanStats(report)
+                } else {
+                    HostReply::Status(cmd.id(), HostStatus::Untimed)
+                }
+            },
+            MatrixCmd::GetSettings => HostReply::Settings(self.cfg),
+            MatrixCmd::GetSocdPair(slot) => self
+                .board
+                .socd
+                .get(usize::from(slot))
+                .map_or(HostReply::Status(cmd.id(), HostStatus::Invalid), |&pair| HostReply::SocdPair(slot, pair)),
+            MatrixCmd::GetTravelStream => HostReply::TravelStream(self.scan.stream.interval_ms()),
+            MatrixCmd::Recalibrate => {
+                let status = if self.calibrate().await { HostStatus::Ok } else { HostStatus::StoreFailed };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::RecalibrateKey { col, row } => {
+                let status = if !has_sensor(row, usize::from(col)) {
+                    HostStatus::InvalidKey
+                } else if !self.calibrate_key(usize::from(row), usize::from(col)).await {
+                    HostStatus::NotCalibrated
+                } else if self.store_calib().await {
+                    HostStatus::Ok
+                } else {
+                    // Keep the new calibration pending for the next save.
+                    self.calib_dirty = true;
+                    HostStatus::StoreFailed
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SaveTuning => {
+                if self.calib_dirty {
+                    _ = self.store_calib().await;
+                }
+                let stored =
+                    calibration::store_tuning(&mut self.eeprom.lock().await, &mut self.crc, &self.tuning, &self.board)
+                        .await;
+                let settings_stored =
+                    calibration::store_settings(&mut self.eeprom.lock().await, &mut self.crc, self.cfg).await;
+                let status = if stored && settings_stored && !self.calib_dirty {
+                    HostStatus::Ok
+                } else {
+                    HostStatus::StoreFailed
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetDksSlot(slot, map) => {
+                let status = if let Some(dst) = self.board.dks.get_mut(usize::from(slot)) {
+                    *dst = map;
+                    self.apply_tuning().await;
+                    HostStatus::Ok
+                } else {
+                    HostStatus::Invalid
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetGamepadInput(input, pos) => {
+                let index = usize::from(input.index());
+                // A key feeds at most one input.
+                let taken = pos.is_some_and(|pos| {
+                    self.board
+                        .gamepad
+                        .iter()
+                        .enumerate()
+                        .any(|(other_index, &other)| other_index != index && other == Some(pos))
+                });
+                let status = match self.board.gamepad.get_mut(index) {
+                    Some(dst) if !taken => {
+                        *dst = pos;
+                        self.link_keys();
+                        HostStatus::Ok
+                    },
+                    _ => HostStatus::Invalid,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetGamepadLayers(mask) => {
+                self.board.gamepad_layers = mask;
+                self.link_keys();
+                HostReply::Status(cmd.id(), HostStatus::Ok)
+            },
+            MatrixCmd::SetHealthDisplay(on) => {
+                let leds = if on { health::fault_leds(&self.keys) } else { 0 };
+                let status = match BACKLIGHT_CH.sender().try_send(BacklightCmd::HealthFaults(leds)) {
+                    Ok(()) => HostStatus::Ok,
+                    Err(_) => HostStatus::Busy,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetKeyProfile { col, profile, row } => {
+                let status = match self
+                    .keys
+                    .get_mut(usize::from(col))
+                    .and_then(|key_col| key_col.get_mut(usize::from(row)))
+                    .filter(|_| has_sensor(row, usize::from(col)))
+                {
+                    Some(key) => {
+                        if key.profile != profile {
+                            key.set_profile(profile);
+                            self.calib_dirty = true;
+                        }
+                        HostStatus::Ok
+                    },
+                    None => HostStatus::InvalidKey,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetKeyTuning { col, row, tuning } => {
+                let status = match (
+                    self.tuning.get_mut(usize::from(col)).and_then(|tuning_col| tuning_col.get_mut(usize::from(row))),
+                    self.keys.get_mut(usize::from(col)).and_then(|key_col| key_col.get_mut(usize::from(row))),
+                ) {
+                    (Some(slot), Some(key)) => {
+                        *slot = tuning;
+                        if let Some(stranded) = key.apply_tuning(tuning, &self.board) {
+                            scan::release_stranded(stranded, usize::from(row), usize::from(col)).await;
+                        }
+                        HostStatus::Ok
+                    },
+                    _ => HostStatus::InvalidKey,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetMouseInput(input, pos) => {
+                let index = usize::from(input.index());
+                // A key feeds at most one input.
+                let taken = pos.is_some_and(|pos| {
+                    self.board
+                        .mouse
+                        .iter()
+                        .enumerate()
+                        .any(|(other_index, &other)| other_index != index && other == Some(pos))
+                });
+                let status = match self.board.mouse.get_mut(index) {
+                    Some(dst) if !taken => {
+                        *dst = pos;
+                        self.link_keys();
+                        HostStatus::Ok
+                    },
+                    _ => HostStatus::Invalid,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetMouseSettings(settings) => {
+                self.board.mouse_settings = settings;
+                self.link_keys();
+                HostReply::Status(cmd.id(), HostStatus::Ok)
+            },
+            MatrixCmd::SetRtMode(rt_mode) => {
+                self.board.rt_mode = rt_mode;
+                self.apply_tuning().await;
+                HostReply::Status(cmd.id(), HostStatus::Ok)
+            },
+            MatrixCmd::SetSettings(cfg) => {
+                self.apply_settings(cfg).await;
+                HostReply::Status(cmd.id(), HostStatus::Ok)
+            },
+            MatrixCmd::SetSocdPair(slot, pair) => {
+                let index = usize::from(slot);
+                let overlaps = pair.is_some_and(|pair| {
+                    self.board.socd.iter().enumerate().any(|(other_index, other)| {
+                        other_index != index && other.is_some_and(|other_pair| other_pair.shares_key(pair))
+                    })
+                });
+                let status = match self.board.socd.get_mut(index) {
+                    Some(dst) if !overlaps => {
+                        *dst = pair;
+                        self.link_keys();
+                        HostStatus::Ok
+                    },
+                    _ => HostStatus::Invalid,
+                };
+                HostReply::Status(cmd.id(), status)
+            },
+            MatrixCmd::SetTravelStream(interval_ms) => {
+                self.scan.stream.set_interval(interval_ms, &mut self.keys);
+                HostReply::Status(cmd.id(), HostStatus::Ok)
+            },
+        };
+        _ = HOST_REPLY.try_send(reply);
+    }
+
+    /// Point every key at its board-wide roles (SOCD partner, gamepad and
+    /// mouse inputs) and unlink all others, then hand the gamepad and mouse
+    /// their current travels and settings.
+    ///
+    /// A newly linked SOCD key has so far published its physical state, so
+    /// its SOCD output starts from [`KeyEntry::pressed`]; already linked keys
+    /// keep theirs, so a suppressed key stays suppressed across an edit.
+    fn link_keys(&mut self) {
+        for (col, key_col) in self.keys.iter_mut().enumerate() {
+            for (row, key) in key_col.iter_mut().enumerate() {
+                if key.rt.socd.is_none() {
+                    key.socd_out = key.pressed;
+                }
+                key.rt.socd = self.board.socd.iter().flatten().find_map(|pair| pair.link_for(row, col));
+                key.rt.gamepad = None;
+                key.rt.mouse = None;
+            }
+        }
+        for (input, pos) in GamepadInput::ALL.into_iter().zip(self.board.gamepad) {
+            let key = pos.and_then(|pos| {
+                self.keys.get_mut(usize::from(pos.col)).and_then(|key_col| key_col.get_mut(usize::from(pos.row)))
+            });
+            let travel = key.map_or(0, |key| {
+                key.rt.gamepad = Some(input);
+                key.travel
+            });
+            input.set_travel(travel);
+        }
+        for (input, pos) in MouseInput::ALL.into_iter().zip(self.board.mouse) {
+            let key = pos.and_then(|pos| {
+                self.keys.get_mut(usize::from(pos.col)).and_then(|key_col| key_col.get_mut(usize::from(pos.row)))
+            });
+            let travel = key.map_or(0, |key| {
+                key.rt.mouse = Some(input);
+                key.travel
+            });
+            input.set_travel(travel);
+        }
+        GAMEPAD_LAYERS.sender().send(self.board.gamepad_layers);
+        MOUSE_SETTINGS.sender().send(self.board.mouse_settings);
+    }
+
     /// Create a new matrix scanner.
     ///
-    /// Calibration is deferred to [`Runnable::run`], which loads from EEPROM
-    /// on subsequent boots or runs a full first-boot calibration pass.
+    /// `cfg` holds the built-in settings. Calibration is deferred to
+    /// [`Runnable::run`], which first replaces `cfg` with the stored settings
+    /// record if one validates, then loads from EEPROM on subsequent boots or
+    /// runs a full first-boot calibration pass. `stale` comes from
+    /// [`partition::stale_regions`], read before RMK cleared a stale keymap
+    /// region.
     pub fn new(
         adc_part: AdcPart<'peripherals, ADC, D, R, IRQ, ROW>,
         cols: Hc164Cols<'peripherals>,
         cfg: HallCfg,
-        eeprom: Ft24c64<'peripherals, IM>,
+        eeprom: &'peripherals SharedStore<S>,
+        stale: StaleRegions,
         crc: Crc<'peripherals>,
         power: Output<'peripherals>,
         wake: ExtiInput<'peripherals, Async>,
     ) -> Self {
-        Self { adc_part, cfg, cols, crc, eeprom, keys: from_fn(|_| from_fn(|_| KeyEntry::default())), power, wake }
+        Self {
+            adc_part,
+            board: BoardTuning::from_cfg(cfg),
+            calib_dirty: false,
+            calib_full: [[0; ROW]; COL],
+            calib_stored: None,
+            cfg,
+            cols,
+            crc,
+            eeprom,
+            keys: from_fn(|_| from_fn(|_| KeyEntry::default())),
+            power,
+            scan: ScanState { persist: PersistTimer::new(), stats: ScanStats::new(), stream: TravelStream::new() },
+            stale,
+            tuning: [[KeyTuning::from_cfg(cfg); ROW]; COL],
+            wake,
+        }
+    }
+
+    /// Run the background persistence check and schedule the next one.
+    ///
+    /// Rewrites the calibration block when auto-calibration has moved a key's
+    /// full-travel reading past [`persist::PERSIST_FULL_DRIFT`]. Skipped
+    /// while a switch-profile edit awaits [`MatrixCmd::SaveTuning`], so the
+    /// edit is saved only on request. A failed write leaves the drift in
+    /// place for the next check.
+    async fn persist_calib(&mut self) {
+        if !self.calib_dirty && persist::drifted(&self.keys, &self.calib_full) {
+            _ = self.store_calib().await;
+        }
+        self.scan.persist.reschedule();
+    }
+
+    /// Write the calibration to the next slot (see
+    /// [`calibration::store_calib`]). On success nothing is left pending and
+    /// drift is measured from the new block.
+    async fn store_calib(&mut self) -> bool {
+        let stored = calibration::store_calib(
+            &mut self.eeprom.lock().await,
+            &mut self.crc,
+            &mut self.keys,
+            &mut self.calib_stored,
+        )
+        .await;
+        if stored {
+            self.calib_dirty = false;
+            self.calib_full = persist::full_snapshot(&self.keys);
+        }
+        stored
     }
 }
 
-impl<'peripherals, ADC, D, R, IRQ, IM, const ROW: usize, const COL: usize> Runnable
-    for AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, IM, ROW, COL>
+impl<'peripherals, ADC, D, R, IRQ, S, const ROW: usize, const COL: usize> Runnable
+    for AnalogHallMatrix<'peripherals, ADC, D, R, IRQ, S, ROW, COL>
 where
     ADC: Instance<Regs = adc::Adc> + BasicInstance,
     D: RxDma<ADC>,
     R: RowChannels<ADC, ROW>,
     IRQ: Binding<D::Interrupt, InterruptHandler<D>> + Copy + 'peripherals,
-    IM: MasterMode,
+    S: Store,
     AdcSampleTime<ADC>: Clone,
 {
     async fn run(&mut self) -> ! {
-        let mut eeprom_buf = [0_u8; CALIB_BUF_LEN];
-
-        let loaded = self.eeprom.read(EEPROM_BASE_ADDR, &mut eeprom_buf).await.is_ok()
-            && try_deserialize::<ROW, COL>(&eeprom_buf, &mut self.keys, &mut self.crc);
-        let mut buf = [0_u16; ROW];
-
-        // Scope the calibration sequence so it is dropped (stopping the ADC)
-        // before `scan::run` takes over `adc_part` to build and tear down its
-        // own sequences around each suspend.
+        // Whether every stale region the matrix owns has been migrated or
+        // reset, so the partition table may record this build's layout.
+        let mut settled = true;
+        // A valid settings record replaces the built-in HallCfg before the
+        // tuning table is seeded from it or any calibration runs. A stale
+        // settings region holds no record of this build and is reset.
+        let mut settings_buf = [0_u8; SETTINGS_BUF_LEN];
+        if self.stale.contains(Region::Settings) {
+            settled &= partition::reset(&mut *self.eeprom.lock().await, Region::Settings).await;
+        } else if self.eeprom.lock().await.read(SETTINGS_BASE_ADDR, &mut settings_buf).await.is_ok()
+            && let Some(cfg) = settings_store::try_deserialize(&settings_buf, &mut self.crc)
         {
-            let mut seq = self.adc_part.configure_sequence();
-            if loaded {
-                // Re-measure zero travel on every boot to compensate for
-                // temperature drift; full-travel data comes from EEPROM.
-                let zero_raw = calibration::calibrate_zero_raw(&mut self.cols, &mut seq, &mut buf, self.cfg).await;
+            self.cfg = cfg;
+            self.board = BoardTuning::from_cfg(cfg);
+            self.tuning = [[KeyTuning::from_cfg(cfg); ROW]; COL];
+        }
+        if self.stale.contains(Region::Stats) {
+            settled &= partition::reset(&mut *self.eeprom.lock().await, Region::Stats).await;
+        }
+        // A stale calibration region may still hold the v1 block of firmware
+        // without a partition table, which is migrated below; holding no
+        // block at all, it is reset before its tuning block is read.
+        let calib_stale = self.stale.contains(Region::Calib);
+        let loaded =
+            calibration::load_calib(&mut self.eeprom.lock().await, &mut self.crc, &mut self.keys, calib_stale).await;
+        let migrated = matches!(loaded, CalibRead::Found(_, true));
+        if calib_stale && matches!(loaded, CalibRead::Missing) {
+            settled &= partition::reset(&mut *self.eeprom.lock().await, Region::Calib).await;
+        }
+
+        // A missing or invalid tuning block leaves the HallCfg defaults in
+        // place; it never forces a recalibration.
+        let mut tuning_buf = [0_u8; TUNING_BUF_LEN];
+        if self.eeprom.lock().await.read(TUNING_BASE_ADDR, &mut tuning_buf).await.is_ok() {
+            _ = tuning_store::try_deserialize::<ROW, COL>(
+                &tuning_buf,
+                &mut self.tuning,
+                &mut self.board,
+                &mut self.crc,
+            );
+        }
+        match loaded {
+            CalibRead::Found(stored, _) => {
+                self.calib_stored = Some(stored);
+                self.calib_full = persist::full_snapshot(&self.keys);
+                {
+                    let mut buf = [0_u16; ROW];
+                    // Scope the calibration sequence so it is dropped
+                    // (stopping the ADC) before `scan::run` takes over
+                    // `adc_part` to build and tear down its own sequences
+                    // around each suspend.
+                    let mut seq = self.adc_part.configure_sequence();
+                    // Re-measure zero travel on every boot to compensate for
+                    // temperature drift; full-travel data comes from EEPROM,
+                    // and a key held down meanwhile keeps its stored zero
+                    // and noise.
+                    let (zero_raw, noise) =
+                        calibration::calibrate_zero_raw(&mut self.cols, &mut seq, &mut buf, self.cfg).await;
+                    calibration::apply_calib(&mut self.keys, &zero_raw);
+                    calibration::apply_noise(&mut self.keys, &noise, self.cfg.noise_gate);
+                }
+                // Rewrite a v1 block as v2 now that the zero pass has
+                // measured what it lacked; the v1 block stays behind until
+                // the partition table or a write to slot A replaces it. A
+                // failed write leaves the migration pending for the next save
+                // or boot.
+                if migrated {
+                    settled &= self.store_calib().await;
+                }
+            },
+            CalibRead::Missing => _ = self.calibrate().await,
+            // A guided calibration could not be saved, and forcing one on
+            // every boot of a board with a failing EEPROM helps nobody: run
+            // on a fresh zero pass and the default travel range, which
+            // auto-calibration refines while typing.
+            CalibRead::Unreachable => {
+                settled = false;
+                let mut buf = [0_u16; ROW];
+                let mut seq = self.adc_part.configure_sequence();
+                let (zero_raw, noise) =
+                    calibration::calibrate_zero_raw(&mut self.cols, &mut seq, &mut buf, self.cfg).await;
+                calibration::apply_default_full(&mut self.keys, &zero_raw);
                 calibration::apply_calib(&mut self.keys, &zero_raw);
-            } else {
-                calibration::run_first_boot_calib(
-                    &mut self.cols,
-                    &mut seq,
-                    &mut buf,
-                    self.cfg,
-                    &mut self.eeprom,
-                    &mut self.crc,
-                    &mut self.keys,
-                )
-                .await;
-            }
+                calibration::apply_noise(&mut self.keys, &noise, self.cfg.noise_gate);
+            },
+        }
+        // Only now may the table record this build's layout: a power loss or
+        // failed reset before this point leaves the regions stale, and the
+        // next boot handles them again.
+        if !self.stale.is_empty() && settled {
+            _ = partition::store_table(&mut *self.eeprom.lock().await, &mut self.crc).await;
         }
 
         let Some(mut usb) = USB_ACTIVE.receiver() else {
@@ -267,16 +817,24 @@ where
                 pending::<()>().await;
             }
         };
-        scan::run(
-            &mut self.cols,
-            &mut self.keys,
-            &mut self.adc_part,
-            &mut self.power,
-            &mut self.wake,
-            &mut usb,
-            self.cfg,
-        )
-        .await;
+        self.apply_tuning().await;
+        self.link_keys();
+        loop {
+            let exit = scan::run(
+                &mut self.cols,
+                &mut self.keys,
+                &mut self.adc_part,
+                &mut self.power,
+                &mut self.wake,
+                &mut usb,
+                &mut self.scan,
+            )
+            .await;
+            match exit {
+                ScanExit::Cmd(cmd) => self.handle_cmd(cmd).await,
+                ScanExit::Persist => self.persist_calib().await,
+            }
+        }
     }
 }
 
@@ -10,29 +10,49 @@
 use super::scan_pass;
 use crate::{
     backlight::processor::{BACKLIGHT_CH, BacklightCmd, CalibPhase},
-    eeprom::Ft24c64,
+    eeprom::Store,
     layout::{MATRIX_TO_LED, VALID_ROWS_BY_COL, valid_readings},
     matrix::{
         analog_matrix::types::{
+            AutoCalibPhase,
+            BoardTuning,
             CALIB_HOLD_DURATION_MS,
             CALIB_PRESS_THRESHOLD,
             CALIB_SETTLE_AFTER_ALL_DONE,
             HallCfg,
             KeyCalibState,
             KeyEntry,
+            KeyTuning,
+            NOISE_UNMEASURED,
             REF_ZERO_TRAVEL,
             ZERO_TRAVEL_DEAD_ZONE,
             entry_full_from,
+            noise_from,
             zero_plausible,
         },
-        calib_store::{self, CALIB_BUF_LEN, EEPROM_BASE_ADDR, try_deserialize},
+        calib_store::{self, CALIB_BUF_LEN, CalibSlot, EEPROM_BASE_ADDR, StoredCalib, try_deserialize},
         hc164_cols::Hc164Cols,
+        settings_store::{self, SETTINGS_BASE_ADDR, SETTINGS_BUF_LEN},
+        tuning_store::{self, TUNING_BASE_ADDR, TUNING_BUF_LEN},
     },
 };
 use core::hint::{likely, unlikely};
-use embassy_stm32::{adc::ConfiguredSequence, crc::Crc, i2c::mode::MasterMode, pac::adc};
+use embassy_stm32::{adc::ConfiguredSequence, crc::Crc, pac::adc};
 use embassy_time::{Duration, Instant};
 
+/// Outcome of [`load_calib`].
+pub(super) enum CalibRead {
+    /// A slot validated: its slot and write counter, and whether the block
+    /// was in the v1 format.
+    Found(StoredCalib, bool),
+    /// The EEPROM answered, but no slot holds a valid block.
+    Missing,
+    /// The EEPROM did not answer even after bus retries. What it holds is
+    /// unknown, so this is not a missing calibration: a guided calibration
+    /// could not be saved anyway.
+    Unreachable,
+}
+
 /// Recompute [`KeyEntry::calib_used`] and the hot-path calibration fields
 /// for every key from the freshly measured zero-travel readings in
 /// `zero_raw`, using the full-travel value stored in each
@@ -53,26 +73,67 @@ pub(super) fn apply_calib<const ROW: usize, const COL: usize>(
     }
 }
 
+/// Give every key the default travel range below its zero-travel reading
+/// in `zero_raw` (see [`entry_full_from`]), for a boot on which no stored
+/// calibration could be read. Call before [`apply_calib`].
+pub(super) fn apply_default_full<const ROW: usize, const COL: usize>(
+    keys: &mut [[KeyEntry; ROW]; COL],
+    zero_raw: &[[u16; COL]; ROW],
+) {
+    for (col, key_col) in keys.iter_mut().enumerate() {
+        for (key, zero_row) in key_col.iter_mut().zip(zero_raw.iter()) {
+            if let Some(&zero) = zero_row.get(col) {
+                key.entry_full = entry_full_from(zero, u16::MAX);
+            }
+        }
+    }
+}
+
+/// Record the resting noise of every key from the zero pass's `noise`
+/// (see [`calibrate_zero_raw`]) and derive each key's noise gate, falling
+/// back to `fallback_gate` where nothing was measured.
+///
+/// A key held down during the zero pass (see [`KeyEntry::held_at_boot`])
+/// keeps the figure loaded from the calibration block if there is one,
+/// since its reading was not taken at rest. Call after [`apply_calib`].
+pub(super) fn apply_noise<const ROW: usize, const COL: usize>(
+    keys: &mut [[KeyEntry; ROW]; COL],
+    noise: &[[u16; COL]; ROW],
+    fallback_gate: u16,
+) {
+    for (col, key_col) in keys.iter_mut().enumerate() {
+        for (key, noise_row) in key_col.iter_mut().zip(noise.iter()) {
+            let measured = noise_row.get(col).copied().unwrap_or(NOISE_UNMEASURED);
+            let resting = if key.held_at_boot() && key.noise != NOISE_UNMEASURED { key.noise } else { measured };
+            key.set_noise(resting, fallback_gate);
+        }
+    }
+}
+
 /// Average `cfg.calib_passes` full-matrix scans to establish per-key
-/// zero-travel (resting) ADC values.
+/// zero-travel (resting) ADC values, measuring each key's resting noise
+/// along the way.
 ///
-/// All keys must be fully released during this pass. Returns a
-/// `ROW × COL` array of raw ADC averages, each reduced by
+/// All keys must be fully released during this pass. Returns two
+/// `ROW × COL` arrays: the raw ADC averages, each reduced by
 /// [`ZERO_TRAVEL_DEAD_ZONE`] so that the resting position sits cleanly
 /// below the measured average, preventing ADC noise from producing
-/// spurious non-zero travel readings.
+/// spurious non-zero travel readings; and the standard deviation of each
+/// key's readings (see [`noise_from`]).
 pub(super) async fn calibrate_zero_raw<const ROW: usize, const COL: usize>(
     cols: &mut Hc164Cols<'_>,
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
     buf: &mut [u16; ROW],
     cfg: HallCfg,
-) -> [[u16; COL]; ROW] {
+) -> ([[u16; COL]; ROW], [[u16; COL]; ROW]) {
     let mut acc = [[0_u32; COL]; ROW];
+    let mut acc_sq = [[0_u64; COL]; ROW];
     for _ in 0..cfg.calib_passes {
         scan_pass(cols, seq, buf, COL, |col, readings| {
-            for (acc_row, &raw) in acc.iter_mut().zip(readings.iter()) {
-                if let Some(cell) = acc_row.get_mut(col) {
+            for ((acc_row, sq_row), &raw) in acc.iter_mut().zip(acc_sq.iter_mut()).zip(readings.iter()) {
+                if let (Some(cell), Some(sq_cell)) = (acc_row.get_mut(col), sq_row.get_mut(col)) {
                     *cell = cell.saturating_add(u32::from(raw));
+                    *sq_cell = sq_cell.saturating_add(u64::from(raw).saturating_mul(u64::from(raw)));
                 }
             }
         })
@@ -80,16 +141,22 @@ pub(super) async fn calibrate_zero_raw<const ROW: usize, const COL: usize>(
     }
 
     let mut result = [[REF_ZERO_TRAVEL; COL]; ROW];
-    for (res_row, acc_row) in result.iter_mut().zip(acc.iter()) {
-        for (res, &total) in res_row.iter_mut().zip(acc_row.iter()) {
+    let mut noise = [[NOISE_UNMEASURED; COL]; ROW];
+    for (((res_row, noise_row), acc_row), sq_row) in
+        result.iter_mut().zip(noise.iter_mut()).zip(acc.iter()).zip(acc_sq.iter())
+    {
+        for (((res, key_noise), &total), &total_sq) in
+            res_row.iter_mut().zip(noise_row.iter_mut()).zip(acc_row.iter()).zip(sq_row.iter())
+        {
             // Leave the REF_ZERO_TRAVEL initializer in place on any
             // arithmetic failure (e.g. calib_passes == 0).
             if let Some(avg) = total.checked_div(cfg.calib_passes) {
                 *res = u16::try_from(avg).unwrap_or(REF_ZERO_TRAVEL).saturating_sub(ZERO_TRAVEL_DEAD_ZONE);
             }
+            *key_noise = noise_from(total, total_sq, cfg.calib_passes);
         }
     }
-    result
+    (result, noise)
 }
 
 /// Count sensor positions that both have a physical hall element and a
@@ -120,7 +187,8 @@ pub(super) fn count_real_sensors<const ROW: usize, const COL: usize>(zero_raw: &
 }
 
 /// Run the guided first-boot two-phase calibration, persist the result to
-/// EEPROM, and apply it to `keys`.
+/// EEPROM with [`store_calib`], and apply it to `keys`. Returns `true`, with
+/// `stored` naming the new block, when it read back valid.
 ///
 /// Backlight signals during the process:
 /// - **Amber** - zero-travel pass, all keys must be released.
@@ -129,29 +197,30 @@ pub(super) fn count_real_sensors<const ROW: usize, const COL: usize>(zero_raw: &
 ///   turns solid green individually.
 /// - **Green blink ×3** - all keys accepted; keys may be released.
 /// - **Green for 2 s** - calibration stored successfully.
-/// - **Amber** - EEPROM write-back verification failed; keyboard will
-///   re-calibrate on the next boot.
+/// - **Amber** - EEPROM write-back verification failed; the next boot loads the
+///   previous calibration slot if one is valid, and otherwise re-calibrates.
 ///
 /// Keys not pressed during the full-travel window fall back to
 /// `zero - DEFAULT_FULL_RANGE` so the keyboard remains functional.
-pub(super) async fn run_first_boot_calib<IM, const ROW: usize, const COL: usize>(
+pub(super) async fn run_first_boot_calib<S, const ROW: usize, const COL: usize>(
     cols: &mut Hc164Cols<'_>,
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
-    buf: &mut [u16; ROW],
     cfg: HallCfg,
-    eeprom: &mut Ft24c64<'_, IM>,
+    eeprom: &mut S,
     crc: &mut Crc<'_>,
     keys: &mut [[KeyEntry; ROW]; COL],
-) where
-    IM: MasterMode,
+    stored: &mut Option<StoredCalib>,
+) -> bool
+where
+    S: Store,
 {
-    let mut eeprom_buf = [0_u8; CALIB_BUF_LEN];
+    let mut buf = [0_u16; ROW];
 
     BACKLIGHT_CH.sender().send(BacklightCmd::CalibPhase(CalibPhase::Zero)).await;
-    let zero_raw = calibrate_zero_raw(cols, seq, buf, cfg).await;
+    let (zero_raw, noise) = calibrate_zero_raw(cols, seq, &mut buf, cfg).await;
 
     BACKLIGHT_CH.sender().send(BacklightCmd::CalibPhase(CalibPhase::Full)).await;
-    let full_raw = sample_full_raw(cols, seq, buf, cfg, &zero_raw).await;
+    let full_raw = sample_full_raw(cols, seq, &mut buf, cfg, &zero_raw).await;
 
     // Compute entry_full for every key from the measured zero and the
     // minimum ADC seen during the full-travel press window; see
@@ -165,23 +234,121 @@ pub(super) async fn run_first_boot_calib<IM, const ROW: usize, const COL: usize>
     }
 
     apply_calib(keys, &zero_raw);
+    apply_noise(keys, &noise, cfg.noise_gate);
 
-    calib_store::serialize(keys, &mut eeprom_buf, crc);
-
-    // Verify by reading back into the same buffer and re-deserializing.
-    // Reusing the buffer avoids a second large stack allocation.
-    let verified = eeprom.erase().await.is_ok()
-        && eeprom.write(EEPROM_BASE_ADDR, &eeprom_buf).await.is_ok()
-        && eeprom.read(EEPROM_BASE_ADDR, &mut eeprom_buf).await.is_ok()
-        && try_deserialize::<ROW, COL>(&eeprom_buf, keys, crc);
-
-    if !verified {
-        // Signal amber so the user knows calibration will repeat on the
-        // next boot.
+    if !store_calib(eeprom, crc, keys, stored).await {
+        // Signal amber so the user knows the calibration was not saved.
         BACKLIGHT_CH.sender().send(BacklightCmd::CalibPhase(CalibPhase::Zero)).await;
-        return;
+        return false;
     }
     BACKLIGHT_CH.sender().send(BacklightCmd::CalibPhase(CalibPhase::Done)).await;
+    true
+}
+
+/// Load the newest calibration slot that validates into `keys`.
+///
+/// With `legacy` set, as when the calibration region is stale, the v1 block
+/// that firmware without a partition table kept at [`EEPROM_BASE_ADDR`]
+/// competes as well. It carries no write counter, so either slot wins over
+/// it, and it is reported as slot A so the migrated block goes to slot B,
+/// clear of the old block and its tuning block until it verifies.
+///
+/// Returns [`CalibRead::Found`] with the slot, its write counter, and
+/// whether the block was in the v1 format (see
+/// [`calib_store::CalibLoad::migrated`]). `keys` is left unchanged if
+/// no block validates ([`CalibRead::Missing`]) or the EEPROM cannot be
+/// read at all ([`CalibRead::Unreachable`]).
+pub(super) async fn load_calib<S, const ROW: usize, const COL: usize>(
+    eeprom: &mut S,
+    crc: &mut Crc<'_>,
+    keys: &mut [[KeyEntry; ROW]; COL],
+    legacy: bool,
+) -> CalibRead
+where
+    S: Store,
+{
+    let [slot_a, slot_b] = CalibSlot::ALL.map(|slot| (slot.addr(), slot));
+    let sources: &[(u16, CalibSlot)] =
+        if legacy { &[slot_a, slot_b, (EEPROM_BASE_ADDR, CalibSlot::A)] } else { &[slot_a, slot_b] };
+    let mut eeprom_buf = [0_u8; CALIB_BUF_LEN];
+    let mut newest: Option<(u16, StoredCalib)> = None;
+    let mut reachable = false;
+    for &(addr, slot) in sources {
+        let read = eeprom.read(addr, &mut eeprom_buf).await.is_ok();
+        reachable |= read;
+        // Released firmware only ever wrote a v1 block (write counter 0)
+        // to the legacy address.
+        if read
+            && let Some(writes) = calib_store::sequence::<ROW, COL>(&eeprom_buf, crc)
+            && (addr != EEPROM_BASE_ADDR || writes == 0)
+            && newest.is_none_or(|(_, best)| calib_store::is_newer(writes, best.writes))
+        {
+            newest = Some((addr, StoredCalib { slot, writes }));
+        }
+    }
+    let Some((addr, stored)) = newest else {
+        return if reachable { CalibRead::Missing } else { CalibRead::Unreachable };
+    };
+    // The buffer holds whichever block was read last; re-read the winner.
+    if eeprom.read(addr, &mut eeprom_buf).await.is_err() {
+        return CalibRead::Unreachable;
+    }
+    try_deserialize::<ROW, COL>(&eeprom_buf, keys, crc)
+        .map_or(CalibRead::Missing, |load| CalibRead::Found(stored, load.migrated))
+}
+
+/// Recalibrate only the key at (`row`, `col`), e.g. after its switch was
+/// replaced, leaving every other key's calibration untouched.
+///
+/// Measures the key's zero travel and noise with a regular zero pass (the
+/// key must stay released), then runs [`sample_key_full`] for its
+/// full-travel reading. Only when the key was accepted are its
+/// [`KeyEntry::entry_full`] and hot-path fields replaced, and its
+/// auto-calibration restarted; persisting them is left to the caller.
+///
+/// The backlight keeps its normal frame with the key highlighted: amber
+/// while it must stay released, red while it must be held down, green once
+/// accepted.
+///
+/// Returns `false`, leaving `key` unchanged, if its resting reading is
+/// implausible or it was not held down within `cfg.key_calib_duration`.
+pub(super) async fn recalibrate_key<const ROW: usize, const COL: usize>(
+    cols: &mut Hc164Cols<'_>,
+    seq: &mut ConfiguredSequence<'_, adc::Adc>,
+    buf: &mut [u16; ROW],
+    cfg: HallCfg,
+    key: &mut KeyEntry,
+    row: usize,
+    col: usize,
+) -> bool {
+    let led = MATRIX_TO_LED.get(row).and_then(|led_row| led_row.get(col)).copied().flatten();
+
+    signal_key(led, CalibPhase::Zero).await;
+    let (zero_raw, noise) = calibrate_zero_raw::<ROW, COL>(cols, seq, buf, cfg).await;
+    let zero = zero_raw.get(row).and_then(|row_slice| row_slice.get(col)).copied().unwrap_or(REF_ZERO_TRAVEL);
+
+    let seen_min = if zero_plausible(zero) {
+        signal_key(led, CalibPhase::Full).await;
+        sample_key_full::<ROW, COL>(cols, seq, buf, zero, (row, col), led, cfg.key_calib_duration).await
+    } else {
+        None
+    };
+
+    if let Some(seen_min) = seen_min {
+        key.entry_full = entry_full_from(zero, seen_min);
+        key.apply_zero(zero);
+        key.set_noise(
+            noise.get(row).and_then(|row_slice| row_slice.get(col)).copied().unwrap_or(NOISE_UNMEASURED),
+            cfg.noise_gate,
+        );
+        // Candidates and scores gathered from the old switch do not apply
+        // to the new one.
+        key.ac_confidence = 0;
+        key.ac_phase = AutoCalibPhase::Idle;
+        key.ac_unscored = 0;
+    }
+    signal_key(led, CalibPhase::Done).await;
+    seen_min.is_some()
 }
 
 /// Phase A of full-travel calibration: drive the per-key
@@ -328,6 +495,59 @@ pub(super) async fn sample_full_raw<const ROW: usize, const COL: usize>(
     min_raw
 }
 
+/// Full-travel pass of [`recalibrate_key`]: wait up to `duration` for the
+/// key at `pos` to go through the Waiting → Holding → Accepted state
+/// machine of the guided calibration, turning `led` green on acceptance,
+/// then keep sampling for [`CALIB_SETTLE_AFTER_ALL_DONE`] so the key can
+/// settle at its true bottom.
+///
+/// Returns the deepest reading seen, or `None` if the key was never
+/// accepted.
+async fn sample_key_full<const ROW: usize, const COL: usize>(
+    cols: &mut Hc164Cols<'_>,
+    seq: &mut ConfiguredSequence<'_, adc::Adc>,
+    buf: &mut [u16; ROW],
+    zero: u16,
+    (row, col): (usize, usize),
+    led: Option<u8>,
+    duration: Duration,
+) -> Option<u16> {
+    let hold_duration = Duration::from_millis(CALIB_HOLD_DURATION_MS);
+    let mut deadline = Instant::now().saturating_add(duration);
+    let mut key_state = KeyCalibState::Waiting;
+    let mut seen_min = u16::MAX;
+
+    while Instant::now() < deadline {
+        scan_pass(cols, seq, buf, COL, |scan_col, readings| {
+            if scan_col != col {
+                return;
+            }
+            let Some(&raw) = readings.get(row) else {
+                return;
+            };
+            seen_min = seen_min.min(raw);
+            let pressed = zero.saturating_sub(raw) >= CALIB_PRESS_THRESHOLD;
+
+            match key_state {
+                KeyCalibState::Waiting if pressed => key_state = KeyCalibState::Holding(Instant::now()),
+                KeyCalibState::Holding(_) if !pressed => key_state = KeyCalibState::Waiting,
+                KeyCalibState::Holding(first_seen) if first_seen.elapsed() >= hold_duration => {
+                    key_state = KeyCalibState::Accepted;
+                    // Replace the press window with the settle window.
+                    deadline = Instant::now().saturating_add(CALIB_SETTLE_AFTER_ALL_DONE);
+                    // Best-effort, as in `run_calib_press_phase`.
+                    if let Some(led_idx) = led {
+                        _ = BACKLIGHT_CH.sender().try_send(BacklightCmd::CalibKeyDone(led_idx));
+                    }
+                },
+                KeyCalibState::Accepted | KeyCalibState::Holding(_) | KeyCalibState::Waiting => {},
+            }
+        })
+        .await;
+    }
+    matches!(key_state, KeyCalibState::Accepted).then_some(seen_min)
+}
+
 /// Phase B of full-travel calibration: continue updating `min_raw` for
 /// `duration` without any state-machine work or backlight signaling, so
 /// each key has time to settle to its true bottom-out ADC.
@@ -363,3 +583,88 @@ fn min_into<const ROW: usize, const COL: usize>(min_raw: &mut [[u16; COL]; ROW],
         *cell = (*cell).min(raw);
     }
 }
+
+/// Send a [`recalibrate_key`] phase to the backlight, if the key has an
+/// LED to highlight.
+async fn signal_key(led: Option<u8>, phase: CalibPhase) {
+    if let Some(led) = led {
+        BACKLIGHT_CH.sender().send(BacklightCmd::CalibKey { led, phase }).await;
+    }
+}
+
+/// Serialize the calibration of `keys` into the slot not holding the
+/// `stored` block, verifying by read-back, and make it the current block;
+/// used by every calibration write.
+///
+/// The current block is never touched, so losing power mid-write leaves it
+/// in place for the next boot. Only the pages that differ from the older
+/// block already in the target slot are rewritten, sparing the EEPROM's
+/// write endurance. Returns `true`, with `stored` naming the new block, only
+/// when it read back valid; on failure `stored` is unchanged.
+pub(super) async fn store_calib<S, const ROW: usize, const COL: usize>(
+    eeprom: &mut S,
+    crc: &mut Crc<'_>,
+    keys: &mut [[KeyEntry; ROW]; COL],
+    stored: &mut Option<StoredCalib>,
+) -> bool
+where
+    S: Store,
+{
+    let mut eeprom_buf = [0_u8; CALIB_BUF_LEN];
+    let mut slot_buf = [0_u8; CALIB_BUF_LEN];
+    let next = StoredCalib::next(*stored);
+    calib_store::serialize(keys, next.writes, &mut eeprom_buf, crc);
+    // A slot that cannot be read is rewritten in full.
+    let old: &[u8] = if eeprom.read(next.slot.addr(), &mut slot_buf).await.is_ok() { &slot_buf } else { &[] };
+    // Verify by reading back into the same buffer and re-deserializing.
+    // Reusing the buffer avoids a second large stack allocation; the
+    // read-back decodes the values just serialized from `keys`, so
+    // deserializing into `keys` itself leaves them unchanged.
+    let verified = eeprom.write_changed(next.slot.addr(), &eeprom_buf, old).await.is_ok()
+        && eeprom.read(next.slot.addr(), &mut eeprom_buf).await.is_ok()
+        && try_deserialize::<ROW, COL>(&eeprom_buf, keys, crc).is_some();
+    if verified {
+        *stored = Some(next);
+    }
+    verified
+}
+
+/// Serialize the sensing settings `cfg` and write them to the settings
+/// record, verifying by read-back.
+///
+/// Returns `true` only when the record read back from the EEPROM validates;
+/// on failure the next boot may fall back to the built-in [`HallCfg`].
+pub(super) async fn store_settings<S>(eeprom: &mut S, crc: &mut Crc<'_>, cfg: HallCfg) -> bool
+where
+    S: Store,
+{
+    let mut settings_buf = [0_u8; SETTINGS_BUF_LEN];
+    settings_store::serialize(cfg, &mut settings_buf, crc);
+    eeprom.write(SETTINGS_BASE_ADDR, &settings_buf).await.is_ok()
+        && eeprom.read(SETTINGS_BASE_ADDR, &mut settings_buf).await.is_ok()
+        && settings_store::try_deserialize(&settings_buf, crc).is_some()
+}
+
+/// Serialize the per-key tuning `table` and the `board`-wide settings and
+/// write them to the tuning block, verifying by read-back.
+///
+/// Returns `true` only when the block read back from the EEPROM validates;
+/// on failure the previous block (if any) may be partially overwritten, in
+/// which case the next boot falls back to the [`HallCfg`] defaults.
+pub(super) async fn store_tuning<S, const ROW: usize, const COL: usize>(
+    eeprom: &mut S,
+    crc: &mut Crc<'_>,
+    table: &[[KeyTuning; ROW]; COL],
+    board: &BoardTuning,
+) -> bool
+where
+    S: Store,
+{
+    let mut tuning_buf = [0_u8; TUNING_BUF_LEN];
+    tuning_store::serialize(table, board, &mut tuning_buf, crc);
+    let mut readback = *table;
+    let mut readback_board = *board;
+    eeprom.write(TUNING_BASE_ADDR, &tuning_buf).await.is_ok()
+        && eeprom.read(TUNING_BASE_ADDR, &mut tuning_buf).await.is_ok()
+        && tuning_store::try_deserialize::<ROW, COL>(&tuning_buf, &mut readback, &mut readback_board, crc)
+}
@@ -0,0 +1,146 @@
+//! Dynamic Keystroke (DKS): up to four keymap actions per key, each bound to
+//! the four depth events of a stroke.
+//!
+//! A DKS key stops emitting its own matrix position. Instead it is assigned
+//! one of [`DKS_SLOTS`] slots; each slot owns [`DKS_ACTIONS`] virtual keymap
+//! positions in the rows past the physical matrix (see
+//! [`crate::layout::KEYMAP_ROW`]), so the actions themselves are ordinary RMK
+//! key actions, remappable through rynk like any other key. The slot's
+//! [`DksMap`] says what each action does on each [`DksEvent`].
+
+use crate::layout::{COL, DKS_ROWS, ROW};
+
+/// Number of actions per DKS slot.
+pub const DKS_ACTIONS: u8 = 4;
+/// Number of depth events per stroke; the length of [`DksEvent::ALL`].
+const DKS_EVENTS: u8 = 4;
+/// Number of DKS slots: as many whole groups of [`DKS_ACTIONS`] positions as
+/// fit in the virtual keymap rows.
+pub const DKS_SLOTS: usize = DKS_ROWS.saturating_mul(COL).checked_div(usize::from(DKS_ACTIONS)).unwrap_or(0);
+
+/// Depth at which a DKS key counts as bottomed out, in 0.05 mm configuration
+/// units (3.6 mm).
+pub const DKS_BOTTOM_UNIT: u8 = 72;
+/// Distance a bottomed-out DKS key must rise before "release from bottom"
+/// fires, in 0.05 mm configuration units, so bottom-of-travel jitter cannot
+/// chatter between the two bottom events.
+pub const DKS_BOTTOM_HYST_UNIT: u8 = 4;
+
+/// What one DKS action does when one event fires.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DksBehavior {
+    /// Leave the action as it is.
+    Nothing,
+    /// Press and hold the action.
+    Press,
+    /// Release the action.
+    Release,
+    /// Press and immediately release the action.
+    Tap,
+}
+
+/// The four depth events of a DKS stroke.
+#[derive(Clone, Copy)]
+pub enum DksEvent {
+    /// The key reached [`DKS_BOTTOM_UNIT`].
+    BottomOut,
+    /// The key was pressed past its actuation point.
+    PressPastActuation,
+    /// The key rose [`DKS_BOTTOM_HYST_UNIT`] back off the bottom.
+    ReleaseFromBottom,
+    /// The key rose back above its actuation point.
+    ReleasePastActuation,
+}
+
+impl DksEvent {
+    /// Every event in stroke order. Events that fire in the same scan step
+    /// are always handled in this order, which is also their chronological
+    /// order: a key cannot pass the same zone boundary both ways in one step.
+    pub const ALL: [Self; 4] =
+        [Self::PressPastActuation, Self::BottomOut, Self::ReleaseFromBottom, Self::ReleasePastActuation];
+
+    /// Bit of this event in the fired-event mask returned by
+    /// [`crate::matrix::analog_matrix::types::KeyEntry::step_dks`].
+    #[must_use]
+    pub const fn bit(self) -> u8 { 1_u8.checked_shl(u32::from(self.index())).unwrap_or(0) }
+
+    /// Position of this event in [`DksEvent::ALL`].
+    #[must_use]
+    pub const fn index(self) -> u8 {
+        match self {
+            Self::PressPastActuation => 0,
+            Self::BottomOut => 1,
+            Self::ReleaseFromBottom => 2,
+            Self::ReleasePastActuation => 3,
+        }
+    }
+}
+
+/// Per-slot binding table: a 2-bit [`DksBehavior`] for every
+/// (action, event) pair, packed as `bits[(action * 4 + event) * 2..][..2]`
+/// (event numbered by [`DksEvent::index`]) with `0` = nothing, `1` = press,
+/// `2` = release, `3` = tap.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DksMap(pub u32);
+
+impl DksMap {
+    /// Default binding: action 0 pressed at the actuation point and released
+    /// when travel falls back above it, so a DKS key with an untouched slot
+    /// behaves like a plain key.
+    pub const DEFAULT: Self = Self(0b1000_0001);
+
+    /// Behavior of `action` when `event` fires.
+    #[must_use]
+    pub const fn behavior(self, action: u8, event: DksEvent) -> DksBehavior {
+        let pair = action.saturating_mul(DKS_EVENTS).saturating_add(event.index());
+        let shift = u32::from(pair).saturating_mul(2);
+        match self.0.checked_shr(shift).unwrap_or(0) & 0b11 {
+            1 => DksBehavior::Press,
+            2 => DksBehavior::Release,
+            3 => DksBehavior::Tap,
+            _ => DksBehavior::Nothing,
+        }
+    }
+}
+
+/// A key's resolved DKS assignment, carried in its hot-path tuning so the
+/// scan loop needs no slot table lookup.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DksBinding {
+    /// Bindings of the assigned slot.
+    pub map:  DksMap,
+    /// Assigned slot, `0..DKS_SLOTS`.
+    pub slot: u8,
+}
+
+/// Depth zone of a DKS key between scan passes; the zone transitions are
+/// what fire [`DksEvent`]s.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum DksZone {
+    /// At or past [`DKS_BOTTOM_UNIT`].
+    Bottom,
+    /// Between the actuation point and the bottom-out depth.
+    Middle,
+    /// Above the actuation point.
+    #[default]
+    Top,
+}
+
+/// Keymap position (`row`, `col`) of `action` in DKS `slot`, or `None` if
+/// either is out of range.
+///
+/// Slots are laid out row-major across the virtual rows that follow the
+/// physical matrix, [`DKS_ACTIONS`] consecutive positions per slot.
+#[must_use]
+pub const fn position(slot: u8, action: u8) -> Option<(u8, u8)> {
+    if usize::from(slot) >= DKS_SLOTS || action >= DKS_ACTIONS {
+        return None;
+    }
+    let flat = usize::from(slot).saturating_mul(usize::from(DKS_ACTIONS)).saturating_add(usize::from(action));
+    let Some(row_offset) = flat.checked_div(COL) else { return None };
+    let Some(col) = flat.checked_rem(COL) else { return None };
+    match (u8::try_from(ROW.saturating_add(row_offset)), u8::try_from(col)) {
+        (Ok(row), Ok(col)) => Some((row, col)),
+        _ => None,
+    }
+}
@@ -0,0 +1,117 @@
+//! Optional per-key filtering of raw readings ahead of the noise gate.
+//!
+//! A noisy key can trade a little latency for steadier travel: an
+//! exponential moving average smooths every reading, and a three-sample
+//! median rejects single-sample spikes while following steps within two
+//! readings. Both run in a handful of integer operations so the pipelined
+//! scan stays inside its DMA window; [`KeyFilter::Off`] passes the reading
+//! through untouched for zero-latency keys.
+
+/// Fractional bits of the exponential moving average accumulator.
+const EMA_FRAC_BITS: u32 = 4;
+
+/// Strongest selectable moving-average smoothing (weight `1/16` per
+/// reading).
+pub const EMA_STRENGTH_MAX: u8 = 4;
+
+/// Filter applied to a key's raw readings.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyFilter {
+    /// Exponential moving average giving each new reading a weight of
+    /// `1 / 2^strength`, `strength` in `1..=EMA_STRENGTH_MAX`.
+    Ema(u8),
+    /// Median of the last three readings.
+    Median,
+    /// No filtering.
+    #[default]
+    Off,
+}
+
+impl KeyFilter {
+    /// Wire code of this filter, as stored in the EEPROM tuning block and
+    /// sent to the host: `0` off, `1` median, `1 + strength` for the moving
+    /// average.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Off => 0,
+            Self::Median => 1,
+            Self::Ema(strength) => strength.saturating_add(1),
+        }
+    }
+
+    /// Decode a wire code produced by [`KeyFilter::code`].
+    #[must_use]
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Off),
+            1 => Some(Self::Median),
+            _ => match code.checked_sub(1) {
+                Some(strength) if strength <= EMA_STRENGTH_MAX => Some(Self::Ema(strength)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Per-key filter history.
+#[derive(Clone, Copy, Default)]
+pub struct FilterState {
+    /// Moving-average accumulator with [`EMA_FRAC_BITS`] fractional bits;
+    /// `0` until seeded by the first reading.
+    ema:     u32,
+    /// The two previous readings, newest first; `0` until seeded.
+    history: [u16; 2],
+}
+
+impl FilterState {
+    /// Filter `raw` through `filter`, updating the history.
+    ///
+    /// The first reading after a [`FilterState::reset`] seeds the history
+    /// and passes through unchanged, so switching filters never drags the
+    /// key from a stale value.
+    #[inline]
+    #[optimize(speed)]
+    pub const fn apply(&mut self, filter: KeyFilter, raw: u16) -> u16 {
+        match filter {
+            KeyFilter::Off => raw,
+            KeyFilter::Ema(strength) => {
+                let sample = u32::from(raw).wrapping_shl(EMA_FRAC_BITS);
+                if self.ema == 0 {
+                    self.ema = sample;
+                } else if sample >= self.ema {
+                    self.ema =
+                        self.ema.saturating_add(sample.saturating_sub(self.ema).wrapping_shr(u32::from(strength)));
+                } else {
+                    self.ema =
+                        self.ema.saturating_sub(self.ema.saturating_sub(sample).wrapping_shr(u32::from(strength)));
+                }
+                let half = 1_u32.wrapping_shl(EMA_FRAC_BITS.saturating_sub(1));
+                match u16::try_from(self.ema.saturating_add(half).wrapping_shr(EMA_FRAC_BITS)) {
+                    Ok(filtered) => filtered,
+                    Err(_) => raw,
+                }
+            },
+            KeyFilter::Median => {
+                let [prev, prev2] = if self.history[0] == 0 { [raw, raw] } else { self.history };
+                self.history = [raw, prev];
+                median3(raw, prev, prev2)
+            },
+        }
+    }
+
+    /// Forget the history, e.g. after the key's filter changed.
+    pub const fn reset(&mut self) { *self = Self { ema: 0, history: [0; 2] }; }
+}
+
+/// Median of three readings.
+const fn median3(a: u16, b: u16, c: u16) -> u16 {
+    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+    if c < lo {
+        lo
+    } else if c > hi {
+        hi
+    } else {
+        c
+    }
+}
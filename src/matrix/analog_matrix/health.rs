@@ -0,0 +1,114 @@
+//! Per-key sensor health, derived on request from the calibration state.
+//!
+//! A position with a physical sensor can fail quietly: the boot zero pass
+//! disables it when its resting reading is implausible, and a marginal
+//! sensor keeps scanning with a range or noise figure that makes it feel
+//! wrong. [`KeyHealth::of`] collects the evidence the scanner already keeps
+//! into one set of fault flags for the host, and [`fault_leds`] marks the
+//! affected keys for the backlight. Nothing here runs on the scan path.
+
+use crate::{
+    layout::{MATRIX_TO_LED, VALID_ROWS_BY_COL},
+    matrix::analog_matrix::types::{KeyEntry, NOISE_UNMEASURED, ZERO_TRAVEL_DEAD_ZONE},
+};
+
+/// Largest reading of the 12-bit ADC.
+const ADC_MAX: u16 = 4095;
+
+/// Deep presses without the auto-calibrator ever reaching
+/// [`crate::matrix::analog_matrix::types::AUTO_CALIB_CONFIDENCE_THRESHOLD`]
+/// after which the key is reported as not converging; a healthy key gets
+/// there within a handful of presses.
+pub const AUTO_CALIB_STALL_PRESSES: u8 = 32;
+
+/// Smallest calibrated range (zero - full) in ADC counts a healthy key
+/// shows; a genuine full press spans ~900 counts.
+pub const HEALTH_MIN_RANGE: u16 = 600;
+
+/// Resting noise, in 1/16 ADC counts, above which a key is reported as
+/// noisy (a standard deviation of 12 counts, where even the widest derived
+/// noise gate stops covering the noise).
+pub const NOISE_EXCESSIVE: u16 = 192;
+
+/// Distance in ADC counts from either end of the ADC scale within which a
+/// resting reading counts as stuck at the rail.
+pub const RAIL_MARGIN: u16 = 64;
+
+/// Fault flags of one key; empty for a healthy key.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyHealth {
+    /// One bit per fault; see the associated masks.
+    bits: u8,
+}
+
+impl KeyHealth {
+    /// The boot zero pass found no plausible resting reading, so the key is
+    /// excluded from scanning.
+    pub const DISABLED: u8 = 0b0000_0001;
+    /// The resting noise exceeds [`NOISE_EXCESSIVE`].
+    pub const NOISY: u8 = 0b0000_0100;
+    /// [`AUTO_CALIB_STALL_PRESSES`] deep presses were never scored with
+    /// enough confidence to refine the calibration.
+    pub const NOT_CONVERGING: u8 = 0b0001_0000;
+    /// The calibrated range is below [`HEALTH_MIN_RANGE`].
+    pub const RANGE_TOO_SMALL: u8 = 0b0000_1000;
+    /// The resting reading sits at an end of the ADC scale: a shorted or
+    /// open sensor rather than a mis-seated one.
+    pub const STUCK_AT_RAIL: u8 = 0b0000_0010;
+
+    /// Wire form: the fault masks or-ed together.
+    #[must_use]
+    pub const fn bits(self) -> u8 { self.bits }
+
+    /// Whether no fault is flagged.
+    #[must_use]
+    pub const fn is_healthy(self) -> bool { self.bits == 0 }
+
+    /// Assess `key`. The range and convergence checks only apply to a key
+    /// that is scanning, since a disabled key has neither.
+    #[must_use]
+    pub const fn of(key: &KeyEntry) -> Self {
+        let mut bits = 0;
+        if !key.calib_used {
+            bits |= Self::DISABLED;
+        }
+        // `boot_zero` already has the dead zone taken off the average.
+        let rail_high = ADC_MAX.saturating_sub(ZERO_TRAVEL_DEAD_ZONE).saturating_sub(RAIL_MARGIN);
+        if key.boot_zero <= RAIL_MARGIN || key.boot_zero >= rail_high {
+            bits |= Self::STUCK_AT_RAIL;
+        }
+        if key.noise != NOISE_UNMEASURED && key.noise > NOISE_EXCESSIVE {
+            bits |= Self::NOISY;
+        }
+        if key.calib_used {
+            if key.calib_zero.saturating_sub(key.entry_full) < HEALTH_MIN_RANGE {
+                bits |= Self::RANGE_TOO_SMALL;
+            }
+            if key.ac_unscored >= AUTO_CALIB_STALL_PRESSES {
+                bits |= Self::NOT_CONVERGING;
+            }
+        }
+        Self { bits }
+    }
+}
+
+/// LED bitset (bit `i` for LED `i`) of every sensor position whose health
+/// shows a fault.
+#[must_use]
+pub fn fault_leds<const ROW: usize, const COL: usize>(keys: &[[KeyEntry; ROW]; COL]) -> u128 {
+    let mut leds = 0_u128;
+    for (col, (key_col, valid)) in keys.iter().zip(VALID_ROWS_BY_COL.iter()).enumerate() {
+        for &row_u8 in valid.valid_rows() {
+            let row = usize::from(row_u8);
+            if let Some(key) = key_col.get(row)
+                && !KeyHealth::of(key).is_healthy()
+                && let Some(led_row) = MATRIX_TO_LED.get(row)
+                && let Some(&Some(led_idx)) = led_row.get(col)
+                && let Some(bit) = 1_u128.checked_shl(u32::from(led_idx))
+            {
+                leds |= bit;
+            }
+        }
+    }
+    leds
+}
@@ -7,14 +7,22 @@
 //! non-linearity so the *difference* between two transfer values is
 //! proportional to the linear distance between the two raw readings.
 //!
-//! The transfer function is the cubic Hall-sensor travel polynomial from
+//! The transfer function depends on the switch and magnet, so each
+//! [`SwitchProfile`] carries its own cubic travel polynomial and table, and
+//! every key selects one at runtime. The stock profile is the cubic from
 //! Keychron's stock firmware (`CONST_A1..=CONST_D1` in their QMK source):
 //!
 //! ```text
 //! travel(x) = 426.88962 - 0.48358*x + 2.04637e-4*x^2 - 2.99368e-8*x^3
 //! ```
 //!
-//! `TRAVEL_LUT` is generated **at compile time** from those coefficients,
+//! Adding a profile for another switch (Gateron, TTC, ...) means fitting a
+//! cubic to that part's raw-versus-distance curve over the valid raw range,
+//! adding its [`TransferPoly`] and a [`SwitchProfile`] variant; any
+//! monotone-decreasing fit works, since only differences of table values
+//! reach the travel arithmetic.
+//!
+//! Each table is generated **at compile time** from its coefficients,
 //! sampled every `SPARSE_N` raw counts and stored as
 //! `round(256 * travel(raw) + 11008)`. The generator evaluates the
 //! polynomial in exact scaled-integer arithmetic (numerators over
@@ -30,7 +38,7 @@
 //! multiply-shift. The LUT's additive bias cancels in the subtraction, so
 //! it never has to be undone at runtime.
 //!
-//! Sizing: a 37-entry u16 table fits in 74 bytes of `.rodata`. The
+//! Sizing: a 37-entry u16 table fits in 74 bytes of `.rodata` per profile. The
 //! worst-case reconstruction error against the underlying cubic polynomial
 //! is 27 LUT counts (~0.126 travel units, ~0.013 mm, ~8x below the most
 //! sensitive rapid-trigger setting). A flat 2301-entry LUT that skipped
@@ -40,7 +48,7 @@
 //!
 //! Endpoint handling: the raw range (length `RAW_SPAN`) is not an exact
 //! multiple of `SPARSE_N`, so the final segment is shorter than
-//! `SPARSE_N` raw counts. The last entry in `TRAVEL_LUT` is *not* the
+//! `SPARSE_N` raw counts. The last entry of each table is *not* the
 //! polynomial evaluated at the next sample point; it is the value that
 //! makes the regular linear-interpolation formula at `raw = VALID_RAW_MAX`
 //! match `travel(VALID_RAW_MAX)` exactly. That extrapolation keeps the
@@ -54,7 +62,7 @@ pub const VALID_RAW_MAX: u16 = 3500;
 /// Minimum acceptable raw ADC value (table origin).
 pub const VALID_RAW_MIN: u16 = 1200;
 
-/// Number of raw ADC counts between adjacent samples in [`TRAVEL_LUT`].
+/// Number of raw ADC counts between adjacent samples in each table.
 ///
 /// Power-of-two so the `checked_shr` and `checked_rem` calls on the hot
 /// path collapse to a single-cycle shift and AND-mask under LLVM's
@@ -91,30 +99,15 @@ const RAW_SPAN: usize = usize::from(VALID_RAW_MAX).saturating_sub(usize::from(VA
 /// partial and its upper anchor is extrapolated (see [`build_travel_lut`]).
 const FINAL_FRAC: usize = RAW_SPAN.checked_rem(SPARSE_N).unwrap_or(0);
 
-/// Number of entries in [`TRAVEL_LUT`].
+/// Number of entries in each profile's table.
 ///
 /// One sample at every multiple of [`SPARSE_N`] in `0..=RAW_SPAN`, plus
 /// one trailing entry that the interpolation in [`lookup`] uses as the
 /// upper anchor of the final (possibly partial) segment.
 const TRAVEL_LUT_LEN: usize = RAW_SPAN.div_ceil(SPARSE_N).saturating_add(1);
 
-/// Numerator of the stock polynomial's constant coefficient over
-/// [`POLY_SCALE`]: `426.88962` (Keychron `CONST_A1`).
-const POLY_A_NUM: i64 = 4_268_896_200_000_000;
-
-/// Numerator of the stock polynomial's linear coefficient over
-/// [`POLY_SCALE`]: `-0.48358` (Keychron `CONST_B1`).
-const POLY_B_NUM: i64 = -4_835_800_000_000;
-
-/// Numerator of the stock polynomial's quadratic coefficient over
-/// [`POLY_SCALE`]: `2.04637e-4` (Keychron `CONST_C1`).
-const POLY_C_NUM: i64 = 2_046_370_000;
-
-/// Numerator of the stock polynomial's cubic coefficient over
-/// [`POLY_SCALE`]: `-2.99368e-8` (Keychron `CONST_D1`).
-const POLY_D_NUM: i64 = -299_368;
-
-/// Common denominator of the `POLY_*_NUM` coefficient numerators (`10^13`).
+/// Common denominator of the [`TransferPoly`] coefficient numerators
+/// (`10^13`).
 ///
 /// Large enough to represent every coefficient's decimal expansion exactly,
 /// so the compile-time evaluation is exact rational arithmetic rather than
@@ -134,14 +127,97 @@ const LUT_BIAS: i64 = 11008;
 /// fractional bits), giving the table sub-travel-unit resolution.
 const LUT_FRAC_SCALE: i64 = 256;
 
-/// Sparse Hall-sensor transfer-function table, generated at compile time by
-/// [`build_travel_lut`] from the stock-firmware polynomial coefficients.
+/// Travel polynomial of the stock Keychron switch and magnet (Keychron
+/// `CONST_A1..=CONST_D1`).
+const STOCK_POLY: TransferPoly =
+    TransferPoly { a: 4_268_896_200_000_000, b: -4_835_800_000_000, c: 2_046_370_000, d: -299_368 };
+
+/// Straight-line travel polynomial through the stock curve's end points, for
+/// switches whose sensor output is close to linear in distance.
+const LINEAR_POLY: TransferPoly = TransferPoly { a: 1_583_666_500_000_000, b: -573_555_000_000, c: 0, d: 0 };
+
+/// Sparse transfer-function table of [`SwitchProfile::KeychronStock`],
+/// generated at compile time by [`build_travel_lut`].
 ///
-/// `TRAVEL_LUT[i] = round(256 * travel(VALID_RAW_MIN + i * SPARSE_N) +
+/// `STOCK_LUT[i] = round(256 * travel(VALID_RAW_MIN + i * SPARSE_N) +
 /// 11008)` for every entry but the last. The final entry is the
 /// extrapolated upper anchor of the partial last segment; see the
-/// module-level doc for why.
-const TRAVEL_LUT: [u16; TRAVEL_LUT_LEN] = build_travel_lut();
+/// module-level doc for why. Every other profile's table follows the same
+/// layout.
+const STOCK_LUT: [u16; TRAVEL_LUT_LEN] = build_travel_lut(STOCK_POLY);
+
+/// Sparse transfer-function table of [`SwitchProfile::Linear`].
+const LINEAR_LUT: [u16; TRAVEL_LUT_LEN] = build_travel_lut(LINEAR_POLY);
+
+/// A cubic travel polynomial, each coefficient stored as its numerator over
+/// [`POLY_SCALE`] so the compile-time evaluation is exact.
+#[derive(Clone, Copy)]
+struct TransferPoly {
+    /// Constant coefficient numerator.
+    a: i64,
+    /// Linear coefficient numerator.
+    b: i64,
+    /// Quadratic coefficient numerator.
+    c: i64,
+    /// Cubic coefficient numerator.
+    d: i64,
+}
+
+/// Switch and magnet combination a key is fitted with, selecting the
+/// transfer-function table its travel is computed from.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum SwitchProfile {
+    /// The switch and magnet Keychron ships with the Q6 HE.
+    #[default]
+    KeychronStock,
+    /// A sensor whose raw reading is close to linear in distance.
+    Linear,
+}
+
+impl SwitchProfile {
+    /// Wire code of this profile, as stored with the calibration and sent to
+    /// the host. Fits in four bits.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::KeychronStock => 0,
+            Self::Linear => 1,
+        }
+    }
+
+    /// Decode a wire code produced by [`SwitchProfile::code`].
+    #[must_use]
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::KeychronStock),
+            1 => Some(Self::Linear),
+            _ => None,
+        }
+    }
+
+    /// Transfer-function table of this profile.
+    const fn table(self) -> &'static [u16; TRAVEL_LUT_LEN] {
+        match self {
+            Self::KeychronStock => &STOCK_LUT,
+            Self::Linear => &LINEAR_LUT,
+        }
+    }
+}
+
+/// Compile-time check that every profile's table is non-increasing, which
+/// [`lookup`] relies on for its `lo - hi` segment difference.
+const _: () = {
+    let tables = [&STOCK_LUT, &LINEAR_LUT];
+    let mut t = 0_usize;
+    while let Some(table) = tables.get(t) {
+        let mut i = 1_usize;
+        while let (Some(&prev), Some(&next)) = (table.get(i.saturating_sub(1)), table.get(i)) {
+            assert!(next <= prev, "transfer-function table is not monotone-decreasing");
+            i = i.saturating_add(1);
+        }
+        t = t.saturating_add(1);
+    }
+};
 
 /// Compile-time regression pin: the generated table must be bit-identical
 /// to the hand-generated table the firmware shipped with before the
@@ -160,31 +236,33 @@ const _: () = {
     assert!(TRAVEL_LUT_LEN == EXPECTED.len(), "sampling parameters changed; delete or regenerate this pin");
     let mut i = 0_usize;
     while i < EXPECTED.len() {
-        let generated = if let Some(&val) = TRAVEL_LUT.get(i) { val } else { 0 };
+        let generated = if let Some(&val) = STOCK_LUT.get(i) { val } else { 0 };
         let expected = if let Some(&val) = EXPECTED.get(i) { val } else { u16::MAX };
-        assert!(generated == expected, "generated TRAVEL_LUT entry diverges from the shipped table");
+        assert!(generated == expected, "generated STOCK_LUT entry diverges from the shipped table");
         i = i.saturating_add(1);
     }
 };
 
-/// Evaluate one direct table sample: `round(256 * travel(raw) + 11008)`.
+/// Evaluate one direct table sample of `poly`: `round(256 * travel(raw) +
+/// 11008)`.
 ///
-/// The polynomial is evaluated with Horner's rule on the `POLY_*_NUM`
+/// The polynomial is evaluated with Horner's rule on the coefficient
 /// numerators, keeping everything in exact integer arithmetic over
 /// [`POLY_SCALE`]. The result is rounded half-up; the closest value in the
 /// valid domain sits ~0.009 from a rounding boundary, so no tie-breaking
 /// subtleties arise. The saturating operators can never actually saturate
 /// over the valid domain (see [`POLY_SCALE`]); they exist to keep the
 /// arithmetic total.
-const fn lut_sample(raw: u16) -> u16 {
+const fn lut_sample(poly: TransferPoly, raw: u16) -> u16 {
     let x = i64::from(raw);
-    let num = POLY_D_NUM
+    let num = poly
+        .d
         .saturating_mul(x)
-        .saturating_add(POLY_C_NUM)
+        .saturating_add(poly.c)
         .saturating_mul(x)
-        .saturating_add(POLY_B_NUM)
+        .saturating_add(poly.b)
         .saturating_mul(x)
-        .saturating_add(POLY_A_NUM);
+        .saturating_add(poly.a);
     let scaled = num.saturating_mul(LUT_FRAC_SCALE).saturating_add(LUT_BIAS.saturating_mul(POLY_SCALE));
     // Round half-up, then drop the scale. `scaled` is positive across the
     // valid raw domain thanks to LUT_BIAS, so the floor division of the
@@ -194,7 +272,7 @@ const fn lut_sample(raw: u16) -> u16 {
     u16::try_from(rounded).unwrap_or(u16::MAX)
 }
 
-/// Build [`TRAVEL_LUT`] at compile time.
+/// Build the table of `poly` at compile time.
 ///
 /// Every entry whose sample offset lies inside the raw span is a direct
 /// [`lut_sample`] of the polynomial. When the span is not an exact multiple
@@ -204,7 +282,7 @@ const fn lut_sample(raw: u16) -> u16 {
 /// `lut_sample(VALID_RAW_MAX)` exactly: the smallest segment slope `d`
 /// satisfying `(d * FINAL_FRAC + SPARSE_N/2) >> log2(SPARSE_N) ==
 /// lo - target` is subtracted from the last direct sample.
-const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
+const fn build_travel_lut(poly: TransferPoly) -> [u16; TRAVEL_LUT_LEN] {
     let mut table = [0_u16; TRAVEL_LUT_LEN];
     let mut i = 0_usize;
     while i < TRAVEL_LUT_LEN {
@@ -213,12 +291,12 @@ const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
             && let Ok(off_u16) = u16::try_from(offset)
             && let Some(slot) = table.get_mut(i)
         {
-            *slot = lut_sample(VALID_RAW_MIN.saturating_add(off_u16));
+            *slot = lut_sample(poly, VALID_RAW_MIN.saturating_add(off_u16));
         }
         i = i.saturating_add(1);
     }
     if FINAL_FRAC != 0 {
-        let target = lut_sample(VALID_RAW_MAX);
+        let target = lut_sample(poly, VALID_RAW_MAX);
         let lo = if let Some(&prev) = table.get(TRAVEL_LUT_LEN.saturating_sub(2)) { prev } else { target };
         // `delta` is the interpolation result the anchor must produce at
         // segment position FINAL_FRAC; the polynomial is monotone
@@ -240,8 +318,8 @@ const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
     table
 }
 
-/// Look up the LUT-equivalent value for ADC reading `raw` via linear
-/// interpolation between the two nearest sparse samples.
+/// Look up the LUT-equivalent value for ADC reading `raw` in the table of
+/// `profile` via linear interpolation between the two nearest sparse samples.
 ///
 /// Algorithm, all done in safe integer arithmetic:
 ///
@@ -252,10 +330,10 @@ const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
 ///    split the offset into the segment index and the in-segment position. Both
 ///    compile to a single shift / AND-mask under LLVM strength reduction
 ///    because `SPARSE_N` is a compile-time power of two.
-/// 3. Read the segment endpoints `lo = TRAVEL_LUT[idx]` and `hi =
-///    TRAVEL_LUT[idx+1]` through `.get(...).copied().unwrap_or(...)`. The clamp
-///    from step 1 guarantees both indices are in range; the fallbacks are
-///    defensive no-ops the optimiser folds away.
+/// 3. Read the segment endpoints `lo = table[idx]` and `hi = table[idx+1]`
+///    through `.get(...).copied().unwrap_or(...)`. The clamp from step 1
+///    guarantees both indices are in range; the fallbacks are defensive no-ops
+///    the optimiser folds away.
 /// 4. The polynomial is monotone-decreasing, so `lo >= hi`. The signed
 ///    difference is therefore `lo - hi` (a u16) and the interpolated value is
 ///    `lo - (diff * frac + N/2) / N`, computed in u32 to avoid intermediate
@@ -263,7 +341,7 @@ const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
 ///    truncation can add ~half a count of bias.
 ///
 /// Saturating behaviour: readings below [`VALID_RAW_MIN`] resolve to
-/// `TRAVEL_LUT[0]` (the deep-press maximum); readings above
+/// `table[0]` (the deep-press maximum); readings above
 /// [`VALID_RAW_MAX`] resolve to the upper anchor of the final segment
 /// (which equals `travel(VALID_RAW_MAX)` after interpolation thanks to the
 /// endpoint adjustment baked into the table). Hot-path callers always
@@ -271,12 +349,13 @@ const fn build_travel_lut() -> [u16; TRAVEL_LUT_LEN] {
 #[must_use]
 #[inline]
 #[optimize(speed)]
-pub const fn lookup(raw: u16) -> u16 {
+pub const fn lookup(profile: SwitchProfile, raw: u16) -> u16 {
+    let table = profile.table();
     let raw_off = usize::from(raw.saturating_sub(VALID_RAW_MIN)).min(RAW_SPAN);
     let idx = raw_off.checked_shr(SPARSE_N_LOG2).unwrap_or(0);
     let frac = raw_off.checked_rem(SPARSE_N).unwrap_or(0);
-    let lo = TRAVEL_LUT.get(idx).copied().unwrap_or(0);
-    let hi = TRAVEL_LUT.get(idx.saturating_add(1)).copied().unwrap_or(lo).min(lo);
+    let lo = table.get(idx).copied().unwrap_or(0);
+    let hi = table.get(idx.saturating_add(1)).copied().unwrap_or(lo).min(lo);
     let diff = u32::from(lo.saturating_sub(hi));
     let frac_u32 = u32::try_from(frac).unwrap_or(u32::MAX);
     let contrib = diff.saturating_mul(frac_u32).saturating_add(SPARSE_N_HALF).checked_shr(SPARSE_N_LOG2).unwrap_or(0);
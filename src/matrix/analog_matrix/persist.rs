@@ -0,0 +1,71 @@
+//! Background persistence of auto-calibration refinements.
+//!
+//! [`KeyEntry::auto_calib_step`] refines each key's full-travel reading while
+//! the user types, but only a calibration write carries the refinement across
+//! a reboot. Every [`PERSIST_INTERVAL`] the scanner hands control back to the
+//! matrix between two passes with no key held, and the matrix rewrites the
+//! calibration block if any key's [`KeyEntry::entry_full`] has moved more than
+//! [`PERSIST_FULL_DRIFT`] from the stored value.
+//!
+//! The FT24C64 is rated for a million write cycles per page. A write goes to
+//! the slot not holding the current block, so the two slots take turns, and
+//! rewrites only the pages that differ from what that slot already holds. At
+//! no more than three writes an hour each page sees at most one and a half
+//! cycles an hour, far beyond the life of the keyboard even while typing
+//! around the clock.
+
+use crate::matrix::analog_matrix::types::KeyEntry;
+use core::array::from_fn;
+use embassy_time::{Duration, Instant};
+
+/// Drift in raw ADC counts of a key's full-travel reading from its stored
+/// value that makes the calibration worth rewriting.
+///
+/// About 2% of a typical ~1100-count travel range: well past the
+/// auto-calibrator's own commit jitter, so a key settling back and forth
+/// never triggers a write by itself.
+pub const PERSIST_FULL_DRIFT: u16 = 24;
+
+/// Interval between persistence checks, and so the shortest time between
+/// two background calibration writes: 20 minutes, at most three an hour.
+pub const PERSIST_INTERVAL: Duration = Duration::from_secs(1200);
+
+/// Schedule of the background persistence check, polled once per matrix
+/// pass.
+pub(super) struct PersistTimer {
+    /// Earliest time of the next check.
+    due: Instant,
+}
+
+impl PersistTimer {
+    /// Whether the check is due and no key is held, so the write it may
+    /// cause stalls scanning only while the keyboard is idle.
+    #[inline]
+    pub(super) fn is_due<const ROW: usize, const COL: usize>(&self, keys: &[[KeyEntry; ROW]; COL]) -> bool {
+        Instant::now() >= self.due && !keys.as_flattened().iter().any(|key| key.pressed)
+    }
+
+    /// First check one [`PERSIST_INTERVAL`] from now.
+    pub(super) fn new() -> Self { Self { due: Instant::now().saturating_add(PERSIST_INTERVAL) } }
+
+    /// Schedule the next check one [`PERSIST_INTERVAL`] from now.
+    pub(super) fn reschedule(&mut self) { self.due = Instant::now().saturating_add(PERSIST_INTERVAL); }
+}
+
+/// Whether any scanning key's [`KeyEntry::entry_full`] has drifted more than
+/// [`PERSIST_FULL_DRIFT`] from the value in `stored`.
+pub(super) fn drifted<const ROW: usize, const COL: usize>(
+    keys: &[[KeyEntry; ROW]; COL],
+    stored: &[[u16; ROW]; COL],
+) -> bool {
+    keys.as_flattened()
+        .iter()
+        .zip(stored.as_flattened())
+        .any(|(key, &full)| key.calib_used && key.entry_full.abs_diff(full) > PERSIST_FULL_DRIFT)
+}
+
+/// Each key's [`KeyEntry::entry_full`], taken when a calibration block is
+/// loaded or stored so [`drifted`] can measure against it.
+pub(super) fn full_snapshot<const ROW: usize, const COL: usize>(keys: &[[KeyEntry; ROW]; COL]) -> [[u16; ROW]; COL] {
+    from_fn(|col| from_fn(|row| keys.get(col).and_then(|key_col| key_col.get(row)).map_or(0, |key| key.entry_full)))
+}
@@ -10,13 +10,19 @@
 //! behind the DMA transfer that dominates the per-column budget.
 
 use crate::{
+    host::{MATRIX_CMD, MatrixCmd},
     layout::valid_readings,
     matrix::{
         analog_matrix::{
             AdcPart,
             RowChannels,
+            dks::{self, DKS_ACTIONS, DksBehavior, DksBinding, DksEvent},
+            persist::PersistTimer,
             scan_pass,
-            types::{AdcSampleTime, HallCfg, KeyEntry, RtTuning, VALID_RAW_MAX, VALID_RAW_MIN, coarse_ms_now},
+            socd,
+            stats::{self, ScanStats},
+            stream::TravelStream,
+            types::{AdcSampleTime, KeyEntry, Stranded, VALID_RAW_MAX, VALID_RAW_MIN, coarse_ms_now},
         },
         hc164_cols::Hc164Cols,
     },
@@ -67,21 +73,53 @@ const SUSPEND_DISCARD_PASSES: u8 = 3;
 /// user is actually holding does, so the host only wakes on a real press.
 const SUSPEND_CONFIRM_DELAY: Duration = Duration::from_millis(8);
 
-/// Process one column's ADC readings: noise-gate each populated row, advance
+/// Why [`run`] handed control back to the matrix.
+pub(super) enum ScanExit {
+    /// A host command arrived on [`MATRIX_CMD`].
+    Cmd(MatrixCmd),
+    /// The background calibration persistence check is due (see
+    /// [`PersistTimer`]).
+    Persist,
+}
+
+/// Scanner settings and state that outlive a single [`run`] call; owned by
+/// the matrix so host commands can change them between calls.
+pub(super) struct ScanState {
+    /// Schedule of the background calibration persistence check, polled
+    /// once per pass.
+    pub(super) persist: PersistTimer,
+    /// Scan-rate and latency statistics, updated by every pass.
+    pub(super) stats:   ScanStats,
+    /// Live per-key stream to the host, polled once per pass.
+    pub(super) stream:  TravelStream,
+}
+
+/// Process one column's ADC readings: filter each populated row's reading
+/// through [`KeyEntry::filter`], noise-gate it against its own
+/// [`KeyEntry::noise_gate`], advance
 /// the auto-calibrator, recompute travel, run the rapid-trigger state
-/// machine, and publish any press/release transitions via
-/// [`publish_event_async`].
+/// machine (or the Dynamic Keystroke tracker for DKS keys), and publish any
+/// press/release transitions via [`publish_event_async`].
+///
+/// SOCD-paired keys publish nothing directly: their travel changes are
+/// collected and resolved by [`publish_socd`] once the column is done, when
+/// the partner (possibly in another column) can be borrowed too.
 ///
 /// `buf` must hold the row readings sampled while `col` was selected.
 /// Columns with no sensors yield nothing from [`valid_readings`] and
-/// return without touching the key-state machine.
+/// return without touching the key-state machine. Each directly published
+/// transition (rapid trigger or Dynamic Keystroke) is timed from detection
+/// to publication into `stats`.
 #[optimize(speed)]
 async fn process_column<const ROW: usize, const COL: usize>(
     keys: &mut [[KeyEntry; ROW]; COL],
     buf: &[u16; ROW],
     col: usize,
-    tuning: RtTuning,
+    stats: &mut ScanStats,
 ) {
+    // Paired rows whose travel changed this pass, each with whether the
+    // change was the key's physical press.
+    let mut socd_pending: [Option<bool>; ROW] = [None; ROW];
     // valid_readings yields exactly the populated sensor positions (one
     // presence check per column instead of one per key); hoist keys[col]
     // out of the inner loop.
@@ -93,14 +131,17 @@ async fn process_column<const ROW: usize, const COL: usize>(
         // the whole column processes in microseconds.
         let mut now: Option<u32> = None;
         for (row_u8, raw_reading) in valid_readings(col, buf) {
-            // Clamp raw ADC value to valid range to prevent out-of-bounds
-            // LUT access and ensure valid calibration updates.
-            let raw = raw_reading.clamp(VALID_RAW_MIN, VALID_RAW_MAX);
-
             let Some(entry) = key_col.get_mut(usize::from(row_u8)) else { continue };
 
+            // Clamp raw ADC value to valid range to prevent out-of-bounds
+            // LUT access and ensure valid calibration updates, then run it
+            // through the key's filter (a pass-through when off). Everything
+            // downstream, including the auto-calibrator, sees the filtered
+            // reading.
+            let raw = entry.filter.apply(entry.rt.filter, raw_reading.clamp(VALID_RAW_MIN, VALID_RAW_MAX));
+
             // Skip if the reading has not changed beyond the noise gate.
-            if likely(entry.last_raw.abs_diff(raw) < tuning.noise_gate) {
+            if likely(entry.last_raw.abs_diff(raw) < entry.noise_gate) {
                 continue;
             }
 
@@ -108,6 +149,7 @@ async fn process_column<const ROW: usize, const COL: usize>(
             // are filtered by the noise gate even when travel_from later
             // returns None (uncalibrated or out-of-range position).
             entry.last_raw = raw;
+            entry.stream_dirty = true;
 
             // Update the auto-calibrator with this reading before the
             // travel computation so any refined calibration is used immediately.
@@ -121,12 +163,40 @@ async fn process_column<const ROW: usize, const COL: usize>(
                 continue;
             }
             entry.travel = new_travel;
+            if let Some(input) = entry.rt.gamepad {
+                input.set_travel(new_travel);
+            }
+            if let Some(input) = entry.rt.mouse {
+                input.set_travel(new_travel);
+            }
+
+            // Dynamic Keystroke keys publish their slot's virtual positions
+            // instead of their own.
+            if let Some(binding) = entry.rt.dks {
+                let fired = entry.step_dks(new_travel);
+                if fired != 0 {
+                    cold_path();
+                    let detected = stats::cycles();
+                    publish_dks(binding, fired).await;
+                    stats.record_publish(stats::cycles().wrapping_sub(detected));
+                }
+                continue;
+            }
 
             // Dynamic Rapid Trigger; only the transition path needs
             // to publish, so the common no-transition case stays in
             // the `None` arm.
-            if let Some(now_pressed) = entry.step_rapid_trigger(new_travel, tuning) {
+            let transition = entry.step_rapid_trigger(new_travel);
+            if entry.rt.socd.is_some() {
                 cold_path();
+                if let Some(pending) = socd_pending.get_mut(usize::from(row_u8)) {
+                    *pending = Some(transition == Some(true));
+                }
+                continue;
+            }
+            if let Some(now_pressed) = transition {
+                cold_path();
+                let detected = stats::cycles();
                 publish_event_async(KeyboardEvent::key(
                     row_u8,
                     // The matrix has 21 columns, so `col` always fits
@@ -136,15 +206,111 @@ async fn process_column<const ROW: usize, const COL: usize>(
                     now_pressed,
                 ))
                 .await;
+                stats.record_publish(stats::cycles().wrapping_sub(detected));
+            }
+        }
+    }
+    for (row, pending) in socd_pending.into_iter().enumerate() {
+        if let Some(just_pressed) = pending {
+            publish_socd(keys, row, col, just_pressed).await;
+        }
+    }
+}
+
+/// Publish the slot actions bound to every event in `fired` (a mask of
+/// [`DksEvent::bit`]s), events in [`DksEvent::ALL`] order and actions in
+/// slot order within each event.
+async fn publish_dks(binding: DksBinding, fired: u8) {
+    for event in DksEvent::ALL {
+        if fired & event.bit() == 0 {
+            continue;
+        }
+        for action in 0..DKS_ACTIONS {
+            let Some((row, col)) = dks::position(binding.slot, action) else { continue };
+            match binding.map.behavior(action, event) {
+                DksBehavior::Nothing => {},
+                DksBehavior::Press => publish_event_async(KeyboardEvent::key(row, col, true)).await,
+                DksBehavior::Release => publish_event_async(KeyboardEvent::key(row, col, false)).await,
+                DksBehavior::Tap => {
+                    publish_event_async(KeyboardEvent::key(row, col, true)).await;
+                    publish_event_async(KeyboardEvent::key(row, col, false)).await;
+                },
             }
         }
     }
 }
 
+/// Release the positions a tuning change took away from the key at
+/// `row`/`col` (see [`KeyEntry::apply_tuning`]). A DKS slot's actions are
+/// all released, since which of them its bindings left pressed is not
+/// tracked; releasing one that is up is harmless.
+pub(super) async fn release_stranded(stranded: Stranded, row: usize, col: usize) {
+    match stranded {
+        Stranded::Dks(binding) => {
+            for action in 0..DKS_ACTIONS {
+                if let Some((action_row, action_col)) = dks::position(binding.slot, action) {
+                    publish_event_async(KeyboardEvent::key(action_row, action_col, false)).await;
+                }
+            }
+        },
+        Stranded::Own => {
+            if let (Ok(row_u8), Ok(col_u8)) = (u8::try_from(row), u8::try_from(col)) {
+                publish_event_async(KeyboardEvent::key(row_u8, col_u8, false)).await;
+            }
+        },
+    }
+}
+
+/// Resolve the SOCD pair of the key at `row`/`col` after its travel changed
+/// and publish whichever outputs differ from what was last published.
+///
+/// When the outputs swap, the release is published before the press so the
+/// host never sees both directions held at once.
+async fn publish_socd<const ROW: usize, const COL: usize>(
+    keys: &mut [[KeyEntry; ROW]; COL],
+    row: usize,
+    col: usize,
+    just_pressed: bool,
+) {
+    let Some(entry) = keys.get(col).and_then(|key_col| key_col.get(row)) else { return };
+    let Some(link) = entry.rt.socd else { return };
+    let key = entry.socd_side();
+    let (partner_row, partner_col) = (usize::from(link.partner_row), usize::from(link.partner_col));
+    let Some(partner) = keys.get(partner_col).and_then(|key_col| key_col.get(partner_row)) else { return };
+    let (key_out, partner_out) = socd::resolve(link.mode, key, partner.socd_side(), just_pressed);
+    if key_out {
+        publish_socd_out(keys, partner_row, partner_col, partner_out).await;
+        publish_socd_out(keys, row, col, key_out).await;
+    } else {
+        publish_socd_out(keys, row, col, key_out).await;
+        publish_socd_out(keys, partner_row, partner_col, partner_out).await;
+    }
+}
+
+/// Publish `out` for the SOCD-paired key at `row`/`col` if it differs from
+/// [`KeyEntry::socd_out`], recording it there.
+async fn publish_socd_out<const ROW: usize, const COL: usize>(
+    keys: &mut [[KeyEntry; ROW]; COL],
+    row: usize,
+    col: usize,
+    out: bool,
+) {
+    let Some(entry) = keys.get_mut(col).and_then(|key_col| key_col.get_mut(row)) else { return };
+    if entry.socd_out == out {
+        return;
+    }
+    entry.socd_out = out;
+    if let (Ok(row_u8), Ok(col_u8)) = (u8::try_from(row), u8::try_from(col)) {
+        publish_event_async(KeyboardEvent::key(row_u8, col_u8, out)).await;
+    }
+}
+
 /// Pipelined full-rate scan body. Returns cleanly the moment the host
-/// suspends; `prev`/`prev_col` are local so each (re)entry after a resume
-/// starts a fresh pipeline rather than processing a column against stale,
-/// pre-suspend readings.
+/// suspends (`None`), a host command arrives on [`MATRIX_CMD`], or the
+/// persistence check falls due with no key held (`Some`);
+/// `prev`/`prev_col` are local so each (re)entry after a resume starts a
+/// fresh pipeline rather than processing a column against stale, pre-suspend
+/// readings.
 ///
 /// Suspend is detected *cooperatively*, by polling `UsbReceiver::try_get`
 /// once per matrix pass, rather than by letting the supervisor drop this
@@ -162,7 +328,11 @@ async fn process_column<const ROW: usize, const COL: usize>(
 /// The poll runs once per pass instead of once per column: that keeps the
 /// `Watch`'s critical-section lock off the per-column budget and delays
 /// suspend detection by at most one pass (~300 µs), which is noise against
-/// the multi-millisecond USB suspend timeline.
+/// the multi-millisecond USB suspend timeline. Host commands are polled at
+/// the same point for the same reason: handling one needs the EEPROM and the
+/// tuning table, which belong to the caller, so the pass completes first and
+/// the command is handed back up. The travel stream and the persistence
+/// check are polled there too, so neither splits a pass.
 ///
 /// Double-buffered: the first poll of [`ConfiguredSequence::read`] inside
 /// [`join`] arms the DMA transfer and starts the ADC sequence, then the
@@ -170,6 +340,10 @@ async fn process_column<const ROW: usize, const COL: usize>(
 /// proceeds in hardware. This hides the per-column processing window behind
 /// the DMA transfer, which development benchmarks measured dominating the
 /// per-column budget (~9.8 µs DMA versus ~3.5 µs processing).
+///
+/// Every pass and every column's processing is timed into
+/// [`ScanState::stats`]; the two cycle-counter reads per column stay well
+/// inside that window.
 #[optimize(speed)]
 async fn active_scan<const ROW: usize, const COL: usize>(
     cols: &mut Hc164Cols<'_>,
@@ -177,24 +351,36 @@ async fn active_scan<const ROW: usize, const COL: usize>(
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
     buf: &mut [u16; ROW],
     usb: &mut UsbReceiver,
-    tuning: RtTuning,
-) {
+    state: &mut ScanState,
+) -> Option<ScanExit> {
     let mut prev = [0_u16; ROW];
     let mut prev_col: Option<usize> = None;
     loop {
+        let pass_start = stats::cycles();
         // Stop between completed passes (never mid-transfer) once the host
         // suspends, so the ADC sequence always finishes and the data
         // register is left drained and row-aligned for the next resume.
         if usb.try_get() == Some(false) {
-            return;
+            return None;
+        }
+        if let Ok(cmd) = MATRIX_CMD.try_receive() {
+            cold_path();
+            return Some(ScanExit::Cmd(cmd));
+        }
+        if state.persist.is_due(keys) {
+            cold_path();
+            return Some(ScanExit::Persist);
         }
+        state.stream.poll(keys);
         cols.reset();
         for col in 0..COL {
             // Column settle delay; also the executor yield point.
             yield_now().await;
             join(seq.read(buf), async {
                 if let Some(done_col) = prev_col {
-                    process_column(keys, &prev, done_col, tuning).await;
+                    let start = stats::cycles();
+                    process_column(keys, &prev, done_col, &mut state.stats).await;
+                    state.stats.record_column(stats::cycles().wrapping_sub(start));
                 }
             })
             .await;
@@ -202,6 +388,7 @@ async fn active_scan<const ROW: usize, const COL: usize>(
             swap(buf, &mut prev);
             prev_col = Some(col);
         }
+        state.stats.record_pass(stats::cycles().wrapping_sub(pass_start));
     }
 }
 
@@ -213,13 +400,12 @@ async fn confirmed_press<const ROW: usize, const COL: usize>(
     keys: &[[KeyEntry; ROW]; COL],
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
     buf: &mut [u16; ROW],
-    act_threshold: u8,
 ) -> bool {
-    if !any_key_pressed(cols, keys, seq, buf, act_threshold).await {
+    if !any_key_pressed(cols, keys, seq, buf).await {
         return false;
     }
     Timer::after(SUSPEND_CONFIRM_DELAY).await;
-    any_key_pressed(cols, keys, seq, buf, act_threshold).await
+    any_key_pressed(cols, keys, seq, buf).await
 }
 
 /// Event-driven scan supervisor: full-rate scan while the host is awake, park
@@ -227,6 +413,10 @@ async fn confirmed_press<const ROW: usize, const COL: usize>(
 /// polling and no periodic trickle scan: the CPU sits in WFI through suspend
 /// and wakes only on the resume event or a key-wake edge.
 ///
+/// Returns the first host command received while awake, or a due
+/// persistence check; the caller handles it and re-enters. Commands queued
+/// during suspend wait in [`MATRIX_CMD`] until the next awake window.
+///
 /// The ADC [`ConfiguredSequence`] is built fresh for each awake window and
 /// dropped when the host suspends. Embassy exposes no public ADC stop, but
 /// `ConfiguredSequence`'s `Drop` issues one, so dropping the sequence both
@@ -247,8 +437,8 @@ pub(super) async fn run<'peripherals, ADC, D, R, IRQ, const ROW: usize, const CO
     power: &mut Output<'_>,
     wake: &mut ExtiInput<'_, Async>,
     usb: &mut UsbReceiver,
-    cfg: HallCfg,
-) -> !
+    state: &mut ScanState,
+) -> ScanExit
 where
     ADC: Instance<Regs = adc::Adc> + BasicInstance,
     D: RxDma<ADC>,
@@ -256,8 +446,6 @@ where
     IRQ: Binding<D::Interrupt, InterruptHandler<D>> + Copy + 'peripherals,
     AdcSampleTime<ADC>: Clone,
 {
-    let tuning = RtTuning::from_cfg(cfg);
-
     // Scratch buffer for one column's row readings; lives for the whole scan so
     // calibration's own buffer can be dropped before we take over `adc_part`.
     let mut buf = [0_u16; ROW];
@@ -275,7 +463,9 @@ where
         adc_part.rows.set_active();
         {
             let mut seq = adc_part.configure_sequence();
-            active_scan(cols, keys, &mut seq, &mut buf, usb, tuning).await;
+            if let Some(exit) = active_scan(cols, keys, &mut seq, &mut buf, usb, state).await {
+                return exit;
+            }
         }; // `seq` dropped here: ADC stopped, `adc_part` released.
 
         // Suspended: rail off, HC164 and rows parked low, ADC already stopped.
@@ -292,13 +482,13 @@ where
                     for _ in 0..SUSPEND_DISCARD_PASSES {
                         read_pass::<ROW, COL>(cols, &mut seq, &mut buf).await;
                     }
-                    if confirmed_press(cols, keys, &mut seq, &mut buf, tuning.act_threshold).await {
+                    if confirmed_press(cols, keys, &mut seq, &mut buf).await {
                         // Publishing pass raises RMK's remote-wakeup request;
                         // the host resumes and the outer wait_active
                         // breaks us out. Leave the rail powered for it; `seq`
                         // drops at the end of this arm, stopping the ADC until
                         // the awake window rebuilds it.
-                        eval_pass(cols, keys, &mut seq, &mut buf, tuning).await;
+                        eval_pass(cols, keys, &mut seq, &mut buf, &mut state.stats).await;
                     } else {
                         // Spurious edge: drop the sequence (stopping the ADC),
                         // then park rail, HC164, and rows low again.
@@ -325,8 +515,9 @@ async fn read_pass<const ROW: usize, const COL: usize>(
     scan_pass(cols, seq, buf, COL, |_col, _readings| {}).await;
 }
 
-/// Test whether any calibrated key is currently pressed past the actuation
-/// point, by its absolute travel rather than any change from a prior reading.
+/// Test whether any calibrated key is currently pressed past its own
+/// actuation point, by its absolute travel rather than any change from a
+/// prior reading.
 ///
 /// Reads the whole matrix once without mutating key state, so it is safe to
 /// call repeatedly for confirmation and leaves the edge-triggered
@@ -336,7 +527,6 @@ async fn any_key_pressed<const ROW: usize, const COL: usize>(
     keys: &[[KeyEntry; ROW]; COL],
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
     buf: &mut [u16; ROW],
-    act_threshold: u8,
 ) -> bool {
     let mut pressed = false;
     scan_pass(cols, seq, buf, COL, |col, readings| {
@@ -345,7 +535,7 @@ async fn any_key_pressed<const ROW: usize, const COL: usize>(
                 let raw = raw_reading.clamp(VALID_RAW_MIN, VALID_RAW_MAX);
                 if let Some(entry) = key_col.get(usize::from(row_u8))
                     && let Some(travel) = entry.travel_from(raw)
-                    && travel >= act_threshold
+                    && travel >= entry.rt.act_threshold
                 {
                     pressed = true;
                 }
@@ -397,13 +587,13 @@ async fn eval_pass<const ROW: usize, const COL: usize>(
     keys: &mut [[KeyEntry; ROW]; COL],
     seq: &mut ConfiguredSequence<'_, adc::Adc>,
     buf: &mut [u16; ROW],
-    tuning: RtTuning,
+    stats: &mut ScanStats,
 ) {
     cols.reset();
     for col in 0..COL {
         yield_now().await;
         seq.read(buf).await;
         cols.advance();
-        process_column(keys, buf, col, tuning).await;
+        process_column(keys, buf, col, stats).await;
     }
 }
@@ -0,0 +1,195 @@
+//! SOCD (simultaneous opposing cardinal directions) resolution for key pairs.
+//!
+//! A paired key no longer publishes its own rapid-trigger transitions.
+//! Instead, every travel change of either key re-runs [`resolve`] over both
+//! keys' physical state, and the scan loop publishes only the difference
+//! between the resolved outputs and what was last published
+//! ([`crate::matrix::analog_matrix::types::KeyEntry::socd_out`]). That is
+//! what makes a suppressed key's release and later re-press come out as real
+//! events.
+
+use crate::layout::has_sensor;
+
+/// Number of configurable SOCD pairs.
+pub const SOCD_PAIRS: usize = 4;
+
+/// How a pair resolves while both keys are physically pressed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SocdMode {
+    /// The key pressed further wins; a tie keeps the current winner. Only
+    /// possible because the analog scan knows each key's travel.
+    DeeperWins,
+    /// The key pressed first stays active; the second is held back until the
+    /// first is released.
+    FirstWins,
+    /// The most recently pressed key wins ("snap tap"); releasing it hands
+    /// back to the other key if that is still held.
+    LastWins,
+    /// Both keys cancel out while both are held.
+    Neutral,
+}
+
+impl SocdMode {
+    /// Wire code of this mode, as stored in the EEPROM tuning block and sent
+    /// to the host. `0` is reserved for an unused pair slot.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::LastWins => 1,
+            Self::FirstWins => 2,
+            Self::Neutral => 3,
+            Self::DeeperWins => 4,
+        }
+    }
+
+    /// Decode a wire code produced by [`SocdMode::code`].
+    #[must_use]
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::LastWins),
+            2 => Some(Self::FirstWins),
+            3 => Some(Self::Neutral),
+            4 => Some(Self::DeeperWins),
+            _ => None,
+        }
+    }
+}
+
+/// One configured pair of opposing keys, by matrix position.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SocdPair {
+    /// Column of the first key.
+    pub a_col: u8,
+    /// Row of the first key.
+    pub a_row: u8,
+    /// Column of the second key.
+    pub b_col: u8,
+    /// Row of the second key.
+    pub b_row: u8,
+    /// Resolution mode.
+    pub mode:  SocdMode,
+}
+
+impl SocdPair {
+    /// Byte length of an encoded pair.
+    pub const ENCODED_LEN: usize = 5;
+
+    /// Decode a pair from its wire form (`a_row`, `a_col`, `b_row`, `b_col`,
+    /// mode code). Returns `Some(None)` for an unused slot (mode code `0`) and
+    /// `None` for an unknown mode or a pair that is not two distinct sensor
+    /// positions inside the `rows` × `cols` matrix: a key without a sensor
+    /// never changes travel, so a pair holding one would never resolve.
+    #[must_use]
+    pub fn decode(bytes: [u8; Self::ENCODED_LEN], rows: usize, cols: usize) -> Option<Option<Self>> {
+        let [a_row, a_col, b_row, b_col, code] = bytes;
+        if code == 0 {
+            return Some(None);
+        }
+        let mode = SocdMode::from_code(code)?;
+        let in_range = usize::from(a_row) < rows
+            && usize::from(b_row) < rows
+            && usize::from(a_col) < cols
+            && usize::from(b_col) < cols;
+        let sensed = has_sensor(a_row, usize::from(a_col)) && has_sensor(b_row, usize::from(b_col));
+        if !in_range || !sensed || (a_row == b_row && a_col == b_col) {
+            return None;
+        }
+        Some(Some(Self { a_col, a_row, b_col, b_row, mode }))
+    }
+
+    /// Encode `pair` (or an unused slot for `None`) in the form read by
+    /// [`SocdPair::decode`].
+    #[must_use]
+    pub const fn encode(pair: Option<Self>) -> [u8; Self::ENCODED_LEN] {
+        match pair {
+            Some(pair) => [pair.a_row, pair.a_col, pair.b_row, pair.b_col, pair.mode.code()],
+            None => [0; Self::ENCODED_LEN],
+        }
+    }
+
+    /// The link for the key at `row`/`col` if it belongs to this pair.
+    #[must_use]
+    pub const fn link_for(self, row: usize, col: usize) -> Option<SocdLink> {
+        let is_a = usize::from(self.a_row) == row && usize::from(self.a_col) == col;
+        let is_b = usize::from(self.b_row) == row && usize::from(self.b_col) == col;
+        if is_a {
+            Some(SocdLink { mode: self.mode, partner_col: self.b_col, partner_row: self.b_row })
+        } else if is_b {
+            Some(SocdLink { mode: self.mode, partner_col: self.a_col, partner_row: self.a_row })
+        } else {
+            None
+        }
+    }
+
+    /// Whether this pair and `other` have a key in common. A key can only
+    /// belong to one pair, so the host may not configure overlapping pairs.
+    #[must_use]
+    pub const fn shares_key(self, other: Self) -> bool {
+        let (row, col) = (usize::from(other.a_row), usize::from(other.a_col));
+        let (b_row, b_col) = (usize::from(other.b_row), usize::from(other.b_col));
+        self.link_for(row, col).is_some() || self.link_for(b_row, b_col).is_some()
+    }
+}
+
+/// A key's link to its SOCD partner, carried in its hot-path tuning.
+#[derive(Clone, Copy)]
+pub struct SocdLink {
+    /// Resolution mode of the pair.
+    pub mode:        SocdMode,
+    /// Column of the partner key.
+    pub partner_col: u8,
+    /// Row of the partner key.
+    pub partner_row: u8,
+}
+
+/// The inputs [`resolve`] needs from one key of a pair.
+#[derive(Clone, Copy)]
+pub struct SocdSide {
+    /// Output state last published for this key.
+    pub out:     bool,
+    /// Physical (rapid-trigger) press state.
+    pub pressed: bool,
+    /// Current travel, in fine travel units.
+    pub travel:  u8,
+}
+
+/// Resolve the outputs of `key`, which just changed travel, and its
+/// `partner`. `just_pressed` is set when this change was `key`'s physical
+/// press transition. Returns (`key` output, `partner` output).
+///
+/// With at most one key physically pressed the outputs simply follow the
+/// keys, which also hands control back to a still-held partner when the
+/// winner is released; `mode` only decides the both-pressed case.
+#[must_use]
+pub const fn resolve(mode: SocdMode, key: SocdSide, partner: SocdSide, just_pressed: bool) -> (bool, bool) {
+    if !(key.pressed && partner.pressed) {
+        return (key.pressed, partner.pressed);
+    }
+    let keep = (key.out, partner.out);
+    match mode {
+        SocdMode::DeeperWins => {
+            if key.travel > partner.travel {
+                (true, false)
+            } else if key.travel < partner.travel {
+                (false, true)
+            } else {
+                keep
+            }
+        },
+        SocdMode::FirstWins => {
+            if just_pressed {
+                (false, true)
+            } else {
+                keep
+            }
+        },
+        SocdMode::LastWins => {
+            if just_pressed {
+                (true, false)
+            } else {
+                keep
+            }
+        },
+        SocdMode::Neutral => (false, false),
+    }
+}
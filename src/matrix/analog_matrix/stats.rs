@@ -0,0 +1,231 @@
+//! Scan-rate and latency statistics for catching performance regressions.
+//!
+//! The scanner times every matrix pass, every column's processing, and every
+//! published transition with the Cortex-M DWT cycle counter, which costs a
+//! single register load per sample. Pass rate is counted over windows of one
+//! second of scanning, so time spent suspended or handling host commands does
+//! not dilute it. The host reads (and optionally clears) the figures with
+//! [`crate::host::MatrixCmd::GetScanStats`].
+//!
+//! The counter is enabled once at boot by
+//! [`crate::board::enable_cycle_counter`]; if it is not running every time
+//! reads as zero, so the host is told the figures are untimed (see
+//! [`timed`]) rather than sent zeros it would take for a fast scan.
+
+use crate::board::SYSCLK_HZ;
+use cortex_m::peripheral::DWT;
+
+/// CPU cycles per microsecond.
+const CYCLES_PER_US: u32 = SYSCLK_HZ.checked_div(1_000_000).expect("divisor is non-zero");
+
+/// Reported time units per microsecond (0.1 µs each).
+const UNITS_PER_US: u32 = 10;
+
+/// Current value of the free-running DWT cycle counter; intervals are taken
+/// with `wrapping_sub`, so the ~51 s wrap at 84 MHz is harmless.
+#[must_use]
+#[inline]
+pub fn cycles() -> u32 { DWT::cycle_count() }
+
+/// Whether the DWT cycle counter is running, so the figures are real.
+#[must_use]
+pub fn timed() -> bool { DWT::cycle_counter_enabled() }
+
+/// Convert a cycle count to 0.1 µs units, saturating at `u16::MAX`
+/// (~6.5 ms).
+const fn to_units(cycles: u32) -> u16 {
+    match u16::try_from(cycles.saturating_mul(UNITS_PER_US).checked_div(CYCLES_PER_US).unwrap_or(0)) {
+        Ok(units) => units,
+        Err(_) => u16::MAX,
+    }
+}
+
+/// Running minimum, maximum, and total of a timed interval, in cycles.
+#[derive(Clone, Copy)]
+struct CycleStat {
+    /// Number of recorded intervals.
+    count: u32,
+    /// Longest recorded interval.
+    max:   u32,
+    /// Shortest recorded interval; `u32::MAX` until the first one.
+    min:   u32,
+    /// Sum of all recorded intervals.
+    total: u64,
+}
+
+impl CycleStat {
+    /// No intervals recorded.
+    const EMPTY: Self = Self { count: 0, max: 0, min: u32::MAX, total: 0 };
+
+    /// Record one interval of `cycles`.
+    #[inline]
+    const fn record(&mut self, cycles: u32) {
+        self.count = self.count.saturating_add(1);
+        if cycles > self.max {
+            self.max = cycles;
+        }
+        if cycles < self.min {
+            self.min = cycles;
+        }
+        self.total = self.total.saturating_add(u64::from(cycles));
+    }
+
+    /// Minimum, average, and maximum in 0.1 µs units; all zero before the
+    /// first interval.
+    const fn summary(self) -> TimeSummary {
+        if self.count == 0 {
+            return TimeSummary { avg: 0, max: 0, min: 0 };
+        }
+        let avg = match u32::try_from(self.total.checked_div(u64::from(self.count)).unwrap_or(0)) {
+            Ok(avg) => avg,
+            Err(_) => u32::MAX,
+        };
+        TimeSummary { avg: to_units(avg), max: to_units(self.max), min: to_units(self.min) }
+    }
+}
+
+/// Minimum, average, and maximum of one timed interval, in 0.1 µs units.
+#[derive(Clone, Copy)]
+pub struct TimeSummary {
+    /// Average interval.
+    pub avg: u16,
+    /// Longest interval.
+    pub max: u16,
+    /// Shortest interval.
+    pub min: u16,
+}
+
+impl TimeSummary {
+    /// Encode as min, avg, max (2 B LE each).
+    const fn encode(self) -> [u8; 6] {
+        let [min_lo, min_hi] = self.min.to_le_bytes();
+        let [avg_lo, avg_hi] = self.avg.to_le_bytes();
+        let [max_lo, max_hi] = self.max.to_le_bytes();
+        [min_lo, min_hi, avg_lo, avg_hi, max_lo, max_hi]
+    }
+}
+
+/// Snapshot of the statistics as reported to the host.
+#[derive(Clone, Copy)]
+pub struct ScanReport {
+    /// Processing time of one column's readings, transitions included.
+    pub column:         TimeSummary,
+    /// Duration of a complete matrix pass.
+    pub pass:           TimeSummary,
+    /// Matrix passes completed in the last full second of scanning.
+    pub passes_per_sec: u16,
+    /// Time from detecting a transition to the completion of its
+    /// `publish_event_async`.
+    pub publish:        TimeSummary,
+    /// Number of transitions timed by [`ScanReport::publish`], saturating.
+    pub publishes:      u16,
+}
+
+impl ScanReport {
+    /// Byte length of an encoded report.
+    pub const ENCODED_LEN: usize = 22;
+
+    /// Encode as passes per second (2 B LE), pass min/avg/max, column
+    /// min/avg/max, publish count (2 B LE), publish min/avg/max, every time
+    /// in 0.1 µs units (2 B LE).
+    #[must_use]
+    pub fn encode(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0_u8; Self::ENCODED_LEN];
+        let fields: [&[u8]; 5] = [
+            &self.passes_per_sec.to_le_bytes(),
+            &self.pass.encode(),
+            &self.column.encode(),
+            &self.publishes.to_le_bytes(),
+            &self.publish.encode(),
+        ];
+        let mut offset = 0_usize;
+        for field in fields {
+            let end = offset.saturating_add(field.len());
+            if let Some(dst) = bytes.get_mut(offset..end) {
+                dst.copy_from_slice(field);
+            }
+            offset = end;
+        }
+        bytes
+    }
+}
+
+/// Statistics owned by the scanner and updated from the hot path.
+pub struct ScanStats {
+    /// Column processing times.
+    column:         CycleStat,
+    /// Pass times.
+    pass:           CycleStat,
+    /// Passes completed in the last full one-second window.
+    passes_per_sec: u16,
+    /// Transition-to-publish times.
+    publish:        CycleStat,
+    /// Scanning time accumulated in the current rate window.
+    window_cycles:  u32,
+    /// Passes completed in the current rate window.
+    window_passes:  u16,
+}
+
+impl ScanStats {
+    /// No samples recorded.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            column:         CycleStat::EMPTY,
+            pass:           CycleStat::EMPTY,
+            passes_per_sec: 0,
+            publish:        CycleStat::EMPTY,
+            window_cycles:  0,
+            window_passes:  0,
+        }
+    }
+
+    /// Record the processing time of one column.
+    #[inline]
+    pub const fn record_column(&mut self, cycles: u32) { self.column.record(cycles); }
+
+    /// Record one completed pass of `cycles`, closing the rate window once it
+    /// spans a second of scanning.
+    #[inline]
+    pub const fn record_pass(&mut self, cycles: u32) {
+        self.pass.record(cycles);
+        self.window_passes = self.window_passes.saturating_add(1);
+        self.window_cycles = self.window_cycles.saturating_add(cycles);
+        if self.window_cycles >= SYSCLK_HZ {
+            self.passes_per_sec = self.window_passes;
+            self.window_cycles = 0;
+            self.window_passes = 0;
+        }
+    }
+
+    /// Record the time from detecting a transition to its publication.
+    #[inline]
+    pub const fn record_publish(&mut self, cycles: u32) { self.publish.record(cycles); }
+
+    /// Snapshot for the host.
+    #[must_use]
+    pub const fn report(&self) -> ScanReport {
+        ScanReport {
+            column:         self.column.summary(),
+            pass:           self.pass.summary(),
+            passes_per_sec: self.passes_per_sec,
+            publish:        self.publish.summary(),
+            publishes:      match u16::try_from(self.publish.count) {
+                Ok(count) => count,
+                Err(_) => u16::MAX,
+            },
+        }
+    }
+
+    /// Clear the minimum, average, and maximum figures; the pass rate keeps
+    /// its last full window.
+    pub const fn reset(&mut self) {
+        self.column = CycleStat::EMPTY;
+        self.pass = CycleStat::EMPTY;
+        self.publish = CycleStat::EMPTY;
+    }
+}
+
+impl Default for ScanStats {
+    fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,168 @@
+//! Live per-key sample streaming for a host-side visualizer.
+//!
+//! While the host has streaming enabled, [`TravelStream::poll`] runs once per
+//! matrix pass and, every configured interval, sends a [`KeySample`] for each
+//! key whose reading changed since it was last sent. The scan loop only sets
+//! [`KeyEntry::stream_dirty`] when a reading passes the noise gate, so a
+//! disabled stream costs one store per changed key and one branch per pass.
+//!
+//! Samples go out on [`TRAVEL_STREAM`] with `try_send`: a full queue leaves
+//! the remaining keys marked for the next interval instead of stalling the
+//! scanner.
+
+use crate::{
+    host::{HostReply, TRAVEL_STREAM},
+    matrix::analog_matrix::types::KeyEntry,
+};
+use core::hint::likely;
+use embassy_time::{Duration, Instant};
+
+/// One key's reading and calibration as reported to the host.
+#[derive(Clone, Copy)]
+pub struct KeySample {
+    /// Raw ADC at zero travel.
+    pub calib_zero: u16,
+    /// Matrix column.
+    pub col:        u8,
+    /// Persistent full-travel ADC reading.
+    pub entry_full: u16,
+    /// Q16.16 reciprocal of the calibrated travel range.
+    pub inv_scale:  u32,
+    /// Physical (rapid-trigger) press state.
+    pub pressed:    bool,
+    /// Last raw ADC reading that passed the noise gate.
+    pub raw:        u16,
+    /// Matrix row.
+    pub row:        u8,
+    /// Travel in fine travel units.
+    pub travel:     u8,
+}
+
+impl KeySample {
+    /// Byte length of an encoded sample.
+    pub const ENCODED_LEN: usize = 14;
+
+    /// Encode as row, col, raw (2 B LE), travel, pressed, `calib_zero` (2 B
+    /// LE), `entry_full` (2 B LE), `inv_scale` (4 B LE).
+    #[must_use]
+    pub fn encode(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0_u8; Self::ENCODED_LEN];
+        let fields: [&[u8]; 8] = [
+            &[self.row],
+            &[self.col],
+            &self.raw.to_le_bytes(),
+            &[self.travel],
+            &[u8::from(self.pressed)],
+            &self.calib_zero.to_le_bytes(),
+            &self.entry_full.to_le_bytes(),
+            &self.inv_scale.to_le_bytes(),
+        ];
+        let mut offset = 0_usize;
+        for field in fields {
+            let end = offset.saturating_add(field.len());
+            if let Some(dst) = bytes.get_mut(offset..end) {
+                dst.copy_from_slice(field);
+            }
+            offset = end;
+        }
+        bytes
+    }
+
+    /// Snapshot of `key` at `row`/`col`.
+    const fn of(key: &KeyEntry, row: u8, col: u8) -> Self {
+        Self {
+            calib_zero: key.calib_zero,
+            col,
+            entry_full: key.entry_full,
+            inv_scale: key.inv_scale,
+            pressed: key.pressed,
+            raw: key.last_raw,
+            row,
+            travel: key.travel,
+        }
+    }
+}
+
+/// Streaming state owned by the scanner: the host-selected interval and the
+/// time the next batch is due.
+pub struct TravelStream {
+    /// Interval between batches in milliseconds; `0` while streaming is off.
+    interval_ms: u8,
+    /// Earliest time of the next batch.
+    next_at:     Instant,
+}
+
+impl TravelStream {
+    /// Current interval in milliseconds; `0` while streaming is off.
+    #[must_use]
+    pub const fn interval_ms(&self) -> u8 { self.interval_ms }
+
+    /// Streaming off.
+    #[must_use]
+    pub const fn new() -> Self { Self { interval_ms: 0, next_at: Instant::from_ticks(0) } }
+
+    /// Send the changed keys if streaming is on and the interval has elapsed.
+    ///
+    /// Two samples share each packet. Only as many packets as
+    /// [`TRAVEL_STREAM`] has room for are built; every key that did not fit
+    /// keeps its mark and goes out with a later batch.
+    #[optimize(speed)]
+    pub fn poll<const ROW: usize, const COL: usize>(&mut self, keys: &mut [[KeyEntry; ROW]; COL]) {
+        if likely(self.interval_ms == 0) {
+            return;
+        }
+        let now = Instant::now();
+        if now < self.next_at {
+            return;
+        }
+        self.next_at = now.saturating_add(Duration::from_millis(u64::from(self.interval_ms)));
+        // The scanner is the only sender, so every packet counted here fits.
+        let mut budget = TRAVEL_STREAM.free_capacity();
+        let mut held: Option<KeySample> = None;
+        'keys: for (col, key_col) in keys.iter_mut().enumerate() {
+            for (row, key) in key_col.iter_mut().enumerate() {
+                if budget == 0 {
+                    break 'keys;
+                }
+                if !key.stream_dirty {
+                    continue;
+                }
+                let (Ok(row_u8), Ok(col_u8)) = (u8::try_from(row), u8::try_from(col)) else { continue };
+                key.stream_dirty = false;
+                let sample = KeySample::of(key, row_u8, col_u8);
+                match held.take() {
+                    None => held = Some(sample),
+                    Some(first) => {
+                        _ = TRAVEL_STREAM.try_send(HostReply::KeySamples(first, Some(sample)));
+                        budget = budget.saturating_sub(1);
+                    },
+                }
+            }
+        }
+        if let Some(first) = held {
+            _ = TRAVEL_STREAM.try_send(HostReply::KeySamples(first, None));
+        }
+    }
+
+    /// Stream every `interval_ms` milliseconds, or stop with `0`. Starting
+    /// (or retiming) the stream marks every calibrated key so the host first
+    /// receives a full snapshot; other positions are sent once their reading
+    /// changes.
+    pub fn set_interval<const ROW: usize, const COL: usize>(
+        &mut self,
+        interval_ms: u8,
+        keys: &mut [[KeyEntry; ROW]; COL],
+    ) {
+        self.interval_ms = interval_ms;
+        self.next_at = Instant::now();
+        if interval_ms != 0 {
+            for key in keys.as_flattened_mut() {
+                key.stream_dirty |= key.calib_used;
+            }
+        }
+    }
+}
+
+impl Default for TravelStream {
+    fn default() -> Self { Self::new() }
+}
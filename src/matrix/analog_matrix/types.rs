@@ -1,5 +1,15 @@
-use super::lut;
-pub use super::lut::{VALID_RAW_MAX, VALID_RAW_MIN};
+pub use super::lut::{SwitchProfile, VALID_RAW_MAX, VALID_RAW_MIN};
+use super::{
+    dks::{DKS_BOTTOM_HYST_UNIT, DKS_BOTTOM_UNIT, DKS_SLOTS, DksBinding, DksEvent, DksMap, DksZone},
+    filter::{FilterState, KeyFilter},
+    lut,
+    socd::{SOCD_PAIRS, SocdLink, SocdPair, SocdSide},
+};
+use crate::{
+    gamepad::{GAMEPAD_INPUTS, GamepadInput},
+    matrix::layer_toggle::MatrixPos,
+    mouse::{MOUSE_INPUTS, MouseInput, MouseSettings},
+};
 use core::hint::{cold_path, unlikely};
 use embassy_stm32::adc::{BasicAdcRegs, BasicInstance};
 use embassy_time::{Duration, Instant};
@@ -121,9 +131,20 @@ pub const CALIB_SETTLE_AFTER_ALL_DONE: Duration = Duration::from_millis(2000);
 /// considered to have a valid hall-effect sensor at its position.
 pub const CALIB_ZERO_TOLERANCE: u16 = 500;
 
+/// Largest top or bottom dead zone a key accepts, in 0.05 mm configuration
+/// units (1.0 mm); together the two always leave at least 2.0 mm of live
+/// travel.
+pub const DEAD_ZONE_MAX_UNIT: u8 = 20;
+
 /// Default full-range calibration delta used when no better value is available.
 pub const DEFAULT_FULL_RANGE: u16 = 900;
 
+/// The value of `1.0` in the Q8.8 format of [`RtTuning::dz_scale`].
+const DZ_SCALE_ONE: u16 = 256;
+
+/// Number of fractional bits in [`RtTuning::dz_scale`].
+const DZ_SCALE_FRAC_BITS: u32 = 8;
+
 /// Expected travel distance in fine travel units
 /// ([`FULL_TRAVEL_UNIT`] × [`TRAVEL_SCALE`]); represents 4.0 mm.
 pub const FULL_TRAVEL_FINE: u8 = FULL_TRAVEL_UNIT.saturating_mul(TRAVEL_SCALE);
@@ -147,6 +168,26 @@ pub const INV_SCALE_ONE: u32 = 1_u32.checked_shl(INV_SCALE_FRAC_BITS).expect("IN
 /// intentionally small so any genuine press is accepted.
 pub const MIN_USEFUL_FULL_RANGE: u16 = 100;
 
+/// Number of fractional bits in [`KeyEntry::noise`] (1/16 ADC count).
+pub const NOISE_FRAC_BITS: u32 = 4;
+
+/// Upper bound of a derived per-key noise gate, in raw ADC counts (~0.2 mm
+/// of travel); keeps a key that was disturbed during the zero pass usable.
+pub const NOISE_GATE_MAX: u16 = 48;
+
+/// Lower bound of a derived per-key noise gate, in raw ADC counts; even a
+/// perfectly quiet sensor flickers by one count between conversions.
+pub const NOISE_GATE_MIN: u16 = 2;
+
+/// Standard deviations of resting noise a reading must move by to pass the
+/// per-key noise gate. The difference of two noisy readings has
+/// `sqrt(2)` times the noise of one, so four sigmas reject all but ~0.5 % of
+/// pure-noise changes.
+pub const NOISE_GATE_SIGMAS: u16 = 4;
+
+/// [`KeyEntry::noise`] of a key whose noise was never measured.
+pub const NOISE_UNMEASURED: u16 = u16::MAX;
+
 /// Reference zero-travel ADC value used for calibration and used-sensor
 /// validation.
 pub const REF_ZERO_TRAVEL: u16 = 3121;
@@ -157,7 +198,7 @@ pub const REF_ZERO_TRAVEL: u16 = 3121;
 /// firmware uses), three quanta per 0.05 mm configuration step, so
 /// rapid-trigger comparisons are exact instead of carrying up to half a
 /// configuration step of quantisation slop. [`HallCfg`] stays in 0.05 mm
-/// units; [`RtTuning::from_cfg`] converts to fine units once at startup.
+/// units; [`RtTuning::from_key`] converts to fine units once per edit.
 ///
 /// 0.05 mm is the finest honest configuration step for this sensor: the
 /// ±10-count noise gate already spans ~0.045 mm of travel, so finer steps
@@ -199,62 +240,182 @@ pub enum AutoCalibPhase {
 /// Configuration parameters for hall-effect key sensing.
 #[derive(Clone, Copy, Default)]
 pub struct HallCfg {
-    /// Minimum travel threshold in mm/20 units (1 = 0.05 mm) before a key is
-    /// considered actuated.
+    /// Default travel threshold in mm/20 units (1 = 0.05 mm) before a key is
+    /// considered actuated; seeds every position of the per-key tuning table
+    /// until a stored table is loaded from EEPROM.
     pub actuation_pt:           u8           = 20,
+    /// Default bottom dead zone in mm/20 units: travel this close to the
+    /// bottom already reads as full travel.
+    pub bottom_dead_zone:       u8           = 0,
     /// Number of full-matrix passes averaged together during zero-travel
     /// calibration.
     pub calib_passes:           u32          = 512,
+    /// Default per-key filter applied to raw readings ahead of the noise
+    /// gate.
+    pub filter:                 KeyFilter    = KeyFilter::Off,
     /// Duration of the full-travel sampling window during first-boot
     /// calibration.
     pub full_calib_duration:    Duration     = Duration::from_secs(180),
-    /// Raw ADC delta below which readings are treated as noise and discarded.
+    /// Time allowed to hold a key down when recalibrating it on its own.
+    pub key_calib_duration:     Duration     = Duration::from_secs(30),
+    /// Raw ADC delta below which readings are treated as noise and
+    /// discarded, for keys whose noise the zero pass could not measure;
+    /// measured keys derive their own gate (see [`KeyEntry::noise_gate`]).
     pub noise_gate:             u16          = 10,
-    /// Minimum upward travel from the trough required to register a new press,
-    /// in mm/20 units (e.g. 1 = 0.05 mm).
+    /// Default per-key rapid-trigger enable; a disabled key presses and
+    /// releases at its fixed actuation point.
+    pub rt_enabled:             bool         = true,
+    /// Board-wide rapid-trigger mode, used by every key whose tuning does not
+    /// override it; replaced by the stored tuning block when one validates.
+    pub rt_mode:                RtMode       = RtMode::Standard,
+    /// Default minimum upward travel from the trough required to register a
+    /// new press, in mm/20 units (e.g. 1 = 0.05 mm).
     pub rt_sensitivity_press:   u8           = 10,
-    /// Minimum downward travel from the peak required to register a release,
-    /// in mm/20 units (e.g. 1 = 0.05 mm).
+    /// Default minimum downward travel from the peak required to register a
+    /// release, in mm/20 units (e.g. 1 = 0.05 mm).
     pub rt_sensitivity_release: u8           = 6,
+    /// Default top dead zone in mm/20 units: travel up to this depth still
+    /// reads as zero.
+    pub top_dead_zone:          u8           = 0,
 }
 
-/// Rapid-trigger tuning values derived once from [`HallCfg`] before the scan
-/// loop starts, so the hot path reads pre-clamped constants instead of
-/// re-deriving them on every pass. All travel values are in fine travel
+/// How a key with rapid trigger enabled decides press and release.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtMode {
+    /// Continuous rapid trigger: once actuated, the key presses and releases
+    /// purely on the press/release sensitivities anywhere in the stroke,
+    /// including short of the actuation point, until it returns fully to the
+    /// top.
+    Continuous,
+    /// Classic hysteresis: press at the actuation point, release once travel
+    /// falls the release sensitivity below it. No dynamic re-fire.
+    Hysteresis,
+    /// Standard rapid trigger: dynamic release and re-fire past the
+    /// actuation point, with a hard release once travel falls back below it.
+    #[default]
+    Standard,
+}
+
+impl RtMode {
+    /// Wire code of this mode, as stored in the EEPROM tuning block and sent
+    /// to the host. `0` is reserved for "follow the board-wide mode".
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Hysteresis => 1,
+            Self::Standard => 2,
+            Self::Continuous => 3,
+        }
+    }
+
+    /// Decode a wire code produced by [`RtMode::code`].
+    #[must_use]
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::Hysteresis),
+            2 => Some(Self::Standard),
+            3 => Some(Self::Continuous),
+            _ => None,
+        }
+    }
+}
+
+/// Per-key rapid-trigger tuning derived from a [`KeyTuning`] before it
+/// reaches the scan loop, so the hot path reads pre-clamped constants instead
+/// of re-deriving them on every pass. All travel values are in fine travel
 /// units (1/60 mm each, [`TRAVEL_SCALE`] quanta per configuration step).
-#[derive(Clone, Copy)]
+///
+/// Stored in [`KeyEntry::rt`]; the default never actuates, so a key whose
+/// tuning was never applied stays released.
+#[derive(Clone, Copy, Default)]
 pub struct RtTuning {
-    /// Minimum travel threshold before a key is considered actuated, in
-    /// fine travel units.
-    pub act_threshold:       u8,
-    /// Raw ADC delta below which readings are treated as noise.
-    pub noise_gate:          u16,
+    /// Travel threshold at which the key actuates, in fine travel units.
+    pub act_threshold:       u8 = u8::MAX,
+    /// Dynamic Keystroke assignment; when set, the key emits its slot's
+    /// actions via [`KeyEntry::step_dks`] instead of rapid trigger.
+    pub dks:                 Option<DksBinding>,
+    /// Q8.8 factor stretching the live travel between the dead zones back
+    /// onto `0..=FULL_TRAVEL_FINE`; `1.0` without dead zones.
+    pub dz_scale:            u16 = DZ_SCALE_ONE,
+    /// Top dead zone in fine travel units, subtracted from every reading.
+    pub dz_top:              u8,
+    /// Whether the rapid-trigger dead-band logic is active; when `false` the
+    /// key presses and releases at [`RtTuning::act_threshold`].
+    pub enabled:             bool,
+    /// Filter applied to this key's raw readings before the noise gate.
+    pub filter:              KeyFilter,
+    /// Gamepad input fed by this key's travel. Maintained by the matrix from
+    /// [`BoardTuning::gamepad`], not derived from [`KeyTuning`].
+    pub gamepad:             Option<GamepadInput>,
+    /// Resolved rapid-trigger mode (the per-key override, or the board-wide
+    /// mode when there is none).
+    pub mode:                RtMode,
+    /// Analog mouse input fed by this key's travel. Maintained by the matrix
+    /// from [`BoardTuning::mouse`], not derived from [`KeyTuning`].
+    pub mouse:               Option<MouseInput>,
     /// Minimum upward travel from the trough required to register a press,
     /// in fine travel units.
     pub sensitivity_press:   u8,
     /// Minimum downward travel from the peak required to register a
     /// release, in fine travel units.
     pub sensitivity_release: u8,
+    /// SOCD partner; when set, the key's transitions are resolved against
+    /// the partner before publishing. Maintained by the matrix from
+    /// [`BoardTuning::socd`], not derived from [`KeyTuning`].
+    pub socd:                Option<SocdLink>,
     /// Lower clamp applied to the released-side extremum so a key driven
     /// below the actuation point re-fires cleanly at the actuation floor.
     pub trough_floor:        u8,
 }
 
 impl RtTuning {
-    /// Derive the tuning values from `cfg`, converting the 0.05 mm
-    /// configuration units into fine travel units.
+    /// Derive the hot-path values from `tuning`, converting the 0.05 mm
+    /// configuration units into fine travel units and resolving its mode and
+    /// DKS slot against the board-wide `board` settings.
     ///
-    /// Both sensitivities are clamped to 1 so a zero config value never
-    /// disables the dead-band.
+    /// The dead zones become [`RtTuning::dz_top`] and the Q8.8 stretch
+    /// [`RtTuning::dz_scale`] that maps the live travel between them back onto
+    /// the full range, rounded up so the bottom of the live range reaches
+    /// exactly [`FULL_TRAVEL_FINE`].
+    ///
+    /// The actuation point is clamped to `1..=FULL_TRAVEL_UNIT` so a zero
+    /// entry can never hold the key permanently pressed, and both
+    /// sensitivities are clamped to 1 so a zero value never disables the
+    /// dead-band.
     #[must_use]
-    pub const fn from_cfg(cfg: HallCfg) -> Self {
-        let act_threshold = cfg.actuation_pt.saturating_mul(TRAVEL_SCALE);
-        let sensitivity_press = cfg.rt_sensitivity_press.max(1).saturating_mul(TRAVEL_SCALE);
+    pub const fn from_key(tuning: KeyTuning, board: &BoardTuning) -> Self {
+        let act_threshold = tuning.actuation_pt.clamp(1, FULL_TRAVEL_UNIT).saturating_mul(TRAVEL_SCALE);
+        let sensitivity_press = tuning.rt_press.max(1).saturating_mul(TRAVEL_SCALE);
+        let dz_top = tuning.top_dead_zone.min(DEAD_ZONE_MAX_UNIT).saturating_mul(TRAVEL_SCALE);
+        let dz_bottom = tuning.bottom_dead_zone.min(DEAD_ZONE_MAX_UNIT).saturating_mul(TRAVEL_SCALE);
+        let live = u32::from(FULL_TRAVEL_FINE.saturating_sub(dz_top).saturating_sub(dz_bottom));
+        let stretched = u32::from(FULL_TRAVEL_FINE).saturating_mul(u32::from(DZ_SCALE_ONE));
+        let dz_scale = stretched.saturating_add(live.saturating_sub(1)).checked_div(live).unwrap_or(0);
         Self {
             act_threshold,
-            noise_gate: cfg.noise_gate,
+            dks: match tuning.dks {
+                Some(slot) => match board.dks.get(usize::from(slot)) {
+                    Some(&map) => Some(DksBinding { map, slot }),
+                    None => None,
+                },
+                None => None,
+            },
+            dz_scale: match u16::try_from(dz_scale) {
+                Ok(scale) => scale,
+                Err(_) => DZ_SCALE_ONE,
+            },
+            dz_top,
+            enabled: tuning.rt_enabled,
+            filter: tuning.filter,
+            gamepad: None,
+            mode: match tuning.mode {
+                Some(mode) => mode,
+                None => board.rt_mode,
+            },
+            mouse: None,
             sensitivity_press,
-            sensitivity_release: cfg.rt_sensitivity_release.max(1).saturating_mul(TRAVEL_SCALE),
+            sensitivity_release: tuning.rt_release.max(1).saturating_mul(TRAVEL_SCALE),
+            socd: None,
             trough_floor: act_threshold.saturating_sub(sensitivity_press),
         }
     }
@@ -280,6 +441,17 @@ pub enum KeyCalibState {
     Waiting,
 }
 
+/// Positions a tuning change may have left pressed on the host, returned by
+/// [`KeyEntry::apply_tuning`] for the caller to release.
+#[derive(Clone, Copy)]
+pub enum Stranded {
+    /// Every action of the Dynamic Keystroke binding the key left.
+    Dks(DksBinding),
+    /// The key's own matrix position, pressed before it moved into Dynamic
+    /// Keystroke.
+    Own,
+}
+
 /// All per-key data accessed on every scan iteration, in a single flat struct.
 ///
 /// Fields are listed alphabetically. The STM32F401's Cortex-M4 has no data
@@ -305,13 +477,22 @@ pub struct KeyEntry {
     pub ac_full_cand:  u16 = u16::MAX,
     /// Current phase of the auto-calibration state machine.
     pub ac_phase:      AutoCalibPhase,
+    /// Deep presses since the confidence score last reached
+    /// [`AUTO_CALIB_CONFIDENCE_THRESHOLD`], saturating; a key that keeps
+    /// pressing without ever scoring is reported by its sensor health.
+    pub ac_unscored:   u8,
     /// Candidate zero-travel ADC peak tracked during the releasing phase.
     pub ac_zero_cand:  u16,
+    /// Resting reading measured by the last zero pass, before the held-key
+    /// fallback of [`KeyEntry::apply_zero`]; kept for sensor health.
+    pub boot_zero:     u16 = REF_ZERO_TRAVEL,
     /// Whether this matrix position has a valid hall-effect sensor.
     pub calib_used:    bool,
     /// Raw ADC at zero travel; stored for drift detection in
     /// [`KeyEntry::update_calib_if_drifted`].
     pub calib_zero:    u16 = REF_ZERO_TRAVEL,
+    /// Dynamic Keystroke depth zone, advanced by [`KeyEntry::step_dks`].
+    pub dks_zone:      DksZone,
     /// Persistent full-travel ADC reading loaded from / stored to EEPROM.
     /// Combined with a freshly measured zero reading on each boot to derive
     /// the hot-path fields via [`KeyEntry::apply_zero`].
@@ -320,15 +501,39 @@ pub struct KeyEntry {
     /// pressed, trough while released). Reset to `new_travel` on every
     /// press↔release transition.
     pub extremum:      u8 = u8::MAX,
+    /// History of the key's [`RtTuning::filter`].
+    pub filter:        FilterState,
     /// Q16.16 reciprocal of the calibrated travel range.
     pub inv_scale:     u32,
-    /// Raw ADC from the previous scan cycle (noise gate filter).
+    /// Filtered raw ADC from the previous scan cycle (noise gate filter).
     /// `u16::MAX` on first boot so the first real reading always passes.
     pub last_raw:      u16 = u16::MAX,
     /// LUT value at zero travel, precomputed for fast travel arithmetic.
     pub lut_zero:      u16,
+    /// Standard deviation of the resting reading in 1/16 ADC counts,
+    /// measured by every boot's zero pass; [`NOISE_UNMEASURED`] until then.
+    pub noise:         u16 = NOISE_UNMEASURED,
+    /// Raw ADC delta below which this key's readings are treated as noise
+    /// and discarded, derived from [`KeyEntry::noise`] by
+    /// [`KeyEntry::set_noise`].
+    pub noise_gate:    u16 = NOISE_GATE_MAX,
     /// Whether the key is currently considered pressed.
     pub pressed:       bool,
+    /// Switch profile whose transfer function maps this key's readings to
+    /// travel; persisted with the calibration.
+    pub profile:       SwitchProfile,
+    /// Rapid-trigger tuning for this key, set by [`KeyEntry::apply_tuning`].
+    pub rt:            RtTuning,
+    /// Whether continuous rapid trigger is engaged: set on the first
+    /// actuation, cleared once travel returns fully to the top. Only used in
+    /// [`RtMode::Continuous`].
+    pub rt_engaged:    bool,
+    /// Press state last published for an SOCD-paired key, which differs from
+    /// [`KeyEntry::pressed`] while the pair suppresses the key.
+    pub socd_out:      bool,
+    /// Whether the reading changed since the key was last sent to the host
+    /// by [`crate::matrix::analog_matrix::stream::TravelStream`].
+    pub stream_dirty:  bool,
     /// Quantised travel value from the previous scan cycle, in fine travel
     /// units (1/60 mm each).
     pub travel:        u8,
@@ -346,8 +551,8 @@ impl KeyEntry {
     /// [`REF_ZERO_TRAVEL`]; positions outside that band are treated as missing
     /// sensors and excluded from scanning.
     const fn apply_calib(&mut self, zero: u16, full: u16) {
-        let zero_lut = lut::lookup(zero);
-        let full_lut = lut::lookup(full);
+        let zero_lut = lut::lookup(self.profile, zero);
+        let full_lut = lut::lookup(self.profile, full);
         let delta = u32::from(full_lut.saturating_sub(zero_lut));
         // Ceiling division of `FULL_TRAVEL_SCALED` by `delta` (0 when
         // `delta == 0`, i.e. a degenerate calibration that the travel hot
@@ -360,20 +565,76 @@ impl KeyEntry {
         self.calib_used = zero_plausible(zero);
     }
 
+    /// Replace this key's hot-path rapid-trigger values with ones derived
+    /// from `tuning` and the board-wide `board` settings.
+    ///
+    /// Takes effect on the next travel change; the current press state and
+    /// extremum are kept, so a held key is not spuriously released by an edit.
+    /// The SOCD, gamepad, and mouse links are kept too, since they belong to
+    /// the board-wide tables rather than to `tuning`. A changed filter starts
+    /// from a fresh history.
+    ///
+    /// Moving the key into or out of Dynamic Keystroke, or to another slot
+    /// or binding table, changes which positions it publishes, so nothing
+    /// the old tuning pressed would ever be released by the new one. The key
+    /// instead starts over from released, re-pressing on its next travel
+    /// change if it is still held, and the positions the old tuning may have
+    /// left pressed are returned for the caller to release first.
+    #[must_use]
+    pub fn apply_tuning(&mut self, tuning: KeyTuning, board: &BoardTuning) -> Option<Stranded> {
+        let (gamepad, mouse, socd) = (self.rt.gamepad, self.rt.mouse, self.rt.socd);
+        if self.rt.filter.code() != tuning.filter.code() {
+            self.filter.reset();
+        }
+        let next = RtTuning::from_key(tuning, board);
+        let stranded = if next.dks == self.rt.dks {
+            None
+        } else {
+            let own_out = if socd.is_some() { self.socd_out } else { self.pressed };
+            let stranded = match self.rt.dks {
+                Some(binding) => Some(Stranded::Dks(binding)),
+                None => own_out.then_some(Stranded::Own),
+            };
+            self.dks_zone = DksZone::Top;
+            self.extremum = u8::MAX;
+            self.pressed = false;
+            self.rt_engaged = false;
+            self.socd_out = false;
+            // Forget the last travel so a key still held re-presses on the
+            // next reading instead of waiting for it to move.
+            self.last_raw = u16::MAX;
+            self.travel = 0;
+            stranded
+        };
+        self.rt = next;
+        self.rt.gamepad = gamepad;
+        self.rt.mouse = mouse;
+        self.rt.socd = socd;
+        stranded
+    }
+
     /// Recompute calibration from a freshly measured `zero`-travel reading
     /// paired with the full-travel stored in [`KeyEntry::entry_full`].
     ///
     /// A reading more than [`CALIB_ZERO_TOLERANCE`] *below*
     /// [`REF_ZERO_TRAVEL`] means the key was almost certainly held down while
-    /// the boot zero pass sampled it, so instead of letting
-    /// [`KeyEntry::apply_calib`] disable the position, fall back to
-    /// [`REF_ZERO_TRAVEL`]: the key registers as pressed until released and
-    /// the auto-calibrator replaces the approximate zero after the first few
-    /// genuine press/release cycles. Readings far *above* the reference still
-    /// disable the position; that side indicates a missing or faulty sensor
-    /// rather than a held key.
+    /// the boot zero pass sampled it (see [`KeyEntry::held_at_boot`]), so
+    /// instead of letting [`KeyEntry::apply_calib`] disable the position, fall
+    /// back to the previous [`KeyEntry::calib_zero`], as loaded from the
+    /// calibration block, or to [`REF_ZERO_TRAVEL`] if that is implausible:
+    /// the key registers as pressed until released, and the auto-calibrator
+    /// refines an approximate zero after the first few genuine press/release
+    /// cycles. Readings far *above* the reference still disable the position;
+    /// that side indicates a missing or faulty sensor rather than a held key.
     pub const fn apply_zero(&mut self, zero: u16) {
-        let resting = if zero.saturating_add(CALIB_ZERO_TOLERANCE) < REF_ZERO_TRAVEL { REF_ZERO_TRAVEL } else { zero };
+        self.boot_zero = zero;
+        let resting = if !self.held_at_boot() {
+            zero
+        } else if zero_plausible(self.calib_zero) {
+            self.calib_zero
+        } else {
+            REF_ZERO_TRAVEL
+        };
         let full = self.entry_full;
         self.apply_calib(resting, full);
     }
@@ -405,6 +666,7 @@ impl KeyEntry {
                     self.ac_full_cand = raw;
                     self.ac_full_at = now;
                     self.ac_phase = AutoCalibPhase::Pressing;
+                    self.ac_unscored = self.ac_unscored.saturating_add(1);
                 }
             },
             AutoCalibPhase::Pressing => {
@@ -449,6 +711,7 @@ impl KeyEntry {
                         if self.ac_confidence >= AUTO_CALIB_CONFIDENCE_THRESHOLD {
                             cold_path();
                             self.ac_confidence = 0;
+                            self.ac_unscored = 0;
 
                             let new_zero = self.ac_zero_cand.saturating_sub(ZERO_TRAVEL_DEAD_ZONE);
                             let new_full = full_from_min(new_zero, self.ac_full_cand);
@@ -464,6 +727,73 @@ impl KeyEntry {
         }
     }
 
+    /// Whether the last zero pass read this key more than
+    /// [`CALIB_ZERO_TOLERANCE`] below [`REF_ZERO_TRAVEL`], i.e. it was held
+    /// down rather than resting.
+    #[must_use]
+    pub const fn held_at_boot(&self) -> bool { self.boot_zero.saturating_add(CALIB_ZERO_TOLERANCE) < REF_ZERO_TRAVEL }
+
+    /// Record the resting `noise` measured by the zero pass and derive the
+    /// key's noise gate from it (see [`noise_gate_from`]); an unmeasured key
+    /// uses `fallback_gate`.
+    pub const fn set_noise(&mut self, noise: u16, fallback_gate: u16) {
+        self.noise = noise;
+        self.noise_gate = if noise == NOISE_UNMEASURED { fallback_gate } else { noise_gate_from(noise) };
+    }
+
+    /// Switch this key to `profile`, re-deriving its calibration from the
+    /// same zero and full-travel readings through the new transfer function.
+    ///
+    /// `last_raw` is reset so the next scan re-evaluates the key's travel even
+    /// if its reading has not moved.
+    pub const fn set_profile(&mut self, profile: SwitchProfile) {
+        self.profile = profile;
+        self.apply_calib(self.calib_zero, self.entry_full);
+        self.last_raw = u16::MAX;
+    }
+
+    /// This key's inputs to [`crate::matrix::analog_matrix::socd::resolve`].
+    #[must_use]
+    pub const fn socd_side(&self) -> SocdSide {
+        SocdSide { out: self.socd_out, pressed: self.pressed, travel: self.travel }
+    }
+
+    /// Advance the Dynamic Keystroke zone tracker for a new travel reading and
+    /// return the mask of [`DksEvent::bit`]s that fired (`0` for none).
+    ///
+    /// A single step can cross several zone boundaries (a fast press straight
+    /// to the bottom fires both press events); they are checked in
+    /// [`DksEvent::ALL`] order. [`KeyEntry::pressed`] mirrors "past the
+    /// actuation point" so the suspend-wake check still sees a held DKS key.
+    #[inline]
+    #[optimize(speed)]
+    pub fn step_dks(&mut self, new_travel: u8) -> u8 {
+        const BOTTOM: u8 = DKS_BOTTOM_UNIT.saturating_mul(TRAVEL_SCALE);
+        const BOTTOM_RELEASE: u8 = BOTTOM.saturating_sub(DKS_BOTTOM_HYST_UNIT.saturating_mul(TRAVEL_SCALE));
+        let act = self.rt.act_threshold;
+        let mut fired = 0_u8;
+        let mut zone = self.dks_zone;
+        if zone == DksZone::Top && new_travel >= act {
+            fired |= DksEvent::PressPastActuation.bit();
+            zone = DksZone::Middle;
+        }
+        if zone == DksZone::Middle && new_travel >= BOTTOM {
+            fired |= DksEvent::BottomOut.bit();
+            zone = DksZone::Bottom;
+        }
+        if zone == DksZone::Bottom && new_travel < BOTTOM_RELEASE {
+            fired |= DksEvent::ReleaseFromBottom.bit();
+            zone = DksZone::Middle;
+        }
+        if zone == DksZone::Middle && new_travel < act {
+            fired |= DksEvent::ReleasePastActuation.bit();
+            zone = DksZone::Top;
+        }
+        self.dks_zone = zone;
+        self.pressed = zone != DksZone::Top;
+        fired
+    }
+
     /// Apply rapid-trigger logic for a new travel reading and report a press
     /// state transition if one occurred.
     ///
@@ -474,19 +804,70 @@ impl KeyEntry {
     /// or release transition so the next direction starts fresh from the
     /// transition point.
     ///
-    /// [`RtTuning::trough_floor`] clamps the released-side extremum so a key
-    /// driven below the actuation threshold re-fires cleanly at the actuation
-    /// floor without requiring an extra `sensitivity_press` delta above it.
+    /// Dispatches on [`RtTuning::mode`]. With [`RtTuning::enabled`] cleared
+    /// the key ignores the extremum and simply follows its actuation
+    /// threshold in both directions, whatever the mode.
+    #[inline]
+    #[optimize(speed)]
+    pub fn step_rapid_trigger(&mut self, new_travel: u8) -> Option<bool> {
+        let rt = self.rt;
+        let below_act = new_travel < rt.act_threshold;
+        let now_pressed = match (rt.enabled, rt.mode) {
+            // Rapid trigger off: one fixed actuation point for both directions.
+            (false, _) => !below_act,
+            (true, RtMode::Hysteresis) => {
+                // Fixed press and release points; the release point sits
+                // `sensitivity_release` below the actuation point.
+                if self.pressed {
+                    new_travel > rt.act_threshold.saturating_sub(rt.sensitivity_release)
+                } else {
+                    !below_act
+                }
+            },
+            (true, RtMode::Standard) => self.track_extremum(new_travel, below_act),
+            (true, RtMode::Continuous) => {
+                // Disengage only at the very top; until then the actuation
+                // floor no longer applies and the extremum alone decides.
+                if new_travel == 0 {
+                    self.rt_engaged = false;
+                }
+                if self.rt_engaged {
+                    self.track_extremum(new_travel, false)
+                } else {
+                    self.rt_engaged = !below_act;
+                    !below_act
+                }
+            },
+        };
+        let changed = now_pressed != self.pressed;
+        self.pressed = now_pressed;
+        if changed {
+            // Reset extremum so the next direction starts fresh from the
+            // transition point.
+            self.extremum = new_travel;
+        }
+        changed.then_some(now_pressed)
+    }
+
+    /// Dynamic rapid-trigger decision shared by [`RtMode::Standard`] and
+    /// engaged [`RtMode::Continuous`]: update the extremum and return the new
+    /// pressed state.
+    ///
+    /// `below_act` enables the actuation floor. [`RtTuning::trough_floor`]
+    /// clamps the released-side extremum so a key driven below the actuation
+    /// threshold re-fires cleanly at the actuation floor without requiring an
+    /// extra `sensitivity_press` delta above it. Continuous mode passes
+    /// `false` so the key re-fires anywhere in the stroke.
     #[inline]
     #[optimize(speed)]
-    pub fn step_rapid_trigger(&mut self, new_travel: u8, tuning: RtTuning) -> Option<bool> {
-        let below_act = new_travel < tuning.act_threshold;
-        let now_pressed = if self.pressed {
+    fn track_extremum(&mut self, new_travel: u8, below_act: bool) -> bool {
+        let rt = self.rt;
+        if self.pressed {
             // Track the peak while held; release when travel drops at least
             // `sensitivity_release` below it. The actuation floor is a hard
             // lower bound that forces an immediate release.
             self.extremum = self.extremum.max(new_travel);
-            if below_act { false } else { new_travel > self.extremum.saturating_sub(tuning.sensitivity_release) }
+            if below_act { false } else { new_travel > self.extremum.saturating_sub(rt.sensitivity_release) }
         } else {
             // Track the trough while released; re-press when travel climbs at
             // least `sensitivity_press` above it AND exceeds the actuation
@@ -495,20 +876,12 @@ impl KeyEntry {
             // re-fire immediately.
             self.extremum = self.extremum.min(new_travel);
             if below_act {
-                self.extremum = self.extremum.min(tuning.trough_floor);
+                self.extremum = self.extremum.min(rt.trough_floor);
                 false
             } else {
-                new_travel >= self.extremum.saturating_add(tuning.sensitivity_press)
+                new_travel >= self.extremum.saturating_add(rt.sensitivity_press)
             }
-        };
-        let changed = now_pressed != self.pressed;
-        self.pressed = now_pressed;
-        if changed {
-            // Reset extremum so the next direction starts fresh from the
-            // transition point.
-            self.extremum = new_travel;
         }
-        changed.then_some(now_pressed)
     }
 
     /// Convert a raw ADC reading into a travel value.
@@ -516,9 +889,11 @@ impl KeyEntry {
     /// Returns `None` if the position is uncalibrated or `inv_scale == 0`.
     /// Otherwise: look up the per-reading LUT value, subtract `lut_zero`,
     /// multiply by the Q16.16 `inv_scale`, and right-shift to drop the
-    /// fractional bits. The result is in fine travel units; the final clamp
-    /// to [`FULL_TRAVEL_FINE`] keeps bottom-of-travel jitter pinned at the
-    /// maximum.
+    /// fractional bits. The key's dead zones are then applied: the top dead
+    /// zone is subtracted and the remaining live travel stretched by
+    /// [`RtTuning::dz_scale`]. The result is in fine travel units; the final
+    /// clamp to [`FULL_TRAVEL_FINE`] keeps bottom-of-travel jitter (and
+    /// everything inside the bottom dead zone) pinned at the maximum.
     #[inline]
     #[optimize(speed)]
     pub const fn travel_from(&self, raw: u16) -> Option<u8> {
@@ -526,9 +901,13 @@ impl KeyEntry {
             cold_path();
             return None;
         }
-        let delta = lut::lookup(raw).saturating_sub(self.lut_zero);
+        let delta = lut::lookup(self.profile, raw).saturating_sub(self.lut_zero);
         let scaled = u32::from(delta).saturating_mul(self.inv_scale);
-        let travel = scaled.wrapping_shr(INV_SCALE_FRAC_BITS).min(u32::from(FULL_TRAVEL_FINE));
+        let live = scaled.wrapping_shr(INV_SCALE_FRAC_BITS).saturating_sub(u32::from(self.rt.dz_top));
+        let travel = live
+            .saturating_mul(u32::from(self.rt.dz_scale))
+            .wrapping_shr(DZ_SCALE_FRAC_BITS)
+            .min(u32::from(FULL_TRAVEL_FINE));
         Some(u8::try_from(travel).unwrap_or(FULL_TRAVEL_FINE))
     }
 
@@ -545,6 +924,116 @@ impl KeyEntry {
     }
 }
 
+/// Per-key tuning in 0.05 mm configuration units, as persisted in the
+/// EEPROM tuning block and edited from the host.
+///
+/// Converted into the hot-path [`RtTuning`] by [`KeyEntry::apply_tuning`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyTuning {
+    /// Travel threshold in mm/20 units before the key is considered
+    /// actuated; also Dynamic Keystroke's actuation point.
+    pub actuation_pt:     u8,
+    /// Travel at the bottom of the stroke, in mm/20 units, that already
+    /// reads as fully pressed.
+    pub bottom_dead_zone: u8,
+    /// Dynamic Keystroke slot, `0..DKS_SLOTS`; `None` for a normal key.
+    pub dks:              Option<u8>,
+    /// Filter applied to the key's raw readings.
+    pub filter:           KeyFilter,
+    /// Per-key rapid-trigger mode override; `None` follows the board-wide
+    /// mode.
+    pub mode:             Option<RtMode>,
+    /// Whether rapid trigger is active for this key.
+    pub rt_enabled:       bool,
+    /// Minimum upward travel from the trough required to register a new
+    /// press, in mm/20 units.
+    pub rt_press:         u8,
+    /// Minimum downward travel from the peak required to register a release,
+    /// in mm/20 units.
+    pub rt_release:       u8,
+    /// Travel at the top of the stroke, in mm/20 units, that still reads as
+    /// fully released.
+    pub top_dead_zone:    u8,
+}
+
+impl KeyTuning {
+    /// Seed a key's tuning from the board-wide [`HallCfg`] defaults.
+    #[must_use]
+    pub const fn from_cfg(cfg: HallCfg) -> Self {
+        Self {
+            actuation_pt:     cfg.actuation_pt,
+            bottom_dead_zone: cfg.bottom_dead_zone,
+            dks:              None,
+            filter:           cfg.filter,
+            mode:             None,
+            rt_enabled:       cfg.rt_enabled,
+            rt_press:         cfg.rt_sensitivity_press,
+            rt_release:       cfg.rt_sensitivity_release,
+            top_dead_zone:    cfg.top_dead_zone,
+        }
+    }
+
+    /// Whether every distance lies in `1..=FULL_TRAVEL_UNIT`, each dead zone
+    /// in `0..=DEAD_ZONE_MAX_UNIT`, and the DKS slot, if any, exists.
+    ///
+    /// Used to reject corrupt EEPROM data and out-of-range host edits before
+    /// they reach a key; [`RtTuning::from_key`] still clamps defensively.
+    #[must_use]
+    pub const fn is_valid(self) -> bool {
+        let dks_valid = match self.dks {
+            Some(slot) => usize::from(slot) < DKS_SLOTS,
+            None => true,
+        };
+        dks_valid
+            && travel_unit_valid(self.actuation_pt)
+            && travel_unit_valid(self.rt_press)
+            && travel_unit_valid(self.rt_release)
+            && self.top_dead_zone <= DEAD_ZONE_MAX_UNIT
+            && self.bottom_dead_zone <= DEAD_ZONE_MAX_UNIT
+    }
+}
+
+/// Tuning shared by every key rather than stored per key: the board-wide
+/// rapid-trigger mode, the Dynamic Keystroke slot bindings, the SOCD pairs,
+/// and the gamepad and analog mouse settings. Persisted in the EEPROM tuning
+/// block alongside the per-key table.
+#[derive(Clone, Copy)]
+pub struct BoardTuning {
+    /// Binding table of each Dynamic Keystroke slot.
+    pub dks:            [DksMap; DKS_SLOTS],
+    /// Key bound to each gamepad input, indexed by [`GamepadInput::index`].
+    pub gamepad:        [Option<MatrixPos>; GAMEPAD_INPUTS],
+    /// Layers on which the gamepad is active, bit `n` for layer `n`.
+    pub gamepad_layers: u8,
+    /// Key bound to each analog mouse input, indexed by
+    /// [`MouseInput::index`].
+    pub mouse:          [Option<MatrixPos>; MOUSE_INPUTS],
+    /// Analog mouse curve, dead zone, and layers.
+    pub mouse_settings: MouseSettings,
+    /// Rapid-trigger mode for keys without a per-key override.
+    pub rt_mode:        RtMode,
+    /// SOCD pair slots; `None` marks an unused slot.
+    pub socd:           [Option<SocdPair>; SOCD_PAIRS],
+}
+
+impl BoardTuning {
+    /// Seed from the [`HallCfg`] defaults, with every DKS slot at
+    /// [`DksMap::DEFAULT`], no SOCD pairs, and the gamepad and analog mouse
+    /// unbound and off.
+    #[must_use]
+    pub const fn from_cfg(cfg: HallCfg) -> Self {
+        Self {
+            dks:            [DksMap::DEFAULT; DKS_SLOTS],
+            gamepad:        [None; GAMEPAD_INPUTS],
+            gamepad_layers: 0,
+            mouse:          [None; MOUSE_INPUTS],
+            mouse_settings: MouseSettings::DEFAULT,
+            rt_mode:        cfg.rt_mode,
+            socd:           [None; SOCD_PAIRS],
+        }
+    }
+}
+
 /// Coarse millisecond-scale timestamp for the auto-calibrator's
 /// release-time bound.
 ///
@@ -620,6 +1109,47 @@ pub const fn full_from_min(zero: u16, observed_min: u16) -> u16 {
     observed_min.saturating_add(BOTTOM_JITTER).min(zero.saturating_sub(MIN_USEFUL_FULL_RANGE)).max(VALID_RAW_MIN)
 }
 
+/// Standard deviation, in 1/16 ADC counts, of `passes` readings summing to
+/// `sum` with squares summing to `sum_sq`; [`NOISE_UNMEASURED`] for fewer
+/// than two passes.
+///
+/// Uses the integer form `sqrt(256 * (n * sum_sq - sum^2)) / n`, which stays
+/// exact in `u64` for any 12-bit reading over the default 512 passes.
+#[must_use]
+pub const fn noise_from(sum: u32, sum_sq: u64, passes: u32) -> u16 {
+    if passes < 2 {
+        return NOISE_UNMEASURED;
+    }
+    let n = u64::from(passes);
+    let spread = n.saturating_mul(sum_sq).saturating_sub(u64::from(sum).saturating_mul(u64::from(sum)));
+    let scaled = spread.saturating_mul(1_u64.wrapping_shl(NOISE_FRAC_BITS.saturating_mul(2)));
+    let noise = scaled.isqrt().checked_div(n).unwrap_or(0);
+    match u16::try_from(noise) {
+        Ok(noise) if noise != NOISE_UNMEASURED => noise,
+        _ => NOISE_UNMEASURED.saturating_sub(1),
+    }
+}
+
+/// Per-key noise gate for a resting `noise` in 1/16 ADC counts:
+/// [`NOISE_GATE_SIGMAS`] standard deviations, rounded up and clamped to
+/// `NOISE_GATE_MIN..=NOISE_GATE_MAX`.
+#[must_use]
+pub const fn noise_gate_from(noise: u16) -> u16 {
+    let sigmas = u32::from(noise).saturating_mul(u32::from(NOISE_GATE_SIGMAS));
+    let one = 1_u32.wrapping_shl(NOISE_FRAC_BITS);
+    let gate = sigmas.saturating_add(one.saturating_sub(1)).wrapping_shr(NOISE_FRAC_BITS);
+    match u16::try_from(gate) {
+        Ok(gate) => gate.clamp(NOISE_GATE_MIN, NOISE_GATE_MAX),
+        Err(_) => NOISE_GATE_MAX,
+    }
+}
+
+/// Whether `value` is a usable travel distance in configuration units:
+/// non-zero and no deeper than [`FULL_TRAVEL_UNIT`].
+#[must_use]
+#[inline]
+pub const fn travel_unit_valid(value: u8) -> bool { value != 0 && value <= FULL_TRAVEL_UNIT }
+
 /// Whether a resting (zero-travel) ADC reading is close enough to
 /// [`REF_ZERO_TRAVEL`] to indicate a working hall sensor at that position.
 ///
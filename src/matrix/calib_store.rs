@@ -1,25 +1,163 @@
 use crate::{
-    layout::{COL, ROW},
-    matrix::analog_matrix::types::KeyEntry,
+    eeprom::{EEPROM_SIZE, PAGE_SIZE},
+    layout::{COL, LAYOUT_ID, ROW},
+    matrix::{
+        analog_matrix::types::{KeyEntry, SwitchProfile},
+        tuning_store::{TUNING_BASE_ADDR, TUNING_BUF_LEN},
+    },
 };
 use core::mem::size_of;
-use embassy_stm32::crc::Crc;
 
 /// Pre-computed buffer length for the HE matrix.
 pub const CALIB_BUF_LEN: usize = total_len(ROW, COL);
 /// Byte length of the trailing CRC field.
 const CRC_LEN: usize = size_of::<u32>();
-/// EEPROM word address at which the calibration block begins.
+/// EEPROM word address where the single calibration block of earlier
+/// firmware lived; its first page now holds the partition table (see
+/// [`crate::eeprom::partition`]), and the block is read there only to
+/// migrate it.
 pub const EEPROM_BASE_ADDR: u16 = 0x0000;
-/// Byte length of a single serialized entry (one u16 full-travel value).
-const ENTRY_LEN: usize = size_of::<u16>();
-/// Byte length of the header: magic + version.
-const HEADER_LEN: usize = size_of::<u32>().saturating_add(size_of::<u8>());
-/// Format version. Increment on any incompatible layout change to force a
-/// first-boot re-calibration when old EEPROM data is found.
-const VERSION: u8 = 1;
+/// Byte length of a single serialized entry: full-travel reading, zero
+/// reading, and resting noise (2 B LE each), then the flags byte.
+const ENTRY_LEN: usize = size_of::<u16>().saturating_mul(3).saturating_add(size_of::<u8>());
+/// Entry bits holding the full-travel ADC reading; the 12-bit ADC never sets
+/// the bits above.
+const ENTRY_FULL_MASK: u16 = 0x0FFF;
+/// Flags-byte bits holding the key's [`SwitchProfile::code`].
+const FLAG_PROFILE_MASK: u8 = 0b1111_0000;
+/// Shift of [`FLAG_PROFILE_MASK`] within the flags byte.
+const FLAG_PROFILE_SHIFT: u32 = 4;
+/// Flags-byte bit set when the key was scanning (a plausible resting
+/// reading) when the block was written.
+const FLAG_SENSOR: u8 = 0b0000_0001;
+/// Byte length of the header: magic, version, layout, board profile, write
+/// counter.
+const HEADER_LEN: usize = WRITES_OFFSET.saturating_add(size_of::<u32>());
+/// Byte offset of the [`LAYOUT_ID`] in the header.
+const LAYOUT_OFFSET: usize = VERSION_OFFSET.saturating_add(size_of::<u8>());
 /// Magic number identifying a valid Q6 HE calibration block.
 const MAGIC: u32 = 0x5136_4845;
+/// EEPROM word address of calibration slot A: the page after the partition
+/// table.
+pub const SLOT_A_ADDR: u16 = EEPROM_BASE_ADDR.saturating_add(u16::try_from(PAGE_SIZE).unwrap_or(u16::MAX));
+/// EEPROM word address of calibration slot B: the first page boundary after
+/// the tuning block, so no write to one block shares a page with another.
+const SLOT_B_ADDR: u16 =
+    TUNING_BASE_ADDR.saturating_add(u16::try_from(TUNING_BUF_LEN.next_multiple_of(PAGE_SIZE)).unwrap_or(u16::MAX));
+/// Byte offset of the board profile in the header: the
+/// [`SwitchProfile::code`] shared by every scanning key, or
+/// [`PROFILE_MIXED`].
+const PROFILE_OFFSET: usize = LAYOUT_OFFSET.saturating_add(size_of::<u8>());
+/// Board profile of a block whose scanning keys use different profiles.
+const PROFILE_MIXED: u8 = 0xFF;
+/// Byte length of a v1 entry: the full-travel reading in bits 0-11 and the
+/// [`SwitchProfile::code`] in bits 12-15 (2 B LE).
+const V1_ENTRY_LEN: usize = size_of::<u16>();
+/// Byte length of a v1 header: magic + version.
+const V1_HEADER_LEN: usize = LAYOUT_OFFSET;
+/// Shift of the [`SwitchProfile::code`] in a v1 entry.
+const V1_PROFILE_SHIFT: u32 = 12;
+/// Previous format version, holding only the full-travel reading and switch
+/// profile per key. Still read, and migrated by the caller.
+const V1_VERSION: u8 = 1;
+/// Format version. Increment on any incompatible layout change; an unknown
+/// version forces a first-boot re-calibration.
+const VERSION: u8 = 2;
+/// Byte offset of the version byte in the header.
+const VERSION_OFFSET: usize = size_of::<u32>();
+/// Byte offset of the write counter (4 B LE) in the header.
+const WRITES_OFFSET: usize = PROFILE_OFFSET.saturating_add(size_of::<u8>());
+
+const _: () = assert!(
+    usize::from(SLOT_B_ADDR).saturating_add(CALIB_BUF_LEN) <= EEPROM_SIZE,
+    "calibration slot B does not fit in the EEPROM"
+);
+
+/// One of the two calibration slots.
+///
+/// A new block always goes into the slot not holding the current one and
+/// takes over only once it reads back valid, so losing power mid-write never
+/// destroys the last good calibration. On load the valid slot with the newer
+/// write counter wins (see [`is_newer`]).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CalibSlot {
+    /// Slot at [`SLOT_A_ADDR`].
+    A,
+    /// Slot after the tuning block.
+    B,
+}
+
+impl CalibSlot {
+    /// Both slots, in load order.
+    pub const ALL: [Self; 2] = [Self::A, Self::B];
+
+    /// EEPROM word address of the slot.
+    #[must_use]
+    pub const fn addr(self) -> u16 {
+        match self {
+            Self::A => SLOT_A_ADDR,
+            Self::B => SLOT_B_ADDR,
+        }
+    }
+
+    /// The other slot.
+    #[must_use]
+    pub const fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+}
+
+/// The slot holding the current calibration block, and that block's write
+/// counter.
+#[derive(Clone, Copy)]
+pub struct StoredCalib {
+    /// Slot holding the block.
+    pub slot:   CalibSlot,
+    /// Write counter of the block.
+    pub writes: u32,
+}
+
+impl StoredCalib {
+    /// Slot and write counter for the block written after `current`: the
+    /// other slot, so `current` survives until the new block verifies. With
+    /// nothing stored, slot A.
+    #[must_use]
+    pub const fn next(current: Option<Self>) -> Self {
+        match current {
+            Some(stored) => Self { slot: stored.slot.other(), writes: stored.writes.wrapping_add(1) },
+            None => Self { slot: CalibSlot::A, writes: 1 },
+        }
+    }
+}
+
+/// CRC-32 engine fed whole 32-bit words, as the STM32 CRC peripheral is.
+///
+/// Every checksum of persisted data goes through [`crc32_of`] and this
+/// trait, so the stores also run on a host against a software CRC.
+pub trait Crc32 {
+    /// Feed one word.
+    fn feed_word(&mut self, word: u32);
+
+    /// CRC of every word fed since the last [`Crc32::reset`].
+    fn read(&self) -> u32;
+
+    /// Restart from the initial value.
+    fn reset(&mut self);
+}
+
+/// Outcome of a successful [`try_deserialize`].
+#[derive(Clone, Copy)]
+pub struct CalibLoad {
+    /// Whether the block was in the v1 format. It lacks the zero and noise
+    /// figures, so the caller rewrites it as v2 once the boot zero pass has
+    /// measured them.
+    pub migrated: bool,
+    /// Write counter of the block; `0` for a v1 block, which had none.
+    pub writes:   u32,
+}
 
 /// Copy exactly `N` bytes from `buf[start..end]` into a fixed-size array.
 ///
@@ -29,7 +167,7 @@ const MAGIC: u32 = 0x5136_4845;
 /// checksums and serialized values.
 #[must_use]
 #[inline]
-fn read_array<const N: usize>(buf: &[u8], start: usize, end: usize) -> Option<[u8; N]> {
+pub fn read_array<const N: usize>(buf: &[u8], start: usize, end: usize) -> Option<[u8; N]> {
     if let Some(src) = buf.get(start..end)
         && let Ok(fixed) = <[u8; N]>::try_from(src)
     {
@@ -38,11 +176,12 @@ fn read_array<const N: usize>(buf: &[u8], start: usize, end: usize) -> Option<[u
     None
 }
 
-/// Compute CRC-32 over `data` using the STM32 hardware CRC peripheral.
+/// Compute CRC-32 over `data`, on the board with the STM32 hardware CRC
+/// peripheral.
 ///
 /// Feeds data as 32-bit little-endian words. If `data.len()` is not a
 /// multiple of 4 the final word is zero-padded before feeding.
-fn crc32_of(crc: &mut Crc<'_>, data: &[u8]) -> u32 {
+pub fn crc32_of(crc: &mut dyn Crc32, data: &[u8]) -> u32 {
     crc.reset();
     let (chunks, remainder) = data.as_chunks::<4>();
     for &chunk in chunks {
@@ -58,26 +197,34 @@ fn crc32_of(crc: &mut Crc<'_>, data: &[u8]) -> u32 {
     crc.read()
 }
 
-/// Serialize `keys` (column-major `[[KeyEntry; ROW]; COL]`) into `buf`.
+/// Serialize `keys` (column-major `[[KeyEntry; ROW]; COL]`) into `buf` as
+/// the `writes`-th write of the block.
 ///
 /// Entries are written in column-major order (col outer, row inner) matching
 /// the in-memory layout, so no transposition is needed.
 ///
-/// Format: magic (4 B LE) | version (1 B) | entries (COL×ROW×2 B LE) | CRC-32
-/// (4 B LE). `buf` must be at least `total_len(ROW, COL)` bytes (see
+/// Format: magic (4 B LE) | version (1 B) | [`LAYOUT_ID`] (1 B) | board
+/// profile (1 B) | write counter (4 B LE) | entries (COL×ROW×7 B) | CRC-32
+/// (4 B LE). Each entry is [`KeyEntry::entry_full`],
+/// [`KeyEntry::calib_zero`], and [`KeyEntry::noise`] (2 B LE each), then a
+/// flags byte holding [`FLAG_SENSOR`] and the key's [`SwitchProfile::code`]
+/// in bits 4-7. `buf` must be at least `total_len(ROW, COL)` bytes (see
 /// [`total_len`]).
 pub fn serialize<const ROW: usize, const COL: usize>(
     keys: &[[KeyEntry; ROW]; COL],
+    writes: u32,
     buf: &mut [u8; CALIB_BUF_LEN],
-    crc: &mut Crc<'_>,
+    crc: &mut dyn Crc32,
 ) {
     // Write magic number, 4 bytes little-endian.
-    if let Some(dst) = buf.get_mut(0..size_of::<u32>()) {
+    if let Some(dst) = buf.get_mut(0..VERSION_OFFSET) {
         dst.copy_from_slice(&MAGIC.to_le_bytes());
     }
-    // Write version byte.
-    if let Some(version_byte) = buf.get_mut(size_of::<u32>()) {
-        *version_byte = VERSION;
+    if let Some(dst) = buf.get_mut(VERSION_OFFSET..WRITES_OFFSET) {
+        dst.copy_from_slice(&[VERSION, LAYOUT_ID, board_profile(keys)]);
+    }
+    if let Some(dst) = buf.get_mut(WRITES_OFFSET..HEADER_LEN) {
+        dst.copy_from_slice(&writes.to_le_bytes());
     }
     // Write the entries as fixed-size chunks: `as_chunks_mut` needs no
     // per-entry offset bookkeeping, and the zip bounds both sides at once.
@@ -87,7 +234,7 @@ pub fn serialize<const ROW: usize, const COL: usize>(
     if let Some(entry_bytes) = buf.get_mut(HEADER_LEN..crc_start) {
         let (chunks, _) = entry_bytes.as_chunks_mut::<ENTRY_LEN>();
         for (dst, key) in chunks.iter_mut().zip(keys.as_flattened()) {
-            *dst = key.entry_full.to_le_bytes();
+            *dst = encode_entry(key);
         }
     }
     // Compute CRC over the header + all entry bytes.
@@ -98,60 +245,158 @@ pub fn serialize<const ROW: usize, const COL: usize>(
     }
 }
 
+/// Whether write counter `writes` is newer than `than`, allowing for the
+/// counter wrapping around.
+#[must_use]
+pub const fn is_newer(writes: u32, than: u32) -> bool { writes.wrapping_sub(than).cast_signed() > 0 }
+
+/// Write counter of the calibration block in `buf`, `0` for a v1 block, or
+/// `None` if it does not validate; checks the same header fields and CRC as
+/// [`try_deserialize`] without decoding the entries.
+pub fn sequence<const ROW: usize, const COL: usize>(buf: &[u8], crc: &mut dyn Crc32) -> Option<u32> {
+    let version = validate::<ROW, COL>(buf, crc)?;
+    if version == V1_VERSION {
+        return Some(0);
+    }
+    Some(u32::from_le_bytes(read_array::<4>(buf, WRITES_OFFSET, HEADER_LEN)?))
+}
+
 /// Attempt to deserialize a calibration block from `buf` into `out`.
 ///
-/// Validates the magic number, version byte, and CRC-32 checksum.
-/// Returns `true` and populates [`KeyEntry::entry_full`] for every position
-/// in `out` on success. Returns `false` without modifying `out` on any
-/// validation failure, including a VERSION mismatch which rejects data
-/// written by an incompatible layout.
+/// Validates the magic number, version byte, layout, and CRC-32 checksum.
+/// On success populates [`KeyEntry::entry_full`], [`KeyEntry::calib_zero`],
+/// [`KeyEntry::noise`], and [`KeyEntry::profile`] for every position in
+/// `out`; a v1 block only carries the full-travel reading and profile, and
+/// is reported as [`CalibLoad::migrated`] instead of being rejected. Returns
+/// `None` without modifying `out` on any validation failure, including an
+/// unknown version, a block written by a build for another layout (whose
+/// sensor positions differ), and an unknown switch profile.
 pub fn try_deserialize<const ROW: usize, const COL: usize>(
     buf: &[u8],
     out: &mut [[KeyEntry; ROW]; COL],
-    crc: &mut Crc<'_>,
-) -> bool {
-    // Length check against the generic dimensions (matching `crc_end` below)
-    // rather than the crate-level CALIB_BUF_LEN, so the validation stays
-    // self-consistent for any ROW/COL instantiation.
-    if buf.len() < total_len(ROW, COL) {
-        return false;
-    }
-    // Validate magic number.
-    let magic_end = size_of::<u32>();
-    let Some(magic_bytes) = read_array::<4>(buf, 0, magic_end) else { return false };
-    if u32::from_le_bytes(magic_bytes) != MAGIC {
-        return false;
-    }
-    // Validate version byte.
-    let Some(&stored_version) = buf.get(size_of::<u32>()) else { return false };
-    if stored_version != VERSION {
-        return false;
-    }
-    // Validate CRC over header + entries.
-    let crc_end = total_len(ROW, COL);
-    let data_end = crc_end.saturating_sub(CRC_LEN);
-    // None must not be silently replaced with 0 (0 is a valid CRC value).
-    let Some(stored_crc_bytes) = read_array::<4>(buf, data_end, crc_end) else { return false };
-    let stored_crc = u32::from_le_bytes(stored_crc_bytes);
-    let computed_crc = buf.get(..data_end).map_or(0, |data| crc32_of(crc, data));
-    if computed_crc != stored_crc {
-        return false;
+    crc: &mut dyn Crc32,
+) -> Option<CalibLoad> {
+    if validate::<ROW, COL>(buf, crc)? == V1_VERSION {
+        let entry_bytes = buf.get(V1_HEADER_LEN..v1_total_len(ROW, COL).saturating_sub(CRC_LEN))?;
+        return deserialize_v1(entry_bytes, out).then_some(CalibLoad { migrated: true, writes: 0 });
     }
+    let entry_bytes = buf.get(HEADER_LEN..total_len(ROW, COL).saturating_sub(CRC_LEN))?;
     // Deserialize entries directly into each key's persistent calibration
-    // slot. The entry region is validated in full before the first write, so
-    // a validation failure can never leave `out` partially updated.
-    let Some(entry_bytes) = buf.get(HEADER_LEN..data_end) else { return false };
+    // slots. The entry region is validated in full before the first write,
+    // so a validation failure can never leave `out` partially updated.
     let (chunks, remainder) = entry_bytes.as_chunks::<ENTRY_LEN>();
-    if !remainder.is_empty() || chunks.len() != ROW.saturating_mul(COL) {
+    if !remainder.is_empty()
+        || chunks.len() != ROW.saturating_mul(COL)
+        || chunks.iter().any(|&chunk| entry_profile(chunk).is_none())
+    {
+        return None;
+    }
+    for (key, &chunk) in out.as_flattened_mut().iter_mut().zip(chunks.iter()) {
+        let [full_lo, full_hi, zero_lo, zero_hi, noise_lo, noise_hi, _] = chunk;
+        key.entry_full = u16::from_le_bytes([full_lo, full_hi]) & ENTRY_FULL_MASK;
+        key.calib_zero = u16::from_le_bytes([zero_lo, zero_hi]);
+        key.noise = u16::from_le_bytes([noise_lo, noise_hi]);
+        key.profile = entry_profile(chunk).unwrap_or_default();
+    }
+    let writes = u32::from_le_bytes(read_array::<4>(buf, WRITES_OFFSET, HEADER_LEN)?);
+    Some(CalibLoad { migrated: false, writes })
+}
+
+/// [`SwitchProfile::code`] shared by every scanning key of `keys`, or
+/// [`PROFILE_MIXED`] if they differ. Recorded in the header so a block can
+/// be told apart at a glance without decoding every entry.
+fn board_profile<const ROW: usize, const COL: usize>(keys: &[[KeyEntry; ROW]; COL]) -> u8 {
+    let mut codes = keys.as_flattened().iter().filter(|key| key.calib_used).map(|key| key.profile.code());
+    let first = codes.next().unwrap_or_else(|| SwitchProfile::default().code());
+    if codes.all(|code| code == first) { first } else { PROFILE_MIXED }
+}
+
+/// Decode the v1 `entry_bytes` into `out`, touching only the full-travel
+/// reading and profile of each key; the stored zero and noise keep their
+/// defaults until the boot zero pass measures them.
+///
+/// Returns `false` without modifying `out` if the region does not hold one
+/// entry per key or names an unknown switch profile.
+fn deserialize_v1<const ROW: usize, const COL: usize>(entry_bytes: &[u8], out: &mut [[KeyEntry; ROW]; COL]) -> bool {
+    let (chunks, remainder) = entry_bytes.as_chunks::<V1_ENTRY_LEN>();
+    if !remainder.is_empty()
+        || chunks.len() != ROW.saturating_mul(COL)
+        || chunks.iter().any(|&chunk| v1_entry_profile(chunk).is_none())
+    {
         return false;
     }
     for (key, &chunk) in out.as_flattened_mut().iter_mut().zip(chunks.iter()) {
-        key.entry_full = u16::from_le_bytes(chunk);
+        key.entry_full = u16::from_le_bytes(chunk) & ENTRY_FULL_MASK;
+        key.profile = v1_entry_profile(chunk).unwrap_or_default();
     }
     true
 }
 
+/// Encode one key as a v2 entry.
+fn encode_entry(key: &KeyEntry) -> [u8; ENTRY_LEN] {
+    let [full_lo, full_hi] = (key.entry_full & ENTRY_FULL_MASK).to_le_bytes();
+    let [zero_lo, zero_hi] = key.calib_zero.to_le_bytes();
+    let [noise_lo, noise_hi] = key.noise.to_le_bytes();
+    let sensor = if key.calib_used { FLAG_SENSOR } else { 0 };
+    let flags = sensor | (key.profile.code().wrapping_shl(FLAG_PROFILE_SHIFT) & FLAG_PROFILE_MASK);
+    [full_lo, full_hi, zero_lo, zero_hi, noise_lo, noise_hi, flags]
+}
+
+/// Switch profile stored in the flags byte of `entry`, `None` if unknown.
+fn entry_profile(entry: [u8; ENTRY_LEN]) -> Option<SwitchProfile> {
+    let [.., flags] = entry;
+    SwitchProfile::from_code((flags & FLAG_PROFILE_MASK).wrapping_shr(FLAG_PROFILE_SHIFT))
+}
+
 /// Compute the total serialized byte length for a `rows × cols` matrix.
 pub const fn total_len(rows: usize, cols: usize) -> usize {
     HEADER_LEN.saturating_add(rows.saturating_mul(cols).saturating_mul(ENTRY_LEN)).saturating_add(CRC_LEN)
 }
+
+/// Switch profile stored in the upper bits of the v1 `entry`, `None` if
+/// unknown.
+fn v1_entry_profile(entry: [u8; V1_ENTRY_LEN]) -> Option<SwitchProfile> {
+    let code = u16::from_le_bytes(entry).wrapping_shr(V1_PROFILE_SHIFT);
+    SwitchProfile::from_code(u8::try_from(code).ok()?)
+}
+
+/// Validate the header and CRC-32 of the calibration block in `buf`,
+/// returning its version byte.
+///
+/// Checks the magic number, a known version, the CRC over header and
+/// entries, and for a v2 block the [`LAYOUT_ID`]; a v1 block predates the
+/// layout byte.
+fn validate<const ROW: usize, const COL: usize>(buf: &[u8], crc: &mut dyn Crc32) -> Option<u8> {
+    // Validate magic number.
+    let magic_bytes = read_array::<4>(buf, 0, VERSION_OFFSET)?;
+    if u32::from_le_bytes(magic_bytes) != MAGIC {
+        return None;
+    }
+    // Dispatch on the version byte; the length checks use the generic
+    // dimensions rather than the crate-level buffer lengths, so the
+    // validation stays self-consistent for any ROW/COL instantiation.
+    let version = *buf.get(VERSION_OFFSET)?;
+    let crc_end = match version {
+        VERSION => total_len(ROW, COL),
+        V1_VERSION => v1_total_len(ROW, COL),
+        _ => return None,
+    };
+    // Validate CRC over header + entries. None must not be silently replaced
+    // with 0 (0 is a valid CRC value).
+    let data_end = crc_end.checked_sub(CRC_LEN)?;
+    let stored_crc = u32::from_le_bytes(read_array::<4>(buf, data_end, crc_end)?);
+    let computed_crc = buf.get(..data_end).map_or(0, |data| crc32_of(crc, data));
+    if computed_crc != stored_crc {
+        return None;
+    }
+    if version == VERSION && *buf.get(LAYOUT_OFFSET)? != LAYOUT_ID {
+        return None;
+    }
+    Some(version)
+}
+
+/// Compute the serialized byte length of a v1 block for a `rows × cols`
+/// matrix.
+const fn v1_total_len(rows: usize, cols: usize) -> usize {
+    V1_HEADER_LEN.saturating_add(rows.saturating_mul(cols).saturating_mul(V1_ENTRY_LEN)).saturating_add(CRC_LEN)
+}
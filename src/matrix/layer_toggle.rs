@@ -4,7 +4,7 @@ use embassy_time::Duration;
 use rmk::{event::KeyboardEvent, macros::input_device};
 
 /// Matrix coordinates for a key position.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct MatrixPos {
     /// Column index within the matrix.
     pub col: u8,
@@ -12,6 +12,35 @@ pub struct MatrixPos {
     pub row: u8,
 }
 
+impl MatrixPos {
+    /// Byte length of an encoded position: row, column.
+    pub const ENCODED_LEN: usize = 2;
+    /// Row byte of an encoded position that marks "no key".
+    const NONE_ROW: u8 = u8::MAX;
+
+    /// Decode a position encoded by [`MatrixPos::encode`]. Returns
+    /// `Some(None)` for "no key" and `None` for a position outside the
+    /// `rows` × `cols` matrix.
+    #[must_use]
+    pub const fn decode(bytes: [u8; Self::ENCODED_LEN], rows: usize, cols: usize) -> Option<Option<Self>> {
+        let [row, col] = bytes;
+        if row == Self::NONE_ROW {
+            return Some(None);
+        }
+        if usize::from(row) < rows && usize::from(col) < cols { Some(Some(Self { col, row })) } else { None }
+    }
+
+    /// Encode `pos` as row, column, or "no key" for `None`; used for the
+    /// key bindings stored in the EEPROM tuning block and sent to the host.
+    #[must_use]
+    pub const fn encode(pos: Option<Self>) -> [u8; Self::ENCODED_LEN] {
+        match pos {
+            Some(pos) => [pos.row, pos.col],
+            None => [Self::NONE_ROW, 0],
+        }
+    }
+}
+
 /// Input device that toggles layers based on a switch position.
 #[input_device(publish = KeyboardEvent)]
 pub struct LayerToggle<'peripherals> {
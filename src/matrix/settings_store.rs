@@ -0,0 +1,156 @@
+use crate::{
+    eeprom::{EEPROM_SIZE, PAGE_SIZE},
+    matrix::{
+        analog_matrix::{HallCfg, types::KeyTuning},
+        calib_store::{CALIB_BUF_LEN, CalibSlot, Crc32, crc32_of, read_array},
+        tuning_store::{self, ENTRY_LEN},
+    },
+};
+use core::{mem::size_of, ops::RangeInclusive};
+use embassy_time::Duration;
+
+/// Accepted [`HallCfg::calib_passes`]: enough passes to measure each key's
+/// resting noise, few enough that the boot zero pass stays short.
+const CALIB_PASSES: RangeInclusive<u16> = 64..=4096;
+/// Byte length of the trailing CRC field.
+const CRC_LEN: usize = size_of::<u32>();
+/// Byte length of encoded settings (see [`encode_settings`]).
+pub const ENCODED_LEN: usize = ENTRY_LEN.saturating_add(SENSING_LEN);
+/// Accepted [`HallCfg::full_calib_duration`], in seconds.
+const FULL_CALIB_SECS: RangeInclusive<u16> = 30..=600;
+/// Byte length of the header: magic + version.
+const HEADER_LEN: usize = size_of::<u32>().saturating_add(size_of::<u8>());
+/// Accepted [`HallCfg::key_calib_duration`], in seconds.
+const KEY_CALIB_SECS: RangeInclusive<u8> = 5..=120;
+/// Magic number identifying a valid Q6 HE settings record.
+const MAGIC: u32 = 0x5136_5354;
+/// Accepted [`HallCfg::noise_gate`]: a gate of `0` would pass every reading,
+/// and one past the top would swallow a light press.
+const NOISE_GATE: RangeInclusive<u16> = 1..=200;
+/// Byte length of the sensing fields that follow the tuning defaults: noise
+/// gate, zero-pass passes, and full-travel window (2 B LE each), then the
+/// single-key window.
+const SENSING_LEN: usize = size_of::<u16>().saturating_mul(3).saturating_add(size_of::<u8>());
+/// EEPROM word address of the settings record: the first page boundary after
+/// calibration slot B, so it shares a page with no other block.
+pub const SETTINGS_BASE_ADDR: u16 =
+    CalibSlot::B.addr().saturating_add(u16::try_from(CALIB_BUF_LEN.next_multiple_of(PAGE_SIZE)).unwrap_or(u16::MAX));
+/// Byte length of the settings record.
+pub const SETTINGS_BUF_LEN: usize = HEADER_LEN.saturating_add(ENCODED_LEN).saturating_add(CRC_LEN);
+/// Format version. Increment on any incompatible layout change; a mismatch
+/// falls back to the built-in [`HallCfg`].
+const VERSION: u8 = 1;
+
+const _: () = assert!(
+    usize::from(SETTINGS_BASE_ADDR).saturating_add(SETTINGS_BUF_LEN) <= EEPROM_SIZE,
+    "settings record does not fit in the EEPROM"
+);
+
+/// Decode settings encoded by [`encode_settings`], returning `None` if the
+/// tuning defaults fail [`tuning_store::decode_entry`], carry no board-wide
+/// mode or a DKS slot, or any sensing field is out of its accepted range.
+pub fn decode_settings(bytes: [u8; ENCODED_LEN]) -> Option<HallCfg> {
+    let tuning = tuning_store::decode_entry(read_array::<ENTRY_LEN>(&bytes, 0, ENTRY_LEN)?)?;
+    if tuning.dks.is_some() {
+        return None;
+    }
+    let [gate_lo, gate_hi, passes_lo, passes_hi, full_lo, full_hi, key_secs] =
+        read_array::<SENSING_LEN>(&bytes, ENTRY_LEN, ENCODED_LEN)?;
+    let noise_gate = u16::from_le_bytes([gate_lo, gate_hi]);
+    let calib_passes = u16::from_le_bytes([passes_lo, passes_hi]);
+    let full_secs = u16::from_le_bytes([full_lo, full_hi]);
+    if !NOISE_GATE.contains(&noise_gate)
+        || !CALIB_PASSES.contains(&calib_passes)
+        || !FULL_CALIB_SECS.contains(&full_secs)
+        || !KEY_CALIB_SECS.contains(&key_secs)
+    {
+        return None;
+    }
+    Some(HallCfg {
+        actuation_pt: tuning.actuation_pt,
+        bottom_dead_zone: tuning.bottom_dead_zone,
+        calib_passes: u32::from(calib_passes),
+        filter: tuning.filter,
+        full_calib_duration: Duration::from_secs(u64::from(full_secs)),
+        key_calib_duration: Duration::from_secs(u64::from(key_secs)),
+        noise_gate,
+        rt_enabled: tuning.rt_enabled,
+        rt_mode: tuning.mode?,
+        rt_sensitivity_press: tuning.rt_press,
+        rt_sensitivity_release: tuning.rt_release,
+        top_dead_zone: tuning.top_dead_zone,
+    })
+}
+
+/// Encode the settings `cfg`; shared with the host protocol so both use one
+/// wire format.
+///
+/// The tuning defaults come first, laid out as a tuning entry (see
+/// [`tuning_store::encode_entry`]) whose mode bits hold the board-wide
+/// [`HallCfg::rt_mode`]. Then the fallback noise gate, the zero-pass passes,
+/// and the full-travel window in seconds (2 B LE each), and the single-key
+/// window in seconds (1 B).
+pub fn encode_settings(cfg: HallCfg) -> [u8; ENCODED_LEN] {
+    let tuning = KeyTuning { mode: Some(cfg.rt_mode), ..KeyTuning::from_cfg(cfg) };
+    let calib_passes = u16::try_from(cfg.calib_passes).unwrap_or(u16::MAX);
+    let full_secs = u16::try_from(cfg.full_calib_duration.as_secs()).unwrap_or(u16::MAX);
+    let key_secs = u8::try_from(cfg.key_calib_duration.as_secs()).unwrap_or(u8::MAX);
+    let mut bytes = [0_u8; ENCODED_LEN];
+    let fields: [&[u8]; 5] = [
+        &tuning_store::encode_entry(tuning),
+        &cfg.noise_gate.to_le_bytes(),
+        &calib_passes.to_le_bytes(),
+        &full_secs.to_le_bytes(),
+        &[key_secs],
+    ];
+    let mut offset = 0_usize;
+    for field in fields {
+        let end = offset.saturating_add(field.len());
+        if let Some(dst) = bytes.get_mut(offset..end) {
+            dst.copy_from_slice(field);
+        }
+        offset = end;
+    }
+    bytes
+}
+
+/// Serialize the settings `cfg` into `buf`.
+///
+/// Format: magic (4 B LE) | version (1 B) | settings ([`ENCODED_LEN`] B,
+/// see [`encode_settings`]) | CRC-32 (4 B LE), mirroring the calibration and
+/// tuning blocks so all three share one validation scheme.
+pub fn serialize(cfg: HallCfg, buf: &mut [u8; SETTINGS_BUF_LEN], crc: &mut dyn Crc32) {
+    if let Some(dst) = buf.get_mut(0..size_of::<u32>()) {
+        dst.copy_from_slice(&MAGIC.to_le_bytes());
+    }
+    if let Some(version_byte) = buf.get_mut(size_of::<u32>()) {
+        *version_byte = VERSION;
+    }
+    let data_end = HEADER_LEN.saturating_add(ENCODED_LEN);
+    if let Some(dst) = buf.get_mut(HEADER_LEN..data_end) {
+        dst.copy_from_slice(&encode_settings(cfg));
+    }
+    let checksum = buf.get(..data_end).map_or(0, |data| crc32_of(crc, data));
+    if let Some(dst) = buf.get_mut(data_end..SETTINGS_BUF_LEN) {
+        dst.copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+/// Attempt to deserialize a settings record from `buf`.
+///
+/// Validates the magic number, version byte, and CRC-32 checksum, then
+/// decodes the settings with [`decode_settings`]. Returns `None` on any
+/// validation failure, leaving the caller on the built-in [`HallCfg`].
+pub fn try_deserialize(buf: &[u8], crc: &mut dyn Crc32) -> Option<HallCfg> {
+    let magic_bytes = read_array::<4>(buf, 0, size_of::<u32>())?;
+    if u32::from_le_bytes(magic_bytes) != MAGIC || *buf.get(size_of::<u32>())? != VERSION {
+        return None;
+    }
+    let data_end = HEADER_LEN.saturating_add(ENCODED_LEN);
+    let stored_crc = u32::from_le_bytes(read_array::<4>(buf, data_end, SETTINGS_BUF_LEN)?);
+    let computed_crc = buf.get(..data_end).map_or(0, |data| crc32_of(crc, data));
+    if computed_crc != stored_crc {
+        return None;
+    }
+    decode_settings(read_array::<ENCODED_LEN>(buf, HEADER_LEN, data_end)?)
+}
@@ -0,0 +1,291 @@
+use crate::{
+    eeprom::PAGE_SIZE,
+    gamepad::GAMEPAD_INPUTS,
+    layout::{COL, ROW},
+    matrix::{
+        analog_matrix::{
+            dks::{DKS_SLOTS, DksMap},
+            filter::KeyFilter,
+            socd::{SOCD_PAIRS, SocdPair},
+            types::{BoardTuning, KeyTuning, RtMode},
+        },
+        calib_store::{CALIB_BUF_LEN, Crc32, SLOT_A_ADDR, crc32_of, read_array},
+        layer_toggle::MatrixPos,
+    },
+    mouse::{MOUSE_INPUTS, MouseSettings},
+};
+use core::mem::size_of;
+
+/// Byte length of the trailing CRC field.
+const CRC_LEN: usize = size_of::<u32>();
+/// Byte length of the Dynamic Keystroke section: one LE [`DksMap`] per slot.
+const DKS_LEN: usize = DKS_SLOTS.saturating_mul(size_of::<u32>());
+/// Byte length of the gamepad section: one binding per input, then the layer
+/// mask.
+const GAMEPAD_LEN: usize = GAMEPAD_INPUTS.saturating_mul(MatrixPos::ENCODED_LEN).saturating_add(size_of::<u8>());
+/// Flags-byte bits holding the per-key DKS slot plus one; `0` means DKS off.
+const FLAG_DKS_MASK: u8 = 0b0111_1000;
+/// Shift of [`FLAG_DKS_MASK`] within the flags byte.
+const FLAG_DKS_SHIFT: u32 = 3;
+/// Byte length of a single serialized entry: actuation point, press
+/// sensitivity, release sensitivity, flags, top and bottom dead zone, filter.
+pub const ENTRY_LEN: usize = 7;
+/// Flags-byte bits holding the per-key [`RtMode::code`]; `0` follows the
+/// board-wide mode.
+const FLAG_MODE_MASK: u8 = 0b0000_0110;
+/// Shift of [`FLAG_MODE_MASK`] within the flags byte.
+const FLAG_MODE_SHIFT: u32 = 1;
+/// Flags-byte bit set when rapid trigger is enabled for the key.
+const FLAG_RT_ENABLED: u8 = 0b0000_0001;
+/// Byte offset of the board-wide [`RtMode::code`] in the header.
+const GLOBAL_MODE_OFFSET: usize = size_of::<u32>().saturating_add(size_of::<u8>());
+/// Byte length of the header: magic + version + board-wide mode.
+const HEADER_LEN: usize = GLOBAL_MODE_OFFSET.saturating_add(size_of::<u8>());
+/// Byte length of the analog mouse section: one binding per input, then the
+/// encoded [`MouseSettings`].
+const MOUSE_LEN: usize = MOUSE_INPUTS.saturating_mul(MatrixPos::ENCODED_LEN).saturating_add(MouseSettings::ENCODED_LEN);
+/// Magic number identifying a valid Q6 HE per-key tuning block.
+const MAGIC: u32 = 0x5136_5455;
+/// Byte length of the SOCD section: one encoded [`SocdPair`] per slot.
+const SOCD_LEN: usize = SOCD_PAIRS.saturating_mul(SocdPair::ENCODED_LEN);
+/// EEPROM word address at which the tuning block begins: the first page
+/// boundary after calibration slot A, so a write to one block never shares a
+/// page with the other.
+pub const TUNING_BASE_ADDR: u16 =
+    SLOT_A_ADDR.saturating_add(u16::try_from(CALIB_BUF_LEN.next_multiple_of(PAGE_SIZE)).unwrap_or(u16::MAX));
+/// Pre-computed buffer length for the HE matrix.
+pub const TUNING_BUF_LEN: usize = total_len(ROW, COL);
+/// Format version. Increment on any incompatible layout change; a mismatch
+/// falls back to the [`crate::matrix::analog_matrix::HallCfg`] defaults.
+const VERSION: u8 = 1;
+
+/// Encode one key's tuning as a fixed-size entry; shared with the host
+/// protocol so both use one wire format.
+pub const fn encode_entry(tuning: KeyTuning) -> [u8; ENTRY_LEN] {
+    let enabled = if tuning.rt_enabled { FLAG_RT_ENABLED } else { 0 };
+    let mode = match tuning.mode {
+        Some(mode) => mode.code(),
+        None => 0,
+    };
+    let dks = match tuning.dks {
+        Some(slot) => slot.saturating_add(1),
+        None => 0,
+    };
+    let flags = enabled | mode.wrapping_shl(FLAG_MODE_SHIFT) | (dks.wrapping_shl(FLAG_DKS_SHIFT) & FLAG_DKS_MASK);
+    [
+        tuning.actuation_pt,
+        tuning.rt_press,
+        tuning.rt_release,
+        flags,
+        tuning.top_dead_zone,
+        tuning.bottom_dead_zone,
+        tuning.filter.code(),
+    ]
+}
+
+/// Decode one fixed-size entry, returning `None` if any distance or dead
+/// zone is out of range, the DKS slot does not exist (see
+/// [`KeyTuning::is_valid`]), or the filter code is unknown.
+pub const fn decode_entry(entry: [u8; ENTRY_LEN]) -> Option<KeyTuning> {
+    let [actuation_pt, rt_press, rt_release, flags, top_dead_zone, bottom_dead_zone, filter] = entry;
+    let Some(filter) = KeyFilter::from_code(filter) else { return None };
+    let tuning = KeyTuning {
+        actuation_pt,
+        bottom_dead_zone,
+        dks: (flags & FLAG_DKS_MASK).wrapping_shr(FLAG_DKS_SHIFT).checked_sub(1),
+        filter,
+        mode: RtMode::from_code((flags & FLAG_MODE_MASK).wrapping_shr(FLAG_MODE_SHIFT)),
+        rt_enabled: flags & FLAG_RT_ENABLED != 0,
+        rt_press,
+        rt_release,
+        top_dead_zone,
+    };
+    if tuning.is_valid() { Some(tuning) } else { None }
+}
+
+/// Serialize the per-key tuning `table` (column-major
+/// `[[KeyTuning; ROW]; COL]`, 0.05 mm units) and the `board`-wide settings
+/// into `buf`.
+///
+/// Format: magic (4 B LE) | version (1 B) | board-wide mode (1 B) | entries
+/// (COL×ROW×7 B) | DKS slot maps (`DKS_SLOTS`×4 B LE) | SOCD pairs
+/// (`SOCD_PAIRS`×5 B) | gamepad bindings (`GAMEPAD_INPUTS`×2 B) | gamepad
+/// layer mask (1 B) | mouse bindings (`MOUSE_INPUTS`×2 B) | mouse settings
+/// (3 B) | CRC-32 (4 B LE), mirroring the calibration block so both share
+/// one validation scheme. Each entry is actuation point, press
+/// sensitivity, release sensitivity, and a flags byte (bit 0 rapid-trigger
+/// enable, bits 1-2 mode override, bits 3-6 DKS slot plus one), then the top
+/// and bottom dead zone and the [`KeyFilter::code`]. Each pair is
+/// encoded by [`SocdPair::encode`] and each binding by [`MatrixPos::encode`].
+pub fn serialize<const ROW: usize, const COL: usize>(
+    table: &[[KeyTuning; ROW]; COL],
+    board: &BoardTuning,
+    buf: &mut [u8; TUNING_BUF_LEN],
+    crc: &mut dyn Crc32,
+) {
+    if let Some(dst) = buf.get_mut(0..size_of::<u32>()) {
+        dst.copy_from_slice(&MAGIC.to_le_bytes());
+    }
+    if let Some(version_byte) = buf.get_mut(size_of::<u32>()) {
+        *version_byte = VERSION;
+    }
+    if let Some(mode_byte) = buf.get_mut(GLOBAL_MODE_OFFSET) {
+        *mode_byte = board.rt_mode.code();
+    }
+    let crc_start = total_len(ROW, COL).saturating_sub(CRC_LEN);
+    let mouse_start = crc_start.saturating_sub(MOUSE_LEN);
+    let gamepad_start = mouse_start.saturating_sub(GAMEPAD_LEN);
+    let socd_start = gamepad_start.saturating_sub(SOCD_LEN);
+    let dks_start = socd_start.saturating_sub(DKS_LEN);
+    if let Some(entry_bytes) = buf.get_mut(HEADER_LEN..dks_start) {
+        let (chunks, _) = entry_bytes.as_chunks_mut::<ENTRY_LEN>();
+        for (dst, &tuning) in chunks.iter_mut().zip(table.as_flattened()) {
+            *dst = encode_entry(tuning);
+        }
+    }
+    if let Some(dks_bytes) = buf.get_mut(dks_start..socd_start) {
+        let (chunks, _) = dks_bytes.as_chunks_mut::<4>();
+        for (dst, map) in chunks.iter_mut().zip(&board.dks) {
+            *dst = map.0.to_le_bytes();
+        }
+    }
+    if let Some(socd_bytes) = buf.get_mut(socd_start..gamepad_start) {
+        let (chunks, _) = socd_bytes.as_chunks_mut::<{ SocdPair::ENCODED_LEN }>();
+        for (dst, &pair) in chunks.iter_mut().zip(&board.socd) {
+            *dst = SocdPair::encode(pair);
+        }
+    }
+    if let Some(gamepad_bytes) = buf.get_mut(gamepad_start..mouse_start) {
+        let (chunks, layers) = gamepad_bytes.as_chunks_mut::<{ MatrixPos::ENCODED_LEN }>();
+        for (dst, &pos) in chunks.iter_mut().zip(&board.gamepad) {
+            *dst = MatrixPos::encode(pos);
+        }
+        if let Some(layers_byte) = layers.first_mut() {
+            *layers_byte = board.gamepad_layers;
+        }
+    }
+    let mouse_settings_start = crc_start.saturating_sub(MouseSettings::ENCODED_LEN);
+    if let Some(mouse_bytes) = buf.get_mut(mouse_start..mouse_settings_start) {
+        let (chunks, _) = mouse_bytes.as_chunks_mut::<{ MatrixPos::ENCODED_LEN }>();
+        for (dst, &pos) in chunks.iter_mut().zip(&board.mouse) {
+            *dst = MatrixPos::encode(pos);
+        }
+    }
+    if let Some(dst) = buf.get_mut(mouse_settings_start..crc_start) {
+        dst.copy_from_slice(&board.mouse_settings.encode());
+    }
+    let crc_end = crc_start.saturating_add(CRC_LEN);
+    let checksum = buf.get(..crc_start).map_or(0, |data| crc32_of(crc, data));
+    if let Some(dst) = buf.get_mut(crc_start..crc_end) {
+        dst.copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+/// Attempt to deserialize a tuning block from `buf` into `out` and `board`.
+///
+/// Validates the magic number, version byte, CRC-32 checksum, that every
+/// entry passes [`KeyTuning::is_valid`], that every SOCD pair names two
+/// distinct keys of the matrix, that every gamepad and mouse binding names a
+/// key of the matrix, and that the mouse settings decode. Returns `true` and
+/// overwrites both outputs on success; returns `false` without modifying either
+/// on any validation failure, leaving the caller's defaults in place. A block
+/// written by an older [`VERSION`] is rejected the same way, so the keys fall
+/// back to the [`crate::matrix::analog_matrix::HallCfg`] defaults.
+pub fn try_deserialize<const ROW: usize, const COL: usize>(
+    buf: &[u8],
+    out: &mut [[KeyTuning; ROW]; COL],
+    board: &mut BoardTuning,
+    crc: &mut dyn Crc32,
+) -> bool {
+    if buf.len() < total_len(ROW, COL) {
+        return false;
+    }
+    let Some(magic_bytes) = read_array::<4>(buf, 0, size_of::<u32>()) else { return false };
+    if u32::from_le_bytes(magic_bytes) != MAGIC {
+        return false;
+    }
+    let Some(&stored_version) = buf.get(size_of::<u32>()) else { return false };
+    if stored_version != VERSION {
+        return false;
+    }
+    let crc_end = total_len(ROW, COL);
+    let data_end = crc_end.saturating_sub(CRC_LEN);
+    let Some(stored_crc_bytes) = read_array::<4>(buf, data_end, crc_end) else { return false };
+    let computed_crc = buf.get(..data_end).map_or(0, |data| crc32_of(crc, data));
+    if computed_crc != u32::from_le_bytes(stored_crc_bytes) {
+        return false;
+    }
+    // Range-check the whole table before the first write so a rejected
+    // block never leaves `out` partially updated.
+    let Some(stored_mode) = buf.get(GLOBAL_MODE_OFFSET).and_then(|&code| RtMode::from_code(code)) else {
+        return false;
+    };
+    let mouse_start = data_end.saturating_sub(MOUSE_LEN);
+    let gamepad_start = mouse_start.saturating_sub(GAMEPAD_LEN);
+    let socd_start = gamepad_start.saturating_sub(SOCD_LEN);
+    let dks_start = socd_start.saturating_sub(DKS_LEN);
+    let Some(entry_bytes) = buf.get(HEADER_LEN..dks_start) else { return false };
+    let (chunks, remainder) = entry_bytes.as_chunks::<ENTRY_LEN>();
+    if !remainder.is_empty()
+        || chunks.len() != ROW.saturating_mul(COL)
+        || chunks.iter().any(|&chunk| decode_entry(chunk).is_none())
+    {
+        return false;
+    }
+    let Some(socd_bytes) = buf.get(socd_start..gamepad_start) else { return false };
+    let (socd_chunks, _) = socd_bytes.as_chunks::<{ SocdPair::ENCODED_LEN }>();
+    if socd_chunks.iter().any(|&chunk| SocdPair::decode(chunk, ROW, COL).is_none()) {
+        return false;
+    }
+    let Some(gamepad_bytes) = buf.get(gamepad_start..mouse_start) else { return false };
+    let (gamepad_chunks, layers) = gamepad_bytes.as_chunks::<{ MatrixPos::ENCODED_LEN }>();
+    let Some(&stored_layers) = layers.first() else { return false };
+    if gamepad_chunks.iter().any(|&chunk| MatrixPos::decode(chunk, ROW, COL).is_none()) {
+        return false;
+    }
+    let mouse_settings_start = data_end.saturating_sub(MouseSettings::ENCODED_LEN);
+    let Some(mouse_bytes) = buf.get(mouse_start..mouse_settings_start) else { return false };
+    let (mouse_chunks, _) = mouse_bytes.as_chunks::<{ MatrixPos::ENCODED_LEN }>();
+    let Some(stored_settings) = read_array::<{ MouseSettings::ENCODED_LEN }>(buf, mouse_settings_start, data_end)
+        .and_then(MouseSettings::decode)
+    else {
+        return false;
+    };
+    if mouse_chunks.iter().any(|&chunk| MatrixPos::decode(chunk, ROW, COL).is_none()) {
+        return false;
+    }
+    let Some(dks_bytes) = buf.get(dks_start..socd_start) else { return false };
+    let (dks_chunks, _) = dks_bytes.as_chunks::<4>();
+    for (dst, &chunk) in out.as_flattened_mut().iter_mut().zip(chunks) {
+        if let Some(tuning) = decode_entry(chunk) {
+            *dst = tuning;
+        }
+    }
+    for (dst, &chunk) in board.dks.iter_mut().zip(dks_chunks) {
+        *dst = DksMap(u32::from_le_bytes(chunk));
+    }
+    for (dst, &chunk) in board.socd.iter_mut().zip(socd_chunks) {
+        *dst = SocdPair::decode(chunk, ROW, COL).flatten();
+    }
+    for (dst, &chunk) in board.gamepad.iter_mut().zip(gamepad_chunks) {
+        *dst = MatrixPos::decode(chunk, ROW, COL).flatten();
+    }
+    board.gamepad_layers = stored_layers;
+    for (dst, &chunk) in board.mouse.iter_mut().zip(mouse_chunks) {
+        *dst = MatrixPos::decode(chunk, ROW, COL).flatten();
+    }
+    board.mouse_settings = stored_settings;
+    board.rt_mode = stored_mode;
+    true
+}
+
+/// Compute the total serialized byte length for a `rows × cols` matrix.
+pub const fn total_len(rows: usize, cols: usize) -> usize {
+    HEADER_LEN
+        .saturating_add(rows.saturating_mul(cols).saturating_mul(ENTRY_LEN))
+        .saturating_add(DKS_LEN)
+        .saturating_add(SOCD_LEN)
+        .saturating_add(GAMEPAD_LEN)
+        .saturating_add(MOUSE_LEN)
+        .saturating_add(CRC_LEN)
+}
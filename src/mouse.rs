@@ -0,0 +1,337 @@
+//! Analog mouse driven by key depth.
+//!
+//! Keys bound to a [`MouseInput`] move the pointer and wheel at a velocity
+//! that follows how deep they are pressed: nothing inside the dead zone, then
+//! rising along the selected [`MouseCurve`] to full speed at the bottom. The
+//! matrix scanner stores each bound key's travel in [`MOUSE_TRAVEL`] whenever
+//! it changes; [`MouseTask`] integrates the velocities once per
+//! [`REPORT_INTERVAL`] while a mouse layer is active and sends a report
+//! whenever the pointer, wheel, or buttons move.
+//!
+//! Reports go out through RMK's own mouse report path, the keyboard report
+//! channel that `UsbTransport` drains, so no extra USB interface is needed.
+//! RMK's digital mouse keys share that report, which is why the analog mouse
+//! has its own button inputs: a report from either side carries only its own
+//! buttons, so mixing the two on one layer would drop a held button.
+
+use crate::matrix::analog_matrix::types::{FULL_TRAVEL_FINE, TRAVEL_SCALE};
+use core::{
+    future::pending,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::{Duration, Ticker};
+use rmk::{
+    channel::KEYBOARD_REPORT_CHANNEL,
+    core_traits::Runnable,
+    descriptor::MouseReport,
+    embassy_futures::select::{Either3, select3},
+    event::{EventSubscriber as _, LayerChangeEvent, SubscribableEvent},
+    hid::Report,
+};
+
+/// Q8 fixed-point one; velocities and the response curve are Q8 fractions.
+const Q8_ONE: i32 = 256;
+
+/// Number of bindable mouse inputs; the length of [`MouseInput::ALL`].
+pub const MOUSE_INPUTS: usize = 9;
+
+/// Receiver count for [`MOUSE_SETTINGS`] (mouse task only).
+const MOUSE_SETTINGS_RECEIVERS: usize = 1;
+
+/// Pointer speed at full travel, in counts per second.
+const POINTER_MAX_SPEED: i32 = 1600;
+
+/// Interval between report samples while a mouse layer is active.
+const REPORT_INTERVAL: Duration = Duration::from_millis(4);
+
+/// [`REPORT_INTERVAL`] in milliseconds, for the per-tick velocity scale.
+const REPORT_INTERVAL_MS: i32 = 4;
+
+/// Wheel speed at full travel, in detents per second.
+const WHEEL_MAX_SPEED: i32 = 40;
+
+/// Latest travel of the key bound to each [`MouseInput`], in fine travel
+/// units, indexed by [`MouseInput::index`]; stored by the scanner the same
+/// way as `GAMEPAD_TRAVEL`.
+pub static MOUSE_TRAVEL: [AtomicU8; MOUSE_INPUTS] = [const { AtomicU8::new(0) }; MOUSE_INPUTS];
+
+/// Current [`MouseSettings`], sent by the matrix scanner whenever they are
+/// loaded or edited.
+pub static MOUSE_SETTINGS: Watch<CriticalSectionRawMutex, MouseSettings, MOUSE_SETTINGS_RECEIVERS> = Watch::new();
+
+/// Shape of the depth-to-velocity response above the dead zone.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum MouseCurve {
+    /// Velocity follows the cube of depth: very fine control near the dead
+    /// zone, a steep rise near the bottom.
+    Cubic,
+    /// Velocity is proportional to depth.
+    Linear,
+    /// Velocity follows the square of depth, for fine aiming at shallow
+    /// presses.
+    #[default]
+    Quadratic,
+}
+
+impl MouseCurve {
+    /// Wire code of this curve, as stored in the EEPROM tuning block and sent
+    /// to the host.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Linear => 0,
+            Self::Quadratic => 1,
+            Self::Cubic => 2,
+        }
+    }
+
+    /// Decode a wire code produced by [`MouseCurve::code`].
+    #[must_use]
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Linear),
+            1 => Some(Self::Quadratic),
+            2 => Some(Self::Cubic),
+            _ => None,
+        }
+    }
+
+    /// Apply the curve to a Q8 depth fraction (`0..=256`).
+    fn apply(self, depth: i32) -> i32 {
+        match self {
+            Self::Cubic => depth.saturating_mul(depth).saturating_mul(depth).checked_div(Q8_ONE.saturating_mul(Q8_ONE)),
+            Self::Linear => Some(depth),
+            Self::Quadratic => depth.saturating_mul(depth).checked_div(Q8_ONE),
+        }
+        .unwrap_or(0)
+    }
+}
+
+/// A mouse control a key can be bound to.
+#[derive(Clone, Copy)]
+pub enum MouseInput {
+    /// Left button, held while the key is past the dead zone.
+    ButtonLeft,
+    /// Middle button, held while the key is past the dead zone.
+    ButtonMiddle,
+    /// Right button, held while the key is past the dead zone.
+    ButtonRight,
+    /// Pointer down (positive Y).
+    Down,
+    /// Pointer left (negative X).
+    Left,
+    /// Pointer right (positive X).
+    Right,
+    /// Pointer up (negative Y).
+    Up,
+    /// Wheel towards the user.
+    WheelDown,
+    /// Wheel away from the user.
+    WheelUp,
+}
+
+impl MouseInput {
+    /// Every input in [`MouseInput::index`] order.
+    pub const ALL: [Self; MOUSE_INPUTS] = [
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+        Self::WheelUp,
+        Self::WheelDown,
+        Self::ButtonLeft,
+        Self::ButtonRight,
+        Self::ButtonMiddle,
+    ];
+
+    /// Decode an index produced by [`MouseInput::index`].
+    #[must_use]
+    pub const fn from_index(index: u8) -> Option<Self> {
+        match Self::ALL.get(usize::from(index)) {
+            Some(&input) => Some(input),
+            None => None,
+        }
+    }
+
+    /// Position of this input in [`MouseInput::ALL`]; also its wire code and
+    /// its slot in [`MOUSE_TRAVEL`].
+    #[must_use]
+    pub const fn index(self) -> u8 {
+        match self {
+            Self::Up => 0,
+            Self::Down => 1,
+            Self::Left => 2,
+            Self::Right => 3,
+            Self::WheelUp => 4,
+            Self::WheelDown => 5,
+            Self::ButtonLeft => 6,
+            Self::ButtonRight => 7,
+            Self::ButtonMiddle => 8,
+        }
+    }
+
+    /// Record `travel` for this input; called by the scanner on every travel
+    /// change of the bound key.
+    pub fn set_travel(self, travel: u8) {
+        if let Some(slot) = MOUSE_TRAVEL.get(usize::from(self.index())) {
+            slot.store(travel, Ordering::Relaxed);
+        }
+    }
+
+    /// Latest travel recorded for this input.
+    fn travel(self) -> u8 { MOUSE_TRAVEL.get(usize::from(self.index())).map_or(0, |slot| slot.load(Ordering::Relaxed)) }
+}
+
+/// Board-wide analog mouse settings.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MouseSettings {
+    /// Depth-to-velocity response curve.
+    pub curve:     MouseCurve,
+    /// Travel below which a key moves nothing, in 0.05 mm configuration
+    /// units; also the press point of the button inputs.
+    pub dead_zone: u8,
+    /// Layers on which the analog mouse is active, bit `n` for layer `n`.
+    pub layers:    u8,
+}
+
+impl MouseSettings {
+    /// Default settings: quadratic curve, 0.5 mm dead zone, no mouse layers.
+    pub const DEFAULT: Self = Self { curve: MouseCurve::Quadratic, dead_zone: 10, layers: 0 };
+    /// Byte length of the encoded settings: layers, dead zone, curve.
+    pub const ENCODED_LEN: usize = 3;
+
+    /// Decode settings encoded by [`MouseSettings::encode`], returning `None`
+    /// for an unknown curve or a dead zone that leaves no travel to use.
+    #[must_use]
+    pub const fn decode(bytes: [u8; Self::ENCODED_LEN]) -> Option<Self> {
+        let [layers, dead_zone, curve] = bytes;
+        let Some(curve) = MouseCurve::from_code(curve) else { return None };
+        if u32::from(dead_zone).saturating_mul(u32::from(TRAVEL_SCALE)) >= u32::from(FULL_TRAVEL_FINE) {
+            return None;
+        }
+        Some(Self { curve, dead_zone, layers })
+    }
+
+    /// Encode as layers, dead zone, [`MouseCurve::code`].
+    #[must_use]
+    pub const fn encode(self) -> [u8; Self::ENCODED_LEN] { [self.layers, self.dead_zone, self.curve.code()] }
+
+    /// Q8 velocity fraction (`0..=256`) for a key at `travel`.
+    fn velocity(self, travel: u8) -> i32 {
+        let dead = i32::from(self.dead_zone.saturating_mul(TRAVEL_SCALE));
+        let span = i32::from(FULL_TRAVEL_FINE).saturating_sub(dead);
+        let depth = i32::from(travel.min(FULL_TRAVEL_FINE)).saturating_sub(dead).max(0);
+        self.curve.apply(depth.saturating_mul(Q8_ONE).checked_div(span).unwrap_or(0))
+    }
+}
+
+/// Sub-count remainders of the pointer and wheel, so slow movement still
+/// accumulates into whole counts.
+#[derive(Default)]
+struct Motion {
+    /// Wheel remainder, Q8 detents.
+    wheel: i32,
+    /// Pointer X remainder, Q8 counts.
+    x:     i32,
+    /// Pointer Y remainder, Q8 counts.
+    y:     i32,
+}
+
+impl Motion {
+    /// Advance one [`REPORT_INTERVAL`] and return the report for it, or
+    /// `None` if nothing moved and the buttons are unchanged from `buttons`.
+    fn step(&mut self, settings: MouseSettings, buttons: u8) -> Option<MouseReport> {
+        let speed = |negative: MouseInput, positive: MouseInput| {
+            settings.velocity(positive.travel()).saturating_sub(settings.velocity(negative.travel()))
+        };
+        let x = take_whole(&mut self.x, speed(MouseInput::Left, MouseInput::Right), POINTER_MAX_SPEED);
+        let y = take_whole(&mut self.y, speed(MouseInput::Up, MouseInput::Down), POINTER_MAX_SPEED);
+        let wheel = take_whole(&mut self.wheel, speed(MouseInput::WheelDown, MouseInput::WheelUp), WHEEL_MAX_SPEED);
+        let new_buttons = pressed_buttons(settings);
+        if x == 0 && y == 0 && wheel == 0 && new_buttons == buttons {
+            return None;
+        }
+        Some(MouseReport { buttons: new_buttons, x, y, wheel, pan: 0 })
+    }
+}
+
+/// Button bit mask from the button inputs held past the dead zone.
+fn pressed_buttons(settings: MouseSettings) -> u8 {
+    let dead = settings.dead_zone.saturating_mul(TRAVEL_SCALE);
+    [(MouseInput::ButtonLeft, 0b001_u8), (MouseInput::ButtonRight, 0b010), (MouseInput::ButtonMiddle, 0b100)]
+        .into_iter()
+        .filter(|&(input, _)| input.travel() > dead)
+        .fold(0, |mask, (_, bit)| mask | bit)
+}
+
+/// Add one tick of a Q8 `velocity` fraction of `max_speed` (per second) to
+/// `remainder` and take out the whole counts, clamped to a report field.
+fn take_whole(remainder: &mut i32, velocity: i32, max_speed: i32) -> i8 {
+    let step = velocity.saturating_mul(max_speed).saturating_mul(REPORT_INTERVAL_MS).checked_div(1000).unwrap_or(0);
+    *remainder = remainder.saturating_add(step);
+    let whole = remainder.checked_div(Q8_ONE).unwrap_or(0).clamp(i32::from(i8::MIN), i32::from(i8::MAX));
+    *remainder = remainder.saturating_sub(whole.saturating_mul(Q8_ONE));
+    i8::try_from(whole).unwrap_or(0)
+}
+
+/// Whether `layer` is one of the mouse layers in `mask`.
+fn layer_active(layer: u8, mask: u8) -> bool { 1_u8.checked_shl(u32::from(layer)).is_some_and(|bit| mask & bit != 0) }
+
+/// Sends analog mouse reports while a mouse layer is active, and releases
+/// its buttons when the mouse layer is left.
+///
+/// Hand to `run_all!`; construct before it so no layer change is missed.
+pub struct MouseTask {
+    /// Subscription to active-layer changes.
+    sub: <LayerChangeEvent as SubscribableEvent>::Subscriber,
+}
+
+impl MouseTask {
+    /// Subscribe immediately so no layer change is missed.
+    #[must_use]
+    pub fn new() -> Self { Self { sub: LayerChangeEvent::subscriber() } }
+}
+
+impl Default for MouseTask {
+    fn default() -> Self { Self::new() }
+}
+
+impl Runnable for MouseTask {
+    async fn run(&mut self) -> ! {
+        let Some(mut settings_rx) = MOUSE_SETTINGS.receiver() else {
+            loop {
+                pending::<()>().await;
+            }
+        };
+        let mut buttons = 0_u8;
+        let mut layer = 0_u8;
+        let mut motion = Motion::default();
+        let mut settings = MouseSettings::DEFAULT;
+        let mut ticker = Ticker::every(REPORT_INTERVAL);
+        loop {
+            let tick = async {
+                if layer_active(layer, settings.layers) {
+                    ticker.next().await;
+                } else {
+                    pending::<()>().await;
+                }
+            };
+            match select3(self.sub.next_event(), settings_rx.changed(), tick).await {
+                Either3::First(LayerChangeEvent(new_layer)) => layer = new_layer,
+                Either3::Second(new_settings) => settings = new_settings,
+                Either3::Third(()) => {},
+            }
+            let report = if layer_active(layer, settings.layers) {
+                motion.step(settings, buttons)
+            } else {
+                motion = Motion::default();
+                (buttons != 0).then(|| MouseReport { buttons: 0, x: 0, y: 0, wheel: 0, pan: 0 })
+            };
+            if let Some(report) = report {
+                buttons = report.buttons;
+                KEYBOARD_REPORT_CHANNEL.send(Report::MouseReport(report)).await;
+            }
+        }
+    }
+}